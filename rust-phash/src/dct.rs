@@ -0,0 +1,150 @@
+// Copyright (c) 2015-2017 The `img_hash` Crate Developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rustdct::{DCTplanner, TransformType2And3};
+use transpose::transpose;
+
+use std::sync::Arc;
+
+pub struct DctCtxt {
+    row_dct: Arc<dyn TransformType2And3<f32>>,
+    col_dct: Arc<dyn TransformType2And3<f32>>,
+    width: usize,
+    height: usize,
+}
+
+impl DctCtxt {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut planner = DCTplanner::new();
+
+        DctCtxt {
+            row_dct: planner.plan_dct2(width),
+            col_dct: planner.plan_dct2(height),
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    /// Perform a 2D DCT on a 1D-packed vector with a given `width x height`.
+    ///
+    /// Assumes `packed_2d` is double-length for scratch space. Returns the vector truncated to
+    /// `width * height`.
+    ///
+    /// ### Panics
+    /// If `self.width * self.height * 2 != packed_2d.len()`
+    pub fn dct_2d(&self, mut packed_2d: Vec<f32>) -> Vec<f32> {
+        let Self { ref row_dct, ref col_dct, width, height } = *self;
+
+        let trunc_len = width * height;
+        assert_eq!(trunc_len * 2, packed_2d.len());
+
+        {
+            let (packed_2d, scratch) = packed_2d.split_at_mut(trunc_len);
+
+            for (row_in, row_out) in packed_2d.chunks_mut(width)
+                .zip(scratch.chunks_mut(width)) {
+                row_dct.process_dct2(row_in, row_out);
+            }
+
+            transpose(scratch, packed_2d, width, height);
+
+            for (row_in, row_out) in packed_2d.chunks_mut(height)
+                .zip(scratch.chunks_mut(height)) {
+                col_dct.process_dct2(row_in, row_out);
+            }
+
+            transpose(scratch, packed_2d, width, height);
+        }
+
+        packed_2d.truncate(trunc_len);
+        packed_2d
+    }
+}
+
+/// Crop the values off a 1D-packed 2D DCT.
+///
+/// Returns `packed` truncated to the premultiplied size, as determined by `rowstride`
+///
+/// Generic for easier testing
+pub fn crop_2d_dct<T: Copy>(mut packed: Vec<T>, rowstride: usize, scale: usize) -> Vec<T> {
+    // assert that the rowstride was previously multiplied by SIZE_MULTIPLIER
+    assert_eq!(rowstride % scale, 0);
+    assert!(rowstride / scale > 0, "rowstride cannot be cropped: {}", rowstride);
+
+    let new_rowstride = rowstride / scale;
+
+    for new_row in 0 .. packed.len() / (rowstride * scale) {
+        let (dest, src) = packed.split_at_mut(new_row * new_rowstride + rowstride);
+        let dest_start = dest.len() - new_rowstride;
+        let src_start = new_rowstride * new_row;
+        let src_end = src_start + new_rowstride;
+        dest[dest_start..].copy_from_slice(&src[src_start..src_end]);
+    }
+
+    let new_len = packed.len() / (scale * scale);
+    packed.truncate(new_len);
+
+    packed
+}
+
+/// The top-left `width x height` values, the lowest frequencies, of a 1D-packed 2D DCT
+pub fn crop_dct_low<T: Copy>(packed: &[T], rowstride: usize, width: usize, height: usize) -> Vec<T> {
+    assert!(width <= rowstride && height * rowstride <= packed.len(),
+        "cannot crop {}x{} off a dct of {} values per row", width, height, rowstride);
+    (0..height).flat_map(|row| packed[row * rowstride..row * rowstride + width].iter().cloned()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DCT_HEIGHT, DCT_WIDTH};
+
+    #[test]
+    fn test_crop_2d_dct() {
+        let packed: Vec<i32> = (0 .. 64).collect();
+        assert_eq!(
+            crop_2d_dct(packed.clone(), 8, 2),
+            [
+                0, 1, 2, 3, // 4, 5, 6, 7
+                8, 9, 10, 11, // 12, 13, 14, 15
+                16, 17, 18, 19, // 20, 21, 22, 23,
+                24, 25, 26, 27, // 28, 29, 30, 31,
+                // 32 .. 64
+            ]
+        );
+    }
+
+    #[test]
+    fn test_crop_dct_low() {
+        let packed: Vec<i32> = (0 .. 64).collect();
+        assert_eq!(crop_dct_low(&packed, 8, 4, 4), crop_2d_dct(packed.clone(), 8, 2));
+        assert_eq!(crop_dct_low(&packed, 8, 3, 2), [0, 1, 2, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_dct_2d() {
+        let dct_width = DCT_WIDTH;
+        let dct_height = DCT_HEIGHT;
+        let ctx = DctCtxt::new(dct_width as usize, dct_height as usize);
+        let input_len: usize = (dct_width * dct_height) as usize;
+        let mut vals_with_scratch: Vec<f32> = Vec::with_capacity((input_len * 2) as usize);
+        vals_with_scratch.extend((0..input_len * 2).map (|val| {
+            val as f32
+        }));
+        let dct_vals = ctx.dct_2d(vals_with_scratch);
+        println!("dct len={} {:?}", dct_vals.len(), dct_vals);
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright (c) 2015-2017 The `img_hash` Crate Developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// Interface for types used for storing hash data.
+///
+/// This is implemented for `Vec<u8>`, `Box<[u8]>` and arrays that are multiples/combinations of
+/// useful x86 bytewise SIMD register widths (64, 128, 256, 512 bits).
+///
+/// Please feel free to open a pull request [on Github](https://github.com/abonander/img_hash)
+/// if you need this implemented for a different array size.
+pub trait HashBytes {
+    /// Construct this type from an iterator of bytes.
+    ///
+    /// If this type has a finite capacity (i.e. an array) then it can ignore extra data
+    /// (the hash API will not create a hash larger than this type can contain). Unused capacity
+    /// **must** be zeroed.
+    fn from_iter<I: Iterator<Item = u8>>(iter: I) -> Self where Self: Sized;
+
+    /// Return the maximum capacity of this type, in bits.
+    ///
+    /// If this type has an arbitrary/theoretically infinite capacity, return `usize::max_value()`.
+    fn max_bits() -> usize;
+
+    /// Get the hash bytes as a slice.
+    fn as_slice(&self) -> &[u8];
+}
+
+impl HashBytes for Box<[u8]> {
+    fn from_iter<I: Iterator<Item = u8>>(iter: I) -> Self {
+        // stable in 1.32, effectively the same thing
+        // iter.collect()
+        iter.collect::<Vec<u8>>().into_boxed_slice()
+    }
+
+    fn max_bits() -> usize {
+        usize::max_value()
+    }
+
+    fn as_slice(&self) -> &[u8] { self }
+}
+
+impl HashBytes for Vec<u8> {
+    fn from_iter<I: Iterator<Item=u8>>(iter: I) -> Self {
+        iter.collect()
+    }
+
+    fn max_bits() -> usize {
+        usize::max_value()
+    }
+
+    fn as_slice(&self) -> &[u8] { self }
+}
+
+macro_rules! hash_bytes_array {
+    ($($n:expr),*) => {$(
+        impl HashBytes for [u8; $n] {
+            fn from_iter<I: Iterator<Item=u8>>(mut iter: I) -> Self {
+                // optimizer should eliminate this zeroing
+                let mut out = [0; $n];
+
+                for (src, dest) in iter.by_ref().zip(out.as_mut()) {
+                    *dest = src;
+                }
+
+                out
+            }
+
+            fn max_bits() -> usize {
+                $n * 8
+            }
+
+            fn as_slice(&self) -> &[u8] { self }
+        }
+    )*}
+}
+
+hash_bytes_array!(8, 16, 24, 32, 40, 48, 56, 64);
+
+struct BoolsToBytes<I> {
+    iter: I,
+}
+
+impl<I> Iterator for BoolsToBytes<I> where I: Iterator<Item=bool> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
+        // starts at the LSB and works up
+        self.iter.by_ref().take(8).enumerate().fold(None, |accum, (n, val)| {
+            accum.or(Some(0)).map(|accum| accum | ((val as u8) << n))
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        (
+            lower / 8,
+            // if the upper bound doesn't evenly divide by `8` then we will yield an extra item
+            upper.map(|upper| if upper % 8 == 0 { upper / 8 } else { upper / 8 + 1})
+        )
+    }
+}
+
+pub trait BitSet: HashBytes {
+    fn from_bools<I: Iterator<Item = bool>>(iter: I) -> Self where Self: Sized {
+        Self::from_iter(BoolsToBytes { iter })
+    }
+
+    fn hamming(&self, other: &Self) -> u32 {
+        self.as_slice().iter().zip(other.as_slice()).map(|(l, r)| (l ^ r).count_ones()).sum()
+    }
+}
+
+impl<T: HashBytes> BitSet for T {}
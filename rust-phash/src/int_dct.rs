@@ -0,0 +1,118 @@
+// Fixed-point DCT-II, bit-exact on every platform.
+//
+// Hashes are Merkle leaves compared bit for bit by verifiers, while a floating point transform may
+// round differently between compilers, SIMD paths and platforms, flipping bits near the mean. This
+// transform only adds, multiplies and shifts `i64` values, with a cosine table written out below
+// rather than computed with the platform `cos`.
+
+/// Fractional bits of the cosine table
+pub const COS_BITS: u32 = 14;
+
+/// round(2^14 * cos(pi * j / 64)) for j in 0..=32, the first quarter period of every cosine
+/// a DCT of up to 32 points takes
+const COS_TABLE: [i64; 33] = [
+    16384, 16364, 16305, 16207, 16069, 15893, 15679, 15426, 15137, 14811, 14449, 14053, 13623,
+    13160, 12665, 12140, 11585, 11003, 10394, 9760, 9102, 8423, 7723, 7005, 6270, 5520, 4756,
+    3981, 3196, 2404, 1606, 804, 0,
+];
+
+/// Largest transform length, the lengths dividing it are supported
+pub const MAX_INT_DCT_LEN: usize = 32;
+
+/// cos(pi * m / 64) in fixed point
+fn cos_fixed(m: usize) -> i64 {
+    let m = m % 128;
+    match m {
+        0..=32 => COS_TABLE[m],
+        33..=64 => -COS_TABLE[64 - m],
+        65..=96 => -COS_TABLE[m - 64],
+        _ => COS_TABLE[128 - m],
+    }
+}
+
+/// Rows of cos(pi * (2n + 1) * k / (2 * len)), k the row and n the column
+fn cos_matrix(len: usize) -> Vec<i64> {
+    assert!(len > 0 && MAX_INT_DCT_LEN % len == 0, "integer dct takes lengths dividing {}, not {}", MAX_INT_DCT_LEN, len);
+    let step = MAX_INT_DCT_LEN / len;
+    (0..len)
+        .flat_map(|k| (0..len).map(move |n| cos_fixed((2 * n + 1) * k * step)))
+        .collect()
+}
+
+/// Drops the fractional bits of a product with the cosine table, rounding halves up
+fn round_fixed(x: i64) -> i64 {
+    (x + (1 << (COS_BITS - 1))) >> COS_BITS
+}
+
+/// Unnormalized 2D DCT-II in fixed point, the integer counterpart of `DctCtxt`. Every pass rounds
+/// to integers, so outputs are within a few units of the floating point transform.
+pub struct IntDctCtxt {
+    row_cos: Vec<i64>,
+    col_cos: Vec<i64>,
+    width: usize,
+    height: usize,
+}
+
+impl IntDctCtxt {
+    /// ### Panics
+    /// If `width` or `height` does not divide `MAX_INT_DCT_LEN`
+    pub fn new(width: usize, height: usize) -> Self {
+        IntDctCtxt {
+            row_cos: cos_matrix(width),
+            col_cos: cos_matrix(height),
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    /// 2D DCT of a 1D-packed `width x height` frame, packed the same way as `DctCtxt::dct_2d`
+    ///
+    /// ### Panics
+    /// If `self.width * self.height != packed_2d.len()`
+    pub fn dct_2d(&self, packed_2d: &[i64]) -> Vec<i64> {
+        let Self { ref row_cos, ref col_cos, width, height } = *self;
+        assert_eq!(width * height, packed_2d.len());
+
+        let mut rows = vec![0i64; width * height];
+        for (row_in, row_out) in packed_2d.chunks(width).zip(rows.chunks_mut(width)) {
+            for (k, out) in row_out.iter_mut().enumerate() {
+                let cos = &row_cos[k * width..(k + 1) * width];
+                *out = round_fixed(row_in.iter().zip(cos).map(|(x, c)| x * c).sum());
+            }
+        }
+
+        let mut out = vec![0i64; width * height];
+        for x in 0..width {
+            for k in 0..height {
+                let cos = &col_cos[k * height..(k + 1) * height];
+                let sum: i64 = (0..height).map(|y| rows[y * width + x] * cos[y]).sum();
+                out[k * width + x] = round_fixed(sum);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_int_dct_2d_golden() {
+        let ctx = IntDctCtxt::new(4, 4);
+        let flat = ctx.dct_2d(&[10; 16]);
+        assert_eq!(flat[0], 160);
+        assert!(flat[1..].iter().all(|x| *x == 0));
+
+        let ramp: Vec<i64> = (0..16).collect();
+        assert_eq!(ctx.dct_2d(&ramp), [120, -12, 0, 0, -50, 0, 0, 0, 0, 0, 0, 0, -4, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,514 @@
+// Copyright (c) 2015-2017 The `img_hash` Crate Developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Perceptual hashes of video frames, the leaves of the proof of retrievability.
+//!
+//! Every algorithm implements `PerceptualHasher` and hashes a grayscale frame into
+//! `width * height` bits of its `HashSize`, 8x8 by default and up to 16x16, packed from the
+//! least significant bit of the first byte. Frames may have any size: the DCT hash resizes them
+//! to `DCT_WIDTH x DCT_HEIGHT` with `resize_box`, the other hashes shrink them with `resize_area`.
+//!
+//! The DCT hash runs in floating point by default. `DctArithmetic::Integer` selects a fixed-point
+//! transform and an integer mean comparison that give the same bits on every platform, for hashes
+//! that become Merkle leaves.
+
+// https://github.com/rust-lang/rust/issues/57533
+#![allow(missing_docs)]
+
+extern crate rustc_serialize;
+
+mod dct;
+mod hash_bytes;
+mod int_dct;
+
+pub use crate::dct::{crop_2d_dct, crop_dct_low, DctCtxt};
+pub use crate::int_dct::{IntDctCtxt, MAX_INT_DCT_LEN};
+pub use crate::hash_bytes::{BitSet, HashBytes};
+
+/// Default hash dimensions, 64-bit hashes
+pub const HASH_WIDTH: u32 = 8;
+pub const HASH_HEIGHT: u32 = 8;
+
+/// Largest hash side, 16x16 hashes are 256 bits
+pub const MAX_HASH_SIDE: u32 = 16;
+
+// we perform the DCT on a frame resized to 32x32
+pub const DCT_WIDTH: u32 = 32;
+pub const DCT_HEIGHT: u32 = 32;
+
+/// Names of the algorithms, as given to `hasher_by_name`
+pub const HASHERS: [&str; 4] = ["dct", "average", "difference", "block"];
+
+/// Grayscale frame in raster order. Frames written before the dimensions were recorded are
+/// `DCT_WIDTH x DCT_HEIGHT`.
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct SampleFrame {
+    pub pixels: Vec<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl SampleFrame {
+    pub fn new(pixels: Vec<u32>, width: u32, height: u32) -> Self {
+        SampleFrame { pixels, width: Some(width), height: Some(height) }
+    }
+
+    /// Width and height, `None` when they do not match the number of pixels
+    pub fn dimensions(&self) -> Option<(usize, usize)> {
+        let (width, height) = match (self.width, self.height) {
+            (Some(width), Some(height)) => (width as usize, height as usize),
+            (None, None) => (DCT_WIDTH as usize, DCT_HEIGHT as usize),
+            _ => return None,
+        };
+        if width > 0 && height > 0 && self.pixels.len() == width * height {
+            Some((width, height))
+        } else {
+            None
+        }
+    }
+}
+
+/// Hash dimensions, one bit per cell
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HashSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl HashSize {
+    /// `None` for sides outside 2 to `MAX_HASH_SIDE`
+    pub fn new(width: u32, height: u32) -> Option<Self> {
+        let valid = |side: u32| (2..=MAX_HASH_SIDE).contains(&side);
+        if valid(width) && valid(height) {
+            Some(HashSize { width, height })
+        } else {
+            None
+        }
+    }
+
+    /// Parses "16x16"
+    pub fn parse(arg: &str) -> Option<Self> {
+        let mut sides = arg.split('x').map(|side| side.parse::<u32>().ok());
+        match (sides.next(), sides.next(), sides.next()) {
+            (Some(Some(width)), Some(Some(height)), None) => HashSize::new(width, height),
+            _ => None,
+        }
+    }
+
+    pub fn bits(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    /// Bytes of a hash, the last one zero padded
+    pub fn bytes(&self) -> usize {
+        (self.bits() + 7) / 8
+    }
+}
+
+impl Default for HashSize {
+    fn default() -> Self {
+        HashSize { width: HASH_WIDTH, height: HASH_HEIGHT }
+    }
+}
+
+/// Arithmetic of the DCT hash
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DctArithmetic {
+    /// `f32` transform and mean, as the hashes of earlier releases
+    Float,
+    /// Fixed-point transform and integer mean comparison, bit-exact on every platform
+    Integer,
+}
+
+impl Default for DctArithmetic {
+    fn default() -> Self {
+        DctArithmetic::Float
+    }
+}
+
+/// Hashes of a sequence of frames.
+///
+/// `hashes` holds the bytes of every hash along with the hash dimensions. `phashes` holds the
+/// same hashes read as little-endian `u64` when they are 64 bits, and is the only field of files
+/// written before hash sizes were configurable.
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct PHashes {
+    pub phashes: Vec<u64>,
+    pub hash_width: Option<u32>,
+    pub hash_height: Option<u32>,
+    pub hashes: Option<Vec<Vec<u8>>>,
+}
+
+impl PHashes {
+    pub fn new(size: HashSize, hashes: Vec<Vec<u8>>) -> Self {
+        let phashes = if size.bits() == 64 {
+            hashes.iter().map(|hash| hash_to_u64(hash)).collect()
+        } else {
+            vec![]
+        };
+        PHashes {
+            phashes,
+            hash_width: Some(size.width),
+            hash_height: Some(size.height),
+            hashes: Some(hashes),
+        }
+    }
+
+    /// Dimensions of the hashes, 8x8 for files without them
+    pub fn hash_size(&self) -> HashSize {
+        match (self.hash_width, self.hash_height) {
+            (Some(width), Some(height)) => HashSize { width, height },
+            _ => HashSize::default(),
+        }
+    }
+
+    /// Bytes of every hash
+    pub fn hash_bytes(&self) -> Vec<Vec<u8>> {
+        match self.hashes {
+            Some(ref hashes) => hashes.clone(),
+            None => self.phashes.iter().map(|x| x.to_le_bytes().to_vec()).collect(),
+        }
+    }
+}
+
+/// Perceptual hash of a grayscale frame, pixels in raster order
+pub trait PerceptualHasher {
+    /// Name of the algorithm, one of `HASHERS`
+    fn name(&self) -> &'static str;
+
+    fn size(&self) -> HashSize;
+
+    /// `size().bits()` hash bits, the first bit in the LSB of the first byte
+    ///
+    /// ### Panics
+    /// If the frame is empty or `pixels.len() != width * height`, frames
+    /// `SampleFrame::dimensions` rejects
+    fn hash(&self, pixels: &[u32], width: usize, height: usize) -> Vec<u8>;
+}
+
+/// First 8 bytes of a hash read little-endian
+pub fn hash_to_u64(hash: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Hasher of the given name and size, `None` for unknown names. Only the dct hash takes `arithmetic`.
+pub fn hasher_by_name(name: &str, size: HashSize, arithmetic: DctArithmetic) -> Option<Box<dyn PerceptualHasher>> {
+    match name {
+        "dct" => Some(Box::new(DctHasher::with_arithmetic(size, arithmetic))),
+        "average" => Some(Box::new(AverageHasher { size })),
+        "difference" => Some(Box::new(DifferenceHasher { size })),
+        "block" => Some(Box::new(BlockHasher { size })),
+        _ => None,
+    }
+}
+
+pub(crate) fn mean_hash_f32<'a>(luma: &'a [f32]) -> impl Iterator<Item = bool> + 'a {
+    let mean = luma.iter().sum::<f32>() / luma.len() as f32;
+    luma.iter().map(move |&x| x >= mean)
+}
+
+/// `x >= mean` for every value, compared as `x * len >= sum` so no rounding is involved
+pub(crate) fn mean_hash_i64<'a>(values: &'a [i64]) -> impl Iterator<Item = bool> + 'a {
+    let sum: i64 = values.iter().sum();
+    let len = values.len() as i64;
+    values.iter().map(move |&x| x * len >= sum)
+}
+
+/// Input pixels covered by output pixel `i` along one axis. Bounds are rounded down, so cells
+/// differ by one pixel at most when the sizes do not divide. When enlarging, a cell is the one
+/// input pixel its start falls in.
+fn cell_bounds(i: usize, size: usize, new_size: usize) -> (usize, usize) {
+    let start = i * size / new_size;
+    (start, ((i + 1) * size / new_size).max(start + 1))
+}
+
+/// Sum and number of the input pixels of every output cell, in raster order
+fn cell_sums<'a>(pixels: &'a [u32], width: usize, height: usize, new_width: usize, new_height: usize) -> impl Iterator<Item = (u64, u64)> + 'a {
+    assert!(width > 0 && height > 0, "empty {}x{} frame", width, height);
+    assert_eq!(pixels.len(), width * height, "frame of {} pixels is not {}x{}", pixels.len(), width, height);
+    (0..new_height).flat_map(move |y| {
+        let (y0, y1) = cell_bounds(y, height, new_height);
+        (0..new_width).map(move |x| {
+            let (x0, x1) = cell_bounds(x, width, new_width);
+            let sum: u64 = (y0..y1).flat_map(|row| pixels[row * width + x0..row * width + x1].iter()).map(|p| *p as u64).sum();
+            (sum, ((y1 - y0) * (x1 - x0)) as u64)
+        })
+    })
+}
+
+/// Resizes the frame to `new_width x new_height`, every output pixel the mean of the input
+/// pixels its cell covers, see `cell_bounds`
+pub fn resize_area(pixels: &[u32], width: usize, height: usize, new_width: usize, new_height: usize) -> Vec<f32> {
+    cell_sums(pixels, width, height, new_width, new_height)
+        .map(|(sum, count)| sum as f32 / count as f32)
+        .collect()
+}
+
+/// Box filter in integers: `resize_area` with means rounded half up, the same on every platform
+pub fn resize_box(pixels: &[u32], width: usize, height: usize, new_width: usize, new_height: usize) -> Vec<u32> {
+    cell_sums(pixels, width, height, new_width, new_height)
+        .map(|(sum, count)| ((sum + count / 2) / count) as u32)
+        .collect()
+}
+
+/// pHash: the lowest `size` frequencies of the 2D DCT of the frame resized to
+/// `DCT_WIDTH x DCT_HEIGHT`, compared to their mean
+pub struct DctHasher {
+    ctx: DctCtxt,
+    int_ctx: IntDctCtxt,
+    size: HashSize,
+    arithmetic: DctArithmetic,
+}
+
+impl DctHasher {
+    pub fn new(size: HashSize) -> Self {
+        DctHasher::with_arithmetic(size, DctArithmetic::Float)
+    }
+
+    pub fn with_arithmetic(size: HashSize, arithmetic: DctArithmetic) -> Self {
+        DctHasher {
+            ctx: DctCtxt::new(DCT_WIDTH as usize, DCT_HEIGHT as usize),
+            int_ctx: IntDctCtxt::new(DCT_WIDTH as usize, DCT_HEIGHT as usize),
+            size,
+            arithmetic,
+        }
+    }
+
+    pub fn arithmetic(&self) -> DctArithmetic {
+        self.arithmetic
+    }
+}
+
+impl Default for DctHasher {
+    fn default() -> Self {
+        DctHasher::new(HashSize::default())
+    }
+}
+
+impl PerceptualHasher for DctHasher {
+    fn name(&self) -> &'static str {
+        "dct"
+    }
+
+    fn size(&self) -> HashSize {
+        self.size
+    }
+
+    fn hash(&self, pixels: &[u32], width: usize, height: usize) -> Vec<u8> {
+        assert_eq!(pixels.len(), width * height, "frame of {} pixels is not {}x{}", pixels.len(), width, height);
+        let (dct_width, dct_height) = (self.ctx.width() as usize, self.ctx.height() as usize);
+        let resized;
+        let pixels = if (width, height) == (dct_width, dct_height) {
+            pixels
+        } else {
+            resized = resize_box(pixels, width, height, dct_width, dct_height);
+            &resized[..]
+        };
+        let width = dct_width;
+        let (hash_width, hash_height) = (self.size.width as usize, self.size.height as usize);
+        if self.arithmetic == DctArithmetic::Integer {
+            let vals: Vec<i64> = pixels.iter().map(|x| *x as i64).collect();
+            let dct_vals = self.int_ctx.dct_2d(&vals);
+            let cropped_dct = crop_dct_low(&dct_vals, width, hash_width, hash_height);
+            return BitSet::from_bools(mean_hash_i64(&cropped_dct));
+        }
+
+        // the second half is scratch space of the transform
+        let mut vals_with_scratch: Vec<f32> = Vec::with_capacity(pixels.len() * 2);
+        vals_with_scratch.extend(pixels.iter().map(|x| *x as f32));
+        vals_with_scratch.resize(pixels.len() * 2, 0.0);
+
+        let dct_vals = self.ctx.dct_2d(vals_with_scratch);
+        let cropped_dct = crop_dct_low(&dct_vals, width, hash_width, hash_height);
+        BitSet::from_bools(mean_hash_f32(&cropped_dct))
+    }
+}
+
+/// aHash: the frame shrunk to the hash size, every pixel compared to the mean
+pub struct AverageHasher {
+    pub size: HashSize,
+}
+
+impl PerceptualHasher for AverageHasher {
+    fn name(&self) -> &'static str {
+        "average"
+    }
+
+    fn size(&self) -> HashSize {
+        self.size
+    }
+
+    fn hash(&self, pixels: &[u32], width: usize, height: usize) -> Vec<u8> {
+        let resized = resize_area(pixels, width, height, self.size.width as usize, self.size.height as usize);
+        BitSet::from_bools(mean_hash_f32(&resized))
+    }
+}
+
+/// dHash: the frame shrunk to one column more than the hash, set where a pixel is darker than
+/// its right neighbour
+pub struct DifferenceHasher {
+    pub size: HashSize,
+}
+
+impl PerceptualHasher for DifferenceHasher {
+    fn name(&self) -> &'static str {
+        "difference"
+    }
+
+    fn size(&self) -> HashSize {
+        self.size
+    }
+
+    fn hash(&self, pixels: &[u32], width: usize, height: usize) -> Vec<u8> {
+        let row_len = self.size.width as usize + 1;
+        let resized = resize_area(pixels, width, height, row_len, self.size.height as usize);
+        BitSet::from_bools(resized.chunks(row_len).flat_map(|row| row.windows(2).map(|pair| pair[0] < pair[1])))
+    }
+}
+
+/// Blockhash (Yang et al.): the mean of every block of the hash grid, compared to the median of
+/// its horizontal band, every band a quarter of the grid rows rounded up
+pub struct BlockHasher {
+    pub size: HashSize,
+}
+
+impl PerceptualHasher for BlockHasher {
+    fn name(&self) -> &'static str {
+        "block"
+    }
+
+    fn size(&self) -> HashSize {
+        self.size
+    }
+
+    fn hash(&self, pixels: &[u32], width: usize, height: usize) -> Vec<u8> {
+        let (hash_width, hash_height) = (self.size.width as usize, self.size.height as usize);
+        let blocks = resize_area(pixels, width, height, hash_width, hash_height);
+        let band_rows = (hash_height + 3) / 4;
+        BitSet::from_bools(blocks.chunks(band_rows * hash_width).flat_map(|band| {
+            let band_len = band.len();
+            let mut sorted = band.to_vec();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let median = (sorted[(band_len - 1) / 2] + sorted[band_len / 2]) / 2.0;
+            band.iter().map(move |x| *x > median)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// 32x32 frame with features in every direction
+    fn sample_frame() -> Vec<u32> {
+        (0..DCT_HEIGHT).flat_map(|y| (0..DCT_WIDTH).map(move |x| (x * 7 + y * 3 + (x * y) % 17) % 256)).collect()
+    }
+
+    #[test]
+    fn test_hashers() {
+        let frame = sample_frame();
+        let brighter: Vec<u32> = frame.iter().map(|x| x + 20).collect();
+        let side = DCT_WIDTH as usize;
+        for hash_size in [HashSize::default(), HashSize::new(16, 16).unwrap(), HashSize::new(12, 6).unwrap()].iter() {
+            for name in HASHERS.iter() {
+                let hasher = hasher_by_name(name, *hash_size, DctArithmetic::Float).unwrap();
+                assert_eq!((hasher.name(), hasher.size()), (*name, *hash_size));
+                let hash = hasher.hash(&frame, side, side);
+                assert_eq!(hash.len(), hash_size.bytes());
+                assert_eq!(hash, hasher.hash(&frame, side, side));
+                if *name != "dct" {
+                    // the spatial hashes only compare pixels with one another
+                    assert_eq!(hash.hamming(&hasher.hash(&brighter, side, side)), 0, "{}", name);
+                }
+            }
+        }
+        assert!(hasher_by_name("wavelet", HashSize::default(), DctArithmetic::Float).is_none());
+    }
+
+    #[test]
+    fn test_integer_dct_hash_golden() {
+        // golden vectors: integer hashes must never change, on any platform
+        let frame = sample_frame();
+        let side = DCT_WIDTH as usize;
+        let hasher = DctHasher::with_arithmetic(HashSize::default(), DctArithmetic::Integer);
+        assert_eq!(hasher.hash(&frame, side, side), [9, 164, 82, 40, 128, 0, 0, 0]);
+        let hasher = DctHasher::with_arithmetic(HashSize::new(16, 16).unwrap(), DctArithmetic::Integer);
+        assert_eq!(hasher.hash(&frame, side, side), [
+            9, 2, 180, 32, 82, 131, 42, 5, 162, 82, 72, 43, 84, 181, 84, 80,
+            128, 0, 42, 0, 0, 0, 21, 40, 64, 0, 42, 84, 0, 10, 4, 170,
+        ]);
+
+        // within a few units of the floating point transform
+        let float_dct = DctCtxt::new(side, side).dct_2d(frame.iter().map(|x| *x as f32).chain((0..side * side).map(|_| 0.0)).collect());
+        let int_dct = IntDctCtxt::new(side, side).dct_2d(&frame.iter().map(|x| *x as i64).collect::<Vec<i64>>());
+        for (f, i) in float_dct.iter().zip(int_dct.iter()) {
+            assert!((f - *i as f32).abs() < 8.0, "float {} integer {}", f, i);
+        }
+    }
+
+    #[test]
+    fn test_difference_hash_of_gradient() {
+        let gradient: Vec<u32> = (0..36 * 16).map(|i| (i % 36) as u32).collect();
+        assert_eq!(hash_to_u64(&DifferenceHasher { size: HashSize::default() }.hash(&gradient, 36, 16)), u64::max_value());
+        assert_eq!(resize_area(&gradient, 36, 16, 9, 8)[..2], [1.5, 5.5]);
+    }
+
+    #[test]
+    fn test_phashes_formats() {
+        let legacy: PHashes = rustc_serialize::json::decode(r#"{"phashes":[1,258]}"#).unwrap();
+        assert_eq!(legacy.hash_size(), HashSize::default());
+        assert_eq!(legacy.hash_bytes()[1], [2, 1, 0, 0, 0, 0, 0, 0]);
+
+        let wide = PHashes::new(HashSize::parse("16x16").unwrap(), vec![vec![0xff; 32]]);
+        assert!(wide.phashes.is_empty());
+        let decoded: PHashes = rustc_serialize::json::decode(&rustc_serialize::json::encode(&wide).unwrap()).unwrap();
+        assert_eq!(decoded.hash_size().bits(), 256);
+        assert_eq!(decoded.hash_bytes(), wide.hash_bytes());
+
+        assert_eq!(PHashes::new(HashSize::default(), legacy.hash_bytes()).phashes, [1, 258]);
+        assert!(HashSize::parse("32x32").is_none() && HashSize::parse("8x").is_none());
+    }
+
+    #[test]
+    fn test_frames_of_any_size() {
+        // every pixel of the sample frame doubled in both directions
+        let frame = sample_frame();
+        let side = DCT_WIDTH as usize;
+        let doubled: Vec<u32> = (0..side * 2).flat_map(|y| (0..side * 2).map(move |x| (y / 2) * side + x / 2)).map(|i| frame[i]).collect();
+        assert_eq!(resize_box(&doubled, side * 2, side * 2, side, side), frame);
+        for arithmetic in [DctArithmetic::Float, DctArithmetic::Integer].iter() {
+            for name in HASHERS.iter() {
+                let hasher = hasher_by_name(name, HashSize::default(), *arithmetic).unwrap();
+                assert_eq!(hasher.hash(&doubled, side * 2, side * 2), hasher.hash(&frame, side, side), "{}", name);
+                // smaller and non-square frames hash too
+                assert_eq!(hasher.hash(&frame[..side * 5], side, 5).len(), 8);
+            }
+        }
+
+        assert_eq!(resize_box(&[1, 2, 3, 4], 2, 2, 4, 1), [2, 2, 3, 3]);
+        assert_eq!(resize_box(&[1, 2, 4], 3, 1, 2, 1), [1, 3]);
+
+        let legacy: SampleFrame = rustc_serialize::json::decode(&format!("{{\"pixels\":{:?}}}", frame)).unwrap();
+        assert_eq!(legacy.dimensions(), Some((side, side)));
+        assert_eq!(SampleFrame::new(vec![0; 12], 4, 3).dimensions(), Some((4, 3)));
+        assert_eq!(SampleFrame::new(vec![0; 12], 4, 4).dimensions(), None);
+        assert_eq!(SampleFrame::new(vec![], 0, 3).dimensions(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "empty 0x3 frame")]
+    fn test_empty_frame() {
+        DctHasher::with_arithmetic(HashSize::default(), DctArithmetic::Integer).hash(&[], 0, 3);
+    }
+}
@@ -0,0 +1,260 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+extern crate bellperson;
+extern crate blake2b_simd;
+extern crate paired;
+use bellperson::{ConstraintSystem, SynthesisError};
+use ff::{Field, PrimeField};
+use fil_sapling_crypto::circuit::{boolean, num};
+use paired::bls12_381::Fr;
+use paired::Engine;
+
+use super::gadget;
+
+// Audited macroblock positions.
+//
+// Positions are derived from a seed the transcoder does not control (a block hash, or a value
+// published by the client) and the dimensions of the stream, so the audited blocks cannot be
+// cherry-picked. Every position is a frame number and a macroblock column and row of the
+// rendition grid. The SSIM proof exposes the position as public inputs and ties it in-circuit
+// to the position of the source block in the frame commitment, row * mbs_per_row + column, and
+// to the frame number hashed into the commitment. Verifiers derive the position from the seed
+// themselves rather than trusting the prover's.
+
+/// Personalization of the blake2b hash deriving positions from the seed
+const CHALLENGE_PERSONALIZATION: &[u8] = b"vc_ssim_chal";
+
+/// Bits of the frame number
+pub const FRAME_BITS: usize = 32;
+
+/// Bits of the macroblock column, row and row length, far above the blocks of an 8K frame
+const GRID_BITS: usize = 16;
+
+/// Frame count and dimensions of a stream, in pixels of the rendition
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Copy, Debug)]
+pub struct StreamDims {
+	pub num_frames: u32,
+	pub width: u32,
+	pub height: u32,
+	/// Side of the macroblock (the SSIM window)
+	pub mb_side: u32,
+}
+
+impl StreamDims {
+	/// Whole macroblocks per row; a partial block at the right edge is never challenged
+	pub fn mbs_per_row(&self) -> u32 {
+		self.width / self.mb_side
+	}
+
+	pub fn mbs_per_col(&self) -> u32 {
+		self.height / self.mb_side
+	}
+}
+
+/// Challenged macroblock
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MbPosition {
+	pub frame: u32,
+	pub mb_x: u32,
+	pub mb_y: u32,
+	pub mbs_per_row: u32,
+}
+
+impl MbPosition {
+	pub fn from_index(frame: u32, index: usize, mbs_per_row: u32) -> Self {
+		MbPosition {
+			frame,
+			mb_x: index as u32 % mbs_per_row,
+			mb_y: index as u32 / mbs_per_row,
+			mbs_per_row,
+		}
+	}
+
+	/// Raster index of the block in the frame, its leaf in the source commitment
+	pub fn index(&self) -> usize {
+		(self.mb_y * self.mbs_per_row + self.mb_x) as usize
+	}
+
+	/// Public inputs of the position, in the order the circuit exposes them
+	pub fn public_inputs(&self) -> Vec<Fr> {
+		[self.frame, self.mb_x, self.mb_y, self.mbs_per_row]
+			.iter()
+			.map(|x| Fr::from_repr((*x as u64).into()).unwrap())
+			.collect()
+	}
+}
+
+/// Seed given as hex, with or without a 0x prefix
+pub fn decode_seed(seed: &str) -> Option<Vec<u8>> {
+	let hex = if seed.starts_with("0x") { &seed[2..] } else { seed };
+	if hex.is_empty() || hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return None;
+	}
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+		.collect()
+}
+
+fn hash_u64(hash: &[u8], i: usize) -> u64 {
+	let mut value = 0u64;
+	for (j, b) in hash[i * 8..(i + 1) * 8].iter().enumerate() {
+		value |= (*b as u64) << (8 * j);
+	}
+	value
+}
+
+/// `count` positions derived from the seed. The stream dimensions are hashed along with the seed,
+/// so the same seed gives unrelated positions for another rendition.
+pub fn challenge_positions(seed: &[u8], dims: &StreamDims, count: usize) -> Vec<MbPosition> {
+	let (mbs_per_row, mbs_per_col) = (dims.mbs_per_row(), dims.mbs_per_col());
+	assert!(dims.num_frames > 0 && mbs_per_row > 0 && mbs_per_col > 0,
+		"stream of {} frames {}x{} holds no {}x{} macroblock", dims.num_frames, dims.width, dims.height, dims.mb_side, dims.mb_side);
+	(0..count).map(|i| {
+		let mut state = blake2b_simd::Params::new()
+			.hash_length(32)
+			.personal(CHALLENGE_PERSONALIZATION)
+			.to_state();
+		state.update(seed);
+		for x in [dims.num_frames, dims.width, dims.height, dims.mb_side, i as u32].iter() {
+			state.update(&x.to_le_bytes());
+		}
+		let hash = state.finalize();
+		let hash = hash.as_bytes();
+		MbPosition {
+			frame: (hash_u64(hash, 0) % dims.num_frames as u64) as u32,
+			mb_x: (hash_u64(hash, 1) % mbs_per_row as u64) as u32,
+			mb_y: (hash_u64(hash, 2) % mbs_per_col as u64) as u32,
+			mbs_per_row,
+		}
+	}).collect()
+}
+
+/// Allocates the frame number along with its `FRAME_BITS` little-endian bits, which the source
+/// commitment hashes
+pub fn alloc_frame<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	frame: Option<u32>,
+) -> Result<(num::AllocatedNum<E>, Vec<boolean::Boolean>), SynthesisError> {
+	let circ_frame = num::AllocatedNum::alloc(cs.namespace(|| "frame"), || {
+		gadget::witness_fr::<E>(frame.map(|x| x as u64))
+	})?;
+	let bits = gadget::range_check(cs.namespace(|| "frame bits"), &circ_frame, FRAME_BITS)?;
+	Ok((circ_frame, bits))
+}
+
+/// Exposes the position as public inputs, with the frame allocated by `alloc_frame`, and enforces
+/// mb_y * mbs_per_row + mb_x to equal the little-endian position bits of the source authentication
+/// path. mb_x < mbs_per_row, so every index has a single column and row.
+pub fn position_circuit<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	position: Option<MbPosition>,
+	frame: &num::AllocatedNum<E>,
+	auth_path_bits: &[boolean::Boolean],
+) -> Result<(), SynthesisError> {
+	let fr = |x: u32| E::Fr::from_repr((x as u64).into()).unwrap();
+	let mb_x = num::AllocatedNum::alloc(cs.namespace(|| "mb x"), || {
+		Ok(fr(position.ok_or(SynthesisError::AssignmentMissing)?.mb_x))
+	})?;
+	let mb_y = num::AllocatedNum::alloc(cs.namespace(|| "mb y"), || {
+		Ok(fr(position.ok_or(SynthesisError::AssignmentMissing)?.mb_y))
+	})?;
+	let mbs_per_row = num::AllocatedNum::alloc(cs.namespace(|| "mbs per row"), || {
+		Ok(fr(position.ok_or(SynthesisError::AssignmentMissing)?.mbs_per_row))
+	})?;
+	gadget::range_check(cs.namespace(|| "mb x range"), &mb_x, GRID_BITS)?;
+	gadget::range_check(cs.namespace(|| "mb y range"), &mb_y, GRID_BITS)?;
+	gadget::range_check(cs.namespace(|| "mbs per row range"), &mbs_per_row, GRID_BITS)?;
+
+	// mb_x + 1 + slack = mbs_per_row, slack in range
+	let col_slack = num::AllocatedNum::alloc(cs.namespace(|| "column slack"), || {
+		let position = position.ok_or(SynthesisError::AssignmentMissing)?;
+		let mut slack = fr(position.mbs_per_row);
+		slack.sub_assign(&fr(position.mb_x));
+		slack.sub_assign(&E::Fr::one());
+		Ok(slack)
+	})?;
+	cs.enforce(|| "enforce column in row",
+		|lc| lc + mb_x.get_variable() + col_slack.get_variable() + CS::one(),
+		|lc| lc + CS::one(),
+		|lc| lc + mbs_per_row.get_variable(),
+	);
+	gadget::range_check(cs.namespace(|| "column slack range"), &col_slack, GRID_BITS)?;
+
+	let row_start = mb_y.mul(cs.namespace(|| "row start"), &mbs_per_row)?;
+
+	let mut coeff = E::Fr::one();
+	let mut index_lc = bellperson::LinearCombination::<E>::zero();
+	for bit in auth_path_bits.iter() {
+		index_lc = index_lc + &bit.lc(CS::one(), coeff);
+		coeff.double();
+	}
+	cs.enforce(|| "enforce position index",
+		|lc| lc + row_start.get_variable() + mb_x.get_variable(),
+		|lc| lc + CS::one(),
+		|lc| lc + &index_lc,
+	);
+
+	frame.inputize(cs.namespace(|| "frame input"))?;
+	mb_x.inputize(cs.namespace(|| "mb x input"))?;
+	mb_y.inputize(cs.namespace(|| "mb y input"))?;
+	mbs_per_row.inputize(cs.namespace(|| "mbs per row input"))?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use paired::bls12_381::Bls12;
+	use storage_proofs::circuit::test::*;
+
+	fn position_cs(position: MbPosition, index: usize, tree_depth: usize) -> TestConstraintSystem<Bls12> {
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let (frame, _) = alloc_frame(cs.namespace(|| "frame"), Some(position.frame)).unwrap();
+		let auth_path_bits: Vec<boolean::Boolean> = (0..tree_depth)
+			.map(|i| boolean::Boolean::from(
+				boolean::AllocatedBit::alloc(cs.namespace(|| format!("position bit {}", i)), Some((index >> i) & 1 == 1)).unwrap()
+			))
+			.collect();
+		position_circuit(cs.namespace(|| "position"), Some(position), &frame, &auth_path_bits).unwrap();
+		cs
+	}
+
+	#[test]
+	fn test_position_circuit() {
+		let position = MbPosition::from_index(9, 6, 4);
+		let cs = position_cs(position, 6, 3);
+		assert!(cs.is_satisfied());
+		assert!(cs.verify(&position.public_inputs()));
+
+		// the same index as column 6 of row 0 is outside a row of 4 blocks
+		let alias = MbPosition { frame: 9, mb_x: 6, mb_y: 0, mbs_per_row: 4 };
+		assert!(!position_cs(alias, 6, 3).is_satisfied());
+		let last_column = MbPosition { frame: 9, mb_x: 4, mb_y: 0, mbs_per_row: 4 };
+		assert!(!position_cs(last_column, 4, 3).is_satisfied());
+	}
+
+	#[test]
+	fn test_decode_seed() {
+		assert_eq!(decode_seed("0x00ff10"), Some(vec![0x00, 0xff, 0x10]));
+		assert_eq!(decode_seed("A0b1"), Some(vec![0xa0, 0xb1]));
+		assert_eq!(decode_seed("abc"), None);
+		assert_eq!(decode_seed("zz"), None);
+		assert_eq!(decode_seed("+1"), None);
+		assert_eq!(decode_seed("0x"), None);
+	}
+
+	#[test]
+	fn test_challenge_positions() {
+		let dims = StreamDims { num_frames: 300, width: 1280, height: 720, mb_side: 16 };
+		let positions = challenge_positions(b"block hash", &dims, 64);
+		assert_eq!(positions, challenge_positions(b"block hash", &dims, 64));
+		assert_ne!(positions, challenge_positions(b"other hash", &dims, 64));
+		for pos in positions.iter() {
+			assert!(pos.frame < 300 && pos.mb_x < 80 && pos.mb_y < 45);
+			assert_eq!(MbPosition::from_index(pos.frame, pos.index(), pos.mbs_per_row), *pos);
+		}
+	}
+}
@@ -0,0 +1,139 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+extern crate bellperson;
+extern crate paired;
+use bellperson::Circuit;
+use paired::bls12_381::Bls12;
+
+use super::macroblock::TestConstraintSystem;
+use super::mb_mse::{Mse, MseApp};
+use super::mb_ssim::{PublicInputSchema, Ssim, SsimApp, SsimWindow};
+use super::merkle_pot::ProofOfRetrievability;
+use super::pixel;
+use super::resample::ResampleRatio;
+use super::source_commit;
+
+// Cost of the circuits, tracked between releases.
+//
+// Every circuit is synthesized without a witness, as during setup, into a constraint system that
+// records the namespace path of each constraint and variable. Costs are summed per namespace,
+// paths cut to their first few levels, so a regression shows up next to the gadget causing it.
+
+/// Circuits `circuit-stats` and `r1cs-export` take
+pub const STATS_CIRCUITS: [&str; 3] = ["por", "ssim", "mse"];
+
+/// Namespace levels of the default breakdown, e.g. "por/merkle tree hash 3" or "y plane/sigma x const"
+pub const DEFAULT_STATS_LEVEL: usize = 2;
+
+/// Cost of one namespace of a circuit
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamespaceStats {
+	/// Namespace path, "" for objects outside any namespace
+	pub namespace: String,
+	pub constraints: usize,
+	pub aux: usize,
+}
+
+/// Cost of a circuit
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Debug)]
+pub struct CircuitStats {
+	pub circuit: String,
+	pub constraints: usize,
+	/// Public inputs, not counting the constant ONE
+	pub inputs: usize,
+	pub aux: usize,
+	pub namespaces: Vec<NamespaceStats>,
+}
+
+impl CircuitStats {
+	/// Synthesizes the circuit without a witness and breaks its cost down by the first `level` namespaces
+	pub fn new<C: Circuit<Bls12>>(name: &str, circuit: C, level: usize) -> Self {
+		let mut cs = TestConstraintSystem::<Bls12>::new_shape();
+		circuit.synthesize(&mut cs).expect("failed to synthesize circuit");
+		let namespaces = cs.namespace_breakdown(level)
+			.into_iter()
+			.map(|(namespace, (constraints, aux))| NamespaceStats { namespace, constraints, aux })
+			.collect();
+		CircuitStats {
+			circuit: name.to_string(),
+			constraints: cs.num_constraints(),
+			inputs: cs.num_inputs() - 1,
+			aux: cs.num_aux(),
+			namespaces,
+		}
+	}
+
+	/// Totals followed by one tab separated line per namespace
+	pub fn to_table(&self) -> String {
+		let mut table = format!("circuit {}\nconstraints {}\ninputs {}\naux {}\n", self.circuit, self.constraints, self.inputs, self.aux);
+		table.push_str("namespace\tconstraints\taux\n");
+		for ns in self.namespaces.iter() {
+			let name = if ns.namespace.is_empty() { "(root)" } else { &ns.namespace };
+			table.push_str(&format!("{}\t{}\t{}\n", name, ns.constraints, ns.aux));
+		}
+		table
+	}
+}
+
+/// Proof of retrievability over a tree of `tree_depth` levels, without a witness
+pub fn blank_por(tree_depth: usize) -> ProofOfRetrievability<'static, Bls12> {
+	ProofOfRetrievability::<Bls12> {
+		params: &source_commit::JUBJUB_BLS_PARAMS,
+		value: None,
+		auth_path: vec![None; tree_depth],
+		root: None,
+	}
+}
+
+/// Luma SSIM of a `window_size` box window, the source block opened in a tree of `tree_depth` levels
+pub fn blank_ssim(window_size: u32, tree_depth: u32) -> Ssim<'static, Bls12> {
+	let app = SsimApp::new(SsimWindow::new(window_size, false), tree_depth, ResampleRatio::identity(), None,
+		pixel::DEFAULT_BIT_DEPTH, PublicInputSchema::default());
+	app.blank_circuit()
+}
+
+/// MSE of a `window_size` x `window_size` block
+pub fn blank_mse(window_size: u32) -> Mse<Bls12> {
+	MseApp::new((window_size * window_size) as usize, pixel::DEFAULT_BIT_DEPTH).blank_circuit()
+}
+
+pub fn por_stats(tree_depth: usize, level: usize) -> CircuitStats {
+	CircuitStats::new("por", blank_por(tree_depth), level)
+}
+
+pub fn ssim_stats(window_size: u32, tree_depth: u32, level: usize) -> CircuitStats {
+	CircuitStats::new("ssim", blank_ssim(window_size, tree_depth), level)
+}
+
+pub fn mse_stats(window_size: u32, level: usize) -> CircuitStats {
+	CircuitStats::new("mse", blank_mse(window_size), level)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_por_stats_breakdown() {
+		let stats = por_stats(4, DEFAULT_STATS_LEVEL);
+		let constraints: usize = stats.namespaces.iter().map(|ns| ns.constraints).sum();
+		let aux: usize = stats.namespaces.iter().map(|ns| ns.aux).sum();
+		assert_eq!(constraints, stats.constraints);
+		assert_eq!(aux, stats.aux);
+
+		// every level of the path costs the same
+		let levels: Vec<&NamespaceStats> = (0..4)
+			.map(|i| stats.namespaces.iter().find(|ns| ns.namespace == format!("por/merkle tree hash {}", i)).unwrap())
+			.collect();
+		assert!(levels[0].constraints > 0);
+		for ns in levels.iter() {
+			assert_eq!((ns.constraints, ns.aux), (levels[0].constraints, levels[0].aux));
+		}
+
+		// a deeper tree only adds levels
+		let deeper = por_stats(5, DEFAULT_STATS_LEVEL);
+		assert_eq!(deeper.constraints, stats.constraints + levels[0].constraints);
+	}
+}
@@ -1,48 +0,0 @@
-use bellperson::ConstraintSystem;
-use fil_sapling_crypto::circuit::num;
-use paired::Engine;
-
-/// Adds a constraint to CS, enforcing an equality relationship between the allocated numbers a and b.
-///
-/// a == b
-pub fn equal<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-    cs: &mut CS,
-    annotation: A,
-    a: &num::AllocatedNum<E>,
-    b: &num::AllocatedNum<E>,
-) where
-    A: FnOnce() -> AR,
-    AR: Into<String>,
-{
-    // a * 1 = b
-    cs.enforce(
-        annotation,
-        |lc| lc + a.get_variable(),
-        |lc| lc + CS::one(),
-        |lc| lc + b.get_variable(),
-    );
-}
-
-/// Adds a constraint to CS, enforcing a difference relationship between the allocated numbers a, b, and difference.
-///
-/// a - b = difference
-pub fn difference<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-    cs: &mut CS,
-    annotation: A,
-    a: &num::AllocatedNum<E>,
-    b: &num::AllocatedNum<E>,
-    difference: &num::AllocatedNum<E>,
-) where
-    A: FnOnce() -> AR,
-    AR: Into<String>,
-{
-    //    difference = a-b
-    // => difference + b = a
-    // => (difference + b) * 1 = a
-    cs.enforce(
-        annotation,
-        |lc| lc + difference.get_variable() + b.get_variable(),
-        |lc| lc + CS::one(),
-        |lc| lc + a.get_variable(),
-    );
-}
@@ -0,0 +1,312 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+extern crate bellperson;
+extern crate paired;
+use bellperson::{ConstraintSystem, SynthesisError};
+use ff::{Field, PrimeField};
+use fil_sapling_crypto::circuit::boolean::Boolean;
+use paired::Engine;
+
+use super::gadget::{self, div_const, range_check, sqrt_floor, sum_vec, witness_fr};
+use super::pixel::AllocatedPixel;
+
+// Unsigned fixed-point numbers, value = raw / 2^frac_bits.
+//
+// `Fixed` is the native twin of the `FixedPoint` gadget: every operation rounds down the same
+// way in both, so witnesses computed natively match the circuit bit for bit. Raw values of a
+// format are below 2^(int_bits + frac_bits), and every gadget operation range checks its result
+// to that width, so an overflow makes the circuit unsatisfiable rather than wrapping around
+// the field. Raw values are at most MAX_FIXED_BITS wide, which keeps products within u64.
+//
+// The module is a library building block for statistics the integer gadgets of the SSIM
+// circuits cannot express; no circuit of the crate uses it yet.
+
+/// Widest raw value of a fixed-point format
+pub const MAX_FIXED_BITS: u32 = 32;
+
+/// Integer and fractional bits of a fixed-point number
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedFormat {
+	pub int_bits: u32,
+	pub frac_bits: u32,
+}
+
+impl FixedFormat {
+	pub fn new(int_bits: u32, frac_bits: u32) -> Self {
+		assert!(int_bits + frac_bits <= MAX_FIXED_BITS,
+			"{}.{} fixed point exceeds {} bits", int_bits, frac_bits, MAX_FIXED_BITS);
+		FixedFormat { int_bits, frac_bits }
+	}
+
+	/// Width of the raw values
+	pub fn bits(&self) -> u32 {
+		self.int_bits + self.frac_bits
+	}
+
+	/// Raw value of 1.0
+	pub fn scale(&self) -> u64 {
+		1 << self.frac_bits
+	}
+}
+
+/// Native fixed-point number
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fixed {
+	pub raw: u64,
+	pub format: FixedFormat,
+}
+
+impl Fixed {
+	pub fn from_raw(raw: u64, format: FixedFormat) -> Self {
+		assert!(raw >> format.bits() == 0, "fixed point overflow, {} exceeds {} bits", raw, format.bits());
+		Fixed { raw, format }
+	}
+
+	pub fn from_int(x: u64, format: FixedFormat) -> Self {
+		Fixed::from_raw(x << format.frac_bits, format)
+	}
+
+	/// Rounds down to the closest fixed-point number
+	pub fn from_f64(x: f64, format: FixedFormat) -> Self {
+		Fixed::from_raw((x * format.scale() as f64).floor() as u64, format)
+	}
+
+	pub fn to_f64(&self) -> f64 {
+		self.raw as f64 / self.format.scale() as f64
+	}
+
+	pub fn add(&self, other: &Self) -> Self {
+		assert_eq!(self.format, other.format);
+		Fixed::from_raw(self.raw + other.raw, self.format)
+	}
+
+	/// floor(a * b / 2^frac_bits)
+	pub fn mul(&self, other: &Self) -> Self {
+		assert_eq!(self.format, other.format);
+		Fixed::from_raw((self.raw * other.raw) >> self.format.frac_bits, self.format)
+	}
+
+	/// floor(a * 2^frac_bits / b)
+	pub fn div(&self, other: &Self) -> Self {
+		assert_eq!(self.format, other.format);
+		assert!(other.raw > 0, "fixed point division by zero");
+		Fixed::from_raw((self.raw << self.format.frac_bits) / other.raw, self.format)
+	}
+
+	/// floor(sqrt(a * 2^frac_bits))
+	pub fn sqrt(&self) -> Self {
+		Fixed::from_raw(gadget::isqrt(self.raw << self.format.frac_bits), self.format)
+	}
+}
+
+/// Allocated fixed-point number, along with its native twin
+#[derive(Clone)]
+pub struct FixedPoint<E: Engine> {
+	pub value: AllocatedPixel<E>,
+	/// `None` during setup
+	pub native: Option<Fixed>,
+	pub format: FixedFormat,
+}
+
+impl<E: Engine> FixedPoint<E> {
+	/// Allocates a number range checked to the format
+	pub fn alloc<CS: ConstraintSystem<E>>(
+		mut cs: CS,
+		native: Option<Fixed>,
+		format: FixedFormat,
+	) -> Result<Self, SynthesisError> {
+		let value = AllocatedPixel::alloc_with_range(cs.namespace(|| "fixed"), || {
+			witness_fr::<E>(native.map(|x| x.raw))
+		}, format.bits() as usize)?;
+		Ok(FixedPoint { value, native, format })
+	}
+
+	/// Fixed-point value of an allocated integer, such as a pixel or a sum of pixels
+	pub fn from_integer<CS: ConstraintSystem<E>>(
+		mut cs: CS,
+		integer: &AllocatedPixel<E>,
+		native: Option<u64>,
+		format: FixedFormat,
+	) -> Result<Self, SynthesisError> {
+		let native = native.map(|x| Fixed::from_int(x, format));
+		let value = AllocatedPixel::alloc(cs.namespace(|| "scaled"), || {
+			witness_fr::<E>(native.map(|x| x.raw))
+		})?;
+		cs.enforce(
+			|| "scale enforce",
+			|lc| lc + integer.get_variable(),
+			|lc| lc + (E::Fr::from_repr(format.scale().into()).unwrap(), CS::one()),
+			|lc| lc + value.get_variable(),
+		);
+		range_check(cs.namespace(|| "scaled range"), &value, format.bits() as usize)?;
+		Ok(FixedPoint { value, native, format })
+	}
+
+	fn result<CS: ConstraintSystem<E>>(
+		mut cs: CS,
+		value: AllocatedPixel<E>,
+		native: Option<Fixed>,
+		format: FixedFormat,
+	) -> Result<Self, SynthesisError> {
+		range_check(cs.namespace(|| "result range"), &value, format.bits() as usize)?;
+		Ok(FixedPoint { value, native, format })
+	}
+
+	pub fn add<CS: ConstraintSystem<E>>(&self, mut cs: CS, other: &Self) -> Result<Self, SynthesisError> {
+		assert_eq!(self.format, other.format);
+		let native = self.native.and_then(|a| other.native.map(|b| a.add(&b)));
+		let sum = sum_vec(cs.namespace(|| "add"), &[self.value.clone(), other.value.clone()], &[1, 1])?;
+		Self::result(cs, sum, native, self.format)
+	}
+
+	/// Product rescaled to the format, rounded down.
+	/// a * b = product * 2^frac_bits + rem with rem < 2^frac_bits.
+	pub fn mul<CS: ConstraintSystem<E>>(&self, mut cs: CS, other: &Self) -> Result<Self, SynthesisError> {
+		assert_eq!(self.format, other.format);
+		let native = self.native.and_then(|a| other.native.map(|b| a.mul(&b)));
+		let wide = self.value.mul(cs.namespace(|| "wide product"), &other.value)?;
+		let product = div_const(cs.namespace(|| "rescale"), &wide,
+			self.native.and_then(|a| other.native.map(|b| a.raw * b.raw)), self.format.scale())?;
+		Self::result(cs, product, native, self.format)
+	}
+
+	/// Quotient rounded down.
+	/// a * 2^frac_bits = quotient * b + rem with rem < b, which also rules out b = 0.
+	pub fn div<CS: ConstraintSystem<E>>(&self, mut cs: CS, other: &Self) -> Result<Self, SynthesisError> {
+		assert_eq!(self.format, other.format);
+		let native = self.native.and_then(|a| other.native.map(|b| a.div(&b)));
+		let rem_u64 = self.native.and_then(|a| other.native.map(|b| (a.raw << a.format.frac_bits) % b.raw));
+		let quotient = AllocatedPixel::alloc(cs.namespace(|| "quotient"), || {
+			witness_fr::<E>(native.map(|x| x.raw))
+		})?;
+		let rem = AllocatedPixel::alloc_with_range(cs.namespace(|| "rem"), || {
+			witness_fr::<E>(rem_u64)
+		}, self.format.bits() as usize)?;
+
+		let scale = E::Fr::from_repr(self.format.scale().into()).unwrap();
+		cs.enforce(
+			|| "div enforce",
+			|lc| lc + quotient.get_variable(),
+			|lc| lc + other.value.get_variable(),
+			|lc| lc + (scale, self.value.get_variable()) - rem.get_variable(),
+		);
+		rem.enforce_less_than(cs.namespace(|| "rem less than divisor"), &other.value, self.format.bits() as usize)?;
+		Self::result(cs, quotient, native, self.format)
+	}
+
+	/// Square root rounded down, sqrt(a * 2^frac_bits).
+	/// radicand = root^2 + fract with fract <= 2 * root, so no other root satisfies it.
+	pub fn sqrt<CS: ConstraintSystem<E>>(&self, mut cs: CS) -> Result<Self, SynthesisError> {
+		let native = self.native.map(|a| a.sqrt());
+		let radicand_u64 = self.native.map(|a| a.raw << a.format.frac_bits);
+		let radicand = AllocatedPixel::alloc(cs.namespace(|| "radicand"), || {
+			witness_fr::<E>(radicand_u64)
+		})?;
+		cs.enforce(
+			|| "radicand enforce",
+			|lc| lc + self.value.get_variable(),
+			|lc| lc + (E::Fr::from_repr(self.format.scale().into()).unwrap(), CS::one()),
+			|lc| lc + radicand.get_variable(),
+		);
+		// the root of a radicand below 2^(bits + frac_bits) fits the format
+		let root = sqrt_floor(cs.namespace(|| "sqrt"), &radicand, radicand_u64,
+			(self.format.bits() + self.format.frac_bits) as usize)?;
+		Ok(FixedPoint { value: root, native, format: self.format })
+	}
+
+	/// Bit set iff self < other
+	pub fn is_less_than<CS: ConstraintSystem<E>>(&self, cs: CS, other: &Self) -> Result<Boolean, SynthesisError> {
+		assert_eq!(self.format, other.format);
+		self.value.is_less_than(cs, &other.value, self.format.bits() as usize)
+	}
+
+	/// Enforces self < other
+	pub fn enforce_less_than<CS: ConstraintSystem<E>>(&self, cs: CS, other: &Self) -> Result<(), SynthesisError> {
+		assert_eq!(self.format, other.format);
+		self.value.enforce_less_than(cs, &other.value, self.format.bits() as usize)
+	}
+
+	pub fn inputize<CS: ConstraintSystem<E>>(&self, cs: CS) -> Result<(), SynthesisError> {
+		self.value.inputize(cs)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use paired::bls12_381::{Bls12, Fr};
+	use storage_proofs::circuit::test::*;
+
+	#[test]
+	fn test_fixed_point_matches_native() {
+		let format = FixedFormat::new(12, 16);
+		let (a, b) = (Fixed::from_f64(3.75, format), Fixed::from_int(2, format));
+		// (a + b) * a / b, then its square root and a comparison with 1
+		let expected = a.add(&b).mul(&a).div(&b);
+		assert!((expected.to_f64() - 5.75 * 3.75 / 2.0).abs() < 1e-4);
+		assert!((expected.sqrt().to_f64() - expected.to_f64().sqrt()).abs() < 1e-4);
+
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let circ_a = FixedPoint::alloc(cs.namespace(|| "a"), Some(a), format).unwrap();
+		let pix = AllocatedPixel::alloc(cs.namespace(|| "b int"), || Ok(Fr::from_str("2").unwrap())).unwrap();
+		let circ_b = FixedPoint::from_integer(cs.namespace(|| "b"), &pix, Some(2), format).unwrap();
+		let sum = circ_a.add(cs.namespace(|| "add"), &circ_b).unwrap();
+		let product = sum.mul(cs.namespace(|| "mul"), &circ_a).unwrap();
+		let quotient = product.div(cs.namespace(|| "div"), &circ_b).unwrap();
+		let root = quotient.sqrt(cs.namespace(|| "sqrt")).unwrap();
+		let one = FixedPoint::alloc(cs.namespace(|| "one"), Some(Fixed::from_int(1, format)), format).unwrap();
+		let lt = root.is_less_than(cs.namespace(|| "root < 1"), &one).unwrap();
+		assert!(cs.is_satisfied());
+
+		assert_eq!(quotient.native, Some(expected));
+		assert_eq!(quotient.value.get_value(), Some(Fr::from_repr(expected.raw.into()).unwrap()));
+		assert_eq!(root.value.get_value(), Some(Fr::from_repr(expected.sqrt().raw.into()).unwrap()));
+		assert_eq!(lt.get_value(), Some(false));
+
+		// a quotient off by one no longer satisfies the division
+		cs.set("div/quotient/pix", Fr::from_repr((expected.raw - 1).into()).unwrap());
+		assert!(!cs.is_satisfied());
+		cs.set("div/quotient/pix", Fr::from_repr(expected.raw.into()).unwrap());
+		assert!(cs.is_satisfied());
+	}
+
+	fn set_bits(cs: &mut TestConstraintSystem<Bls12>, path: &str, value: u64, num_bits: usize) {
+		for i in 0..num_bits {
+			let bit = if (value >> i) & 1 == 1 { Fr::one() } else { Fr::zero() };
+			cs.set(&format!("{}/bit {}/boolean", path, i), bit);
+		}
+	}
+
+	#[test]
+	fn test_fixed_point_sqrt_rejects_overstated_root() {
+		let format = FixedFormat::new(8, 8);
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let a = FixedPoint::alloc(cs.namespace(|| "a"), Some(Fixed::from_int(4, format)), format).unwrap();
+		let root = a.sqrt(cs.namespace(|| "sqrt")).unwrap();
+		assert!(cs.is_satisfied());
+		assert_eq!(root.native, Some(Fixed::from_int(2, format)));
+
+		// radicand 512^2 claimed as 513^2 - 1025, with every other constraint of the root met
+		let fr = |x: u64| Fr::from_repr(x.into()).unwrap();
+		let mut fract = Fr::zero();
+		fract.sub_assign(&fr(1025));
+		cs.set("sqrt/sqrt/squrt/pix", fr(513));
+		set_bits(&mut cs, "sqrt/sqrt/squrt range", 513, 12);
+		cs.set("sqrt/sqrt/fract/pix", fract);
+		cs.set("sqrt/sqrt/fract slack/pix", fr(2 * 513 + 1025));
+		set_bits(&mut cs, "sqrt/sqrt/fract slack range", 2 * 513 + 1025, 13);
+		assert_eq!(cs.which_is_unsatisfied(), Some("sqrt/sqrt/fract range/unpacking constraint"));
+	}
+
+	#[test]
+	fn test_fixed_point_overflow() {
+		let format = FixedFormat::new(4, 8);
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		FixedPoint::alloc(cs.namespace(|| "15"), Some(Fixed::from_int(15, format)), format).unwrap();
+		assert!(cs.is_satisfied());
+		// 16.0 does not fit 4 integer bits
+		FixedPoint::alloc(cs.namespace(|| "16"), Some(Fixed { raw: 16 << 8, format }), format).unwrap();
+		assert!(!cs.is_satisfied());
+	}
+}
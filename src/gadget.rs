@@ -0,0 +1,568 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+extern crate bellperson;
+extern crate paired;
+use bellperson::{ConstraintSystem, LinearCombination, SynthesisError, Variable};
+use ff::{BitIterator, Field, PrimeField};
+use fil_sapling_crypto::circuit::{boolean, multipack, num};
+use paired::bls12_381::{Bls12, Fr};
+use paired::Engine;
+
+use super::pixel::AllocatedPixel;
+
+// Constraint gadgets shared by the SSIM, MSE, SAD and PoR circuits.
+//
+// They are generic over `AllocatedValue`, implemented by `AllocatedPixel` and by
+// `num::AllocatedNum`. Gadgets allocating a result take the namespace of their caller and name
+// their own allocations ("sum", "quotient", "rem", "squrt", ...), so tests can look values up
+// under stable paths. Witness values are passed as `Option<u64>` and are `None` during setup.
+
+/// Allocated variable along with its value, `None` during setup
+pub trait AllocatedValue<E: Engine>: Clone + Sized {
+	fn alloc<CS, F>(cs: CS, value: F) -> Result<Self, SynthesisError>
+	where
+		CS: ConstraintSystem<E>,
+		F: FnOnce() -> Result<E::Fr, SynthesisError>;
+
+	fn get_value(&self) -> Option<E::Fr>;
+
+	fn get_variable(&self) -> Variable;
+}
+
+impl<E: Engine> AllocatedValue<E> for AllocatedPixel<E> {
+	fn alloc<CS, F>(cs: CS, value: F) -> Result<Self, SynthesisError>
+	where
+		CS: ConstraintSystem<E>,
+		F: FnOnce() -> Result<E::Fr, SynthesisError>,
+	{
+		AllocatedPixel::alloc(cs, value)
+	}
+
+	fn get_value(&self) -> Option<E::Fr> {
+		AllocatedPixel::get_value(self)
+	}
+
+	fn get_variable(&self) -> Variable {
+		AllocatedPixel::get_variable(self)
+	}
+}
+
+impl<E: Engine> AllocatedValue<E> for num::AllocatedNum<E> {
+	fn alloc<CS, F>(cs: CS, value: F) -> Result<Self, SynthesisError>
+	where
+		CS: ConstraintSystem<E>,
+		F: FnOnce() -> Result<E::Fr, SynthesisError>,
+	{
+		num::AllocatedNum::alloc(cs, value)
+	}
+
+	fn get_value(&self) -> Option<E::Fr> {
+		num::AllocatedNum::get_value(self)
+	}
+
+	fn get_variable(&self) -> Variable {
+		num::AllocatedNum::get_variable(self)
+	}
+}
+
+/// Field element for an optional witness value, `AssignmentMissing` when it is not known
+pub fn witness_fr<E: Engine>(value: Option<u64>) -> Result<E::Fr, SynthesisError> {
+	let value = value.ok_or(SynthesisError::AssignmentMissing)?;
+	Ok(E::Fr::from_repr(value.into()).unwrap())
+}
+
+fn const_fr<E: Engine>(value: u64) -> E::Fr {
+	E::Fr::from_repr(value.into()).unwrap()
+}
+
+fn value_of<E: Engine, T: AllocatedValue<E>>(a: &T) -> Result<E::Fr, SynthesisError> {
+	a.get_value().ok_or(SynthesisError::AssignmentMissing)
+}
+
+/// Floor of the square root, exact over the whole u64 range
+pub fn isqrt(x: u64) -> u64 {
+	let mut root = (x as f64).sqrt() as u64;
+	while root.checked_mul(root).map_or(true, |sq| sq > x) {
+		root -= 1;
+	}
+	while (root + 1).checked_mul(root + 1).map_or(false, |sq| sq <= x) {
+		root += 1;
+	}
+	root
+}
+
+/// Adds a constraint to CS, enforcing an equality relationship between the allocated numbers a and b.
+///
+/// a == b
+pub fn equal<E: Engine, T: AllocatedValue<E>, A, AR, CS: ConstraintSystem<E>>(
+	cs: &mut CS,
+	annotation: A,
+	a: &T,
+	b: &T,
+) where
+	A: FnOnce() -> AR,
+	AR: Into<String>,
+{
+	// a * 1 = b
+	cs.enforce(
+		annotation,
+		|lc| lc + a.get_variable(),
+		|lc| lc + CS::one(),
+		|lc| lc + b.get_variable(),
+	);
+}
+
+/// Adds a constraint to CS, enforcing a difference relationship between the allocated numbers a, b, and difference.
+///
+/// a - b = difference
+pub fn difference<E: Engine, T: AllocatedValue<E>, A, AR, CS: ConstraintSystem<E>>(
+	cs: &mut CS,
+	annotation: A,
+	a: &T,
+	b: &T,
+	difference: &T,
+) where
+	A: FnOnce() -> AR,
+	AR: Into<String>,
+{
+	//    difference = a-b
+	// => difference + b = a
+	// => (difference + b) * 1 = a
+	cs.enforce(
+		annotation,
+		|lc| lc + difference.get_variable() + b.get_variable(),
+		|lc| lc + CS::one(),
+		|lc| lc + a.get_variable(),
+	);
+}
+
+// From storage-proofs/src/circuit/sloths.rs
+/// Allocates a - b
+pub fn sub<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	a: &T,
+	b: &T,
+) -> Result<T, SynthesisError> {
+	let res = T::alloc(cs.namespace(|| "sub num"), || {
+		let mut tmp = value_of::<E, T>(a)?;
+		tmp.sub_assign(&value_of::<E, T>(b)?);
+		Ok(tmp)
+	})?;
+
+	// a - b = res
+	difference(&mut cs, || "subtraction constraint", a, b, &res);
+
+	Ok(res)
+}
+
+/// Allocates a * b
+pub fn mul<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	a: &T,
+	b: &T,
+) -> Result<T, SynthesisError> {
+	let res = T::alloc(cs.namespace(|| "mul"), || {
+		let mut tmp = value_of::<E, T>(a)?;
+		tmp.mul_assign(&value_of::<E, T>(b)?);
+		Ok(tmp)
+	})?;
+
+	cs.enforce(
+		|| "mul constraint",
+		|lc| lc + a.get_variable(),
+		|lc| lc + b.get_variable(),
+		|lc| lc + res.get_variable(),
+	);
+
+	Ok(res)
+}
+
+/// Enforces sum(weights[i] * a[i]) = sum
+pub fn sum_vec_enforce<E: Engine, T: AllocatedValue<E>, A, AR, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	annotation: A,
+	a: &[T],
+	weights: &[u32],
+	sum: &T,
+) where
+	A: FnOnce() -> AR,
+	AR: Into<String>,
+{
+	cs.enforce(
+		annotation,
+		|mut lc| {
+			for (x, w) in a.iter().zip(weights.iter()) {
+				lc = lc + (const_fr::<E>(*w as u64), x.get_variable())
+			}
+			lc
+		},
+		|lc| lc + CS::one(),
+		|lc| lc + sum.get_variable(),
+	);
+}
+
+/// Allocates the weighted sum of the vector, sum(weights[i] * a[i])
+pub fn sum_vec<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	a: &[T],
+	weights: &[u32],
+) -> Result<T, SynthesisError> {
+	let sum = T::alloc(cs.namespace(|| "sum"), || {
+		let mut value = E::Fr::zero();
+		for (x, w) in a.iter().zip(weights.iter()) {
+			let mut tmp = value_of::<E, T>(x)?;
+			tmp.mul_assign(&const_fr::<E>(*w as u64));
+			value.add_assign(&tmp);
+		}
+		Ok(value)
+	})?;
+	sum_vec_enforce(cs.namespace(|| "sum enforce"), || "sum enforce", a, weights, &sum);
+
+	Ok(sum)
+}
+
+/// Enforces 0 <= value < 2^num_bits by unpacking it into `num_bits` boolean variables
+pub fn range_check<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	cs: CS,
+	value: &T,
+	num_bits: usize,
+) -> Result<Vec<boolean::Boolean>, SynthesisError> {
+	let target = LinearCombination::zero() + value.get_variable();
+	let bits = range_check_lc(cs, value.get_value(), target, num_bits)?;
+	Ok(bits.into_iter().map(boolean::Boolean::from).collect())
+}
+
+/// Allocates the `num_bits` little-endian bits of `value` and enforces they pack to `target`, a
+/// linear combination of that value, which range checks it without allocating it
+pub fn range_check_lc<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	value: Option<E::Fr>,
+	target: LinearCombination<E>,
+	num_bits: usize,
+) -> Result<Vec<boolean::AllocatedBit>, SynthesisError> {
+	let values: Vec<Option<bool>> = match value {
+		Some(value) => {
+			let mut bits: Vec<bool> = BitIterator::new(value.into_repr()).collect();
+			bits.reverse();
+			bits.into_iter().take(num_bits).map(Some).collect()
+		}
+		None => vec![None; num_bits],
+	};
+
+	let mut bits = Vec::with_capacity(num_bits);
+	for (i, bit) in values.into_iter().enumerate() {
+		bits.push(boolean::AllocatedBit::alloc(cs.namespace(|| format!("bit {}", i)), bit)?);
+	}
+
+	let mut lc = LinearCombination::zero();
+	let mut coeff = E::Fr::one();
+	for bit in bits.iter() {
+		lc = lc + (coeff, bit.get_variable());
+		coeff.double();
+	}
+	lc = lc - &target;
+	cs.enforce(|| "unpacking constraint", |lc| lc, |lc| lc, |_| lc);
+
+	Ok(bits)
+}
+
+/// Enforces value <= bound, by range checking bound - value
+pub fn less_or_equal<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	value: &T,
+	bound: &T,
+	num_bits: usize,
+) -> Result<(), SynthesisError> {
+	let slack = sub(cs.namespace(|| "slack"), bound, value)?;
+	range_check(cs.namespace(|| "slack range"), &slack, num_bits)?;
+	Ok(())
+}
+
+/// Floor division by a constant, numerator = quotient * denom + rem with 0 <= rem < denom.
+/// `numerator_u64` is the native value of the numerator. Returns the quotient.
+/// The quotient is not range checked: any other rem gives a field element quotient, so callers
+/// range check it to the bits of the largest floor quotient to make it the floor.
+pub fn div_const<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	numerator: &T,
+	numerator_u64: Option<u64>,
+	denom_u64: u64,
+) -> Result<T, SynthesisError> {
+	assert!(denom_u64 > 0, "division by zero");
+	let quotient = T::alloc(cs.namespace(|| "quotient"), || {
+		witness_fr::<E>(numerator_u64.map(|n| n / denom_u64))
+	})?;
+	let rem = T::alloc(cs.namespace(|| "rem"), || {
+		witness_fr::<E>(numerator_u64.map(|n| n % denom_u64))
+	})?;
+
+	cs.enforce(
+		|| "div enforce",
+		|lc| lc + quotient.get_variable(),
+		|lc| lc + (const_fr::<E>(denom_u64), CS::one()),
+		|lc| lc + numerator.get_variable() - rem.get_variable(),
+	);
+
+	// rem < denom, i.e. denom - 1 - rem fits the bits of denom
+	let num_bits = 64 - denom_u64.leading_zeros() as usize;
+	let rem_slack = T::alloc(cs.namespace(|| "rem slack"), || {
+		witness_fr::<E>(numerator_u64.map(|n| denom_u64 - 1 - n % denom_u64))
+	})?;
+	cs.enforce(
+		|| "rem slack enforce",
+		|lc| lc + rem_slack.get_variable() + rem.get_variable(),
+		|lc| lc + CS::one(),
+		|lc| lc + (const_fr::<E>(denom_u64 - 1), CS::one()),
+	);
+	range_check(cs.namespace(|| "rem range"), &rem, num_bits)?;
+	range_check(cs.namespace(|| "rem slack range"), &rem_slack, num_bits)?;
+
+	Ok(quotient)
+}
+
+/// Integer square root, sqr = squrt^2 + fract with 0 <= fract <= 2 * squrt.
+/// `sqr_u64` is the native value of sqr, which must be below 2^num_bits. Returns squrt.
+pub fn sqrt_floor<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	sqr: &T,
+	sqr_u64: Option<u64>,
+	num_bits: usize,
+) -> Result<T, SynthesisError> {
+	sqrt_with_root(cs, sqr, sqr_u64.map(isqrt), num_bits)
+}
+
+/// Allocates `root_u64` as the square root of sqr, satisfiable by the floor of the square root only
+fn sqrt_with_root<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	sqr: &T,
+	root_u64: Option<u64>,
+	num_bits: usize,
+) -> Result<T, SynthesisError> {
+	let squrt = T::alloc(cs.namespace(|| "squrt"), || {
+		witness_fr::<E>(root_u64)
+	})?;
+	let fract = T::alloc(cs.namespace(|| "fract"), || {
+		let mut root_sq = value_of::<E, T>(&squrt)?;
+		root_sq.square();
+		let mut tmp = value_of::<E, T>(sqr)?;
+		tmp.sub_assign(&root_sq);
+		Ok(tmp)
+	})?;
+
+	cs.enforce(
+		|| "sqrt enforce",
+		|lc| lc + squrt.get_variable(),
+		|lc| lc + squrt.get_variable(),
+		|lc| lc + sqr.get_variable() - fract.get_variable(),
+	);
+
+	// fract <= 2 * squrt, otherwise squrt + 1 would still square below sqr
+	let root_bits = (num_bits + 1) / 2;
+	let fract_slack = T::alloc(cs.namespace(|| "fract slack"), || {
+		let mut tmp = value_of::<E, T>(&squrt)?;
+		tmp.double();
+		tmp.sub_assign(&value_of::<E, T>(&fract)?);
+		Ok(tmp)
+	})?;
+	cs.enforce(
+		|| "fract slack enforce",
+		|lc| lc + fract_slack.get_variable() + fract.get_variable(),
+		|lc| lc + CS::one(),
+		|lc| lc + (const_fr::<E>(2), squrt.get_variable()),
+	);
+	range_check(cs.namespace(|| "squrt range"), &squrt, root_bits)?;
+	// without it a negative fract lets squrt overstate the root
+	range_check(cs.namespace(|| "fract range"), &fract, root_bits + 1)?;
+	range_check(cs.namespace(|| "fract slack range"), &fract_slack, root_bits + 1)?;
+
+	Ok(squrt)
+}
+
+/// Allocates condition ? a : b
+pub fn select<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	condition: &boolean::Boolean,
+	a: &T,
+	b: &T,
+) -> Result<T, SynthesisError> {
+	let res = T::alloc(cs.namespace(|| "select"), || {
+		if condition.get_value().ok_or(SynthesisError::AssignmentMissing)? {
+			value_of::<E, T>(a)
+		} else {
+			value_of::<E, T>(b)
+		}
+	})?;
+
+	// (a - b) * condition = res - b
+	cs.enforce(
+		|| "select constraint",
+		|lc| lc + a.get_variable() - b.get_variable(),
+		|_| condition.lc(CS::one(), E::Fr::one()),
+		|lc| lc + res.get_variable() - b.get_variable(),
+	);
+
+	Ok(res)
+}
+
+/// Absolute difference |a - b|, given the sign bit set when a < b.
+/// The sign is a witness and a wrong one gives the field negation of the difference:
+/// callers range check the result to make it binding.
+pub fn absdiff<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	a: &T,
+	b: &T,
+	sign: boolean::AllocatedBit,
+) -> Result<T, SynthesisError> {
+	let res = T::alloc(cs.namespace(|| "absdiff"), || {
+		let (mut x, mut y) = (value_of::<E, T>(a)?, value_of::<E, T>(b)?);
+		if sign.get_value().ok_or(SynthesisError::AssignmentMissing)? {
+			::std::mem::swap(&mut x, &mut y);
+		}
+		x.sub_assign(&y);
+		Ok(x)
+	})?;
+
+	// (a - b) * (1 - 2 * sign) = res
+	let mut two = E::Fr::one();
+	two.double();
+	cs.enforce(
+		|| "absdiff constraint",
+		|lc| lc + a.get_variable() - b.get_variable(),
+		|lc| lc + CS::one() - (two, sign.get_variable()),
+		|lc| lc + res.get_variable(),
+	);
+
+	Ok(res)
+}
+
+/// Bits of the pixels, `bit_depth` little-endian bits per pixel, `None` when a pixel exceeds `bit_depth` bits
+pub fn pixel_bits(pixels: &[u32], bit_depth: u32) -> Option<Vec<bool>> {
+	let mut bits = Vec::with_capacity(pixels.len() * bit_depth as usize);
+	for pix in pixels.iter() {
+		if *pix >> bit_depth != 0 {
+			return None;
+		}
+		for i in 0..bit_depth {
+			bits.push((pix >> i) & 1 == 1);
+		}
+	}
+	Some(bits)
+}
+
+/// Public inputs of `pack_pixels_into_inputs` for native pixels, `None` when a pixel exceeds `bit_depth` bits
+pub fn compute_pixel_packing(pixels: &[u32], bit_depth: u32) -> Option<Vec<Fr>> {
+	pixel_bits(pixels, bit_depth).map(|bits| multipack::compute_multipacking::<Bls12>(&bits))
+}
+
+/// Exposes the pixels as public inputs, packed with multipack at `bit_depth` bits per pixel:
+/// a 16x16 block of 8-bit pixels takes 9 inputs rather than 256. Unpacking the pixels also
+/// range checks them to the bit depth.
+pub fn pack_pixels_into_inputs<E: Engine, T: AllocatedValue<E>, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	pixels: &[T],
+	bit_depth: u32,
+) -> Result<(), SynthesisError> {
+	let mut bits = Vec::with_capacity(pixels.len() * bit_depth as usize);
+	for (i, pix) in pixels.iter().enumerate() {
+		bits.extend(range_check(cs.namespace(|| format!("pixel bits {}", i)), pix, bit_depth as usize)?);
+	}
+	multipack::pack_into_inputs(cs.namespace(|| "packed pixels"), &bits)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use storage_proofs::circuit::test::*;
+
+	fn fr(x: u64) -> Fr {
+		Fr::from_repr(x.into()).unwrap()
+	}
+
+	fn alloc_pix<CS: ConstraintSystem<Bls12>>(cs: CS, x: u64) -> AllocatedPixel<Bls12> {
+		AllocatedPixel::alloc(cs, || Ok(fr(x))).unwrap()
+	}
+
+	#[test]
+	fn test_arithmetic_gadgets() {
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let a = alloc_pix(cs.namespace(|| "a"), 23);
+		let b = alloc_pix(cs.namespace(|| "b"), 5);
+
+		let sum = sum_vec(cs.namespace(|| "weighted"), &[a.clone(), b.clone()], &[2, 3]).unwrap();
+		assert_eq!(sum.get_value(), Some(fr(61)));
+		let quotient = div_const(cs.namespace(|| "div"), &sum, Some(61), 7).unwrap();
+		assert_eq!(quotient.get_value(), Some(fr(8)));
+		assert_eq!(cs.get("div/rem/pix"), fr(5));
+		let root = sqrt_floor(cs.namespace(|| "sqrt"), &sum, Some(61), 8).unwrap();
+		assert_eq!(root.get_value(), Some(fr(7)));
+		assert_eq!(cs.get("sqrt/fract/pix"), fr(12));
+
+		let product = mul(cs.namespace(|| "mul"), &a, &b).unwrap();
+		assert_eq!(product.get_value(), Some(fr(115)));
+		let diff = sub(cs.namespace(|| "sub"), &a, &b).unwrap();
+		assert_eq!(diff.get_value(), Some(fr(18)));
+		for (name, sign, x, y) in [("a b", false, &a, &b), ("b a", true, &b, &a)].iter() {
+			let sign = boolean::AllocatedBit::alloc(cs.namespace(|| format!("sign {}", name)), Some(*sign)).unwrap();
+			let abs = absdiff(cs.namespace(|| format!("absdiff {}", name)), *x, *y, sign).unwrap();
+			assert_eq!(abs.get_value(), Some(fr(18)));
+		}
+		for (name, condition) in [("true", true), ("false", false)].iter() {
+			let bit = boolean::AllocatedBit::alloc(cs.namespace(|| format!("cond {}", name)), Some(*condition)).unwrap();
+			let selected = select(cs.namespace(|| format!("select {}", name)), &boolean::Boolean::from(bit), &a, &b).unwrap();
+			assert_eq!(selected.get_value(), Some(fr(if *condition { 23 } else { 5 })));
+		}
+		less_or_equal(cs.namespace(|| "b <= a"), &b, &a, 8).unwrap();
+		assert!(cs.is_satisfied());
+
+		// b - a wraps around the field and does not fit the range
+		less_or_equal(cs.namespace(|| "a <= b"), &a, &b, 8).unwrap();
+		assert!(!cs.is_satisfied());
+	}
+
+	#[test]
+	fn test_sqrt_floor_rejects_other_roots() {
+		// 61 = 7^2 + 12
+		for root in [6u64, 8].iter() {
+			let mut cs = TestConstraintSystem::<Bls12>::new();
+			let sqr = alloc_pix(cs.namespace(|| "sqr"), 61);
+			sqrt_with_root(cs.namespace(|| "sqrt"), &sqr, Some(*root), 8).unwrap();
+			assert!(!cs.is_satisfied(), "root {}", root);
+		}
+	}
+
+	#[test]
+	fn test_gadgets_over_allocated_num() {
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let a = num::AllocatedNum::alloc(cs.namespace(|| "a"), || Ok(fr(9))).unwrap();
+		let b = num::AllocatedNum::alloc(cs.namespace(|| "b"), || Ok(fr(4))).unwrap();
+		let diff = sub(cs.namespace(|| "sub"), &a, &b).unwrap();
+		let root = sqrt_floor(cs.namespace(|| "sqrt"), &a, Some(9), 4).unwrap();
+		assert_eq!(root.get_value(), Some(fr(3)));
+		let bit = boolean::AllocatedBit::alloc(cs.namespace(|| "cond"), Some(true)).unwrap();
+		let selected = select(cs.namespace(|| "select"), &boolean::Boolean::from(bit), &diff, &root).unwrap();
+		equal(&mut cs, || "selected diff", &selected, &diff);
+		assert!(cs.is_satisfied());
+
+		equal(&mut cs, || "root is not b", &root, &b);
+		assert!(!cs.is_satisfied());
+	}
+
+	#[test]
+	fn test_pack_pixels_into_inputs() {
+		for bit_depth in [8u32, 10].iter() {
+			let pixels: Vec<u32> = (0..256u32).map(|i| (i * 37) % (1 << *bit_depth)).collect();
+			let mut cs = TestConstraintSystem::<Bls12>::new();
+			let circ_pixels: Vec<AllocatedPixel<Bls12>> = pixels.iter().enumerate()
+				.map(|(i, x)| alloc_pix(cs.namespace(|| format!("pix {}", i)), *x as u64))
+				.collect();
+			pack_pixels_into_inputs(cs.namespace(|| "pack"), &circ_pixels, *bit_depth).unwrap();
+			assert!(cs.is_satisfied());
+
+			let expected = compute_pixel_packing(&pixels, *bit_depth).unwrap();
+			assert_eq!(expected.len(), (256 * *bit_depth as usize + 253) / 254);
+			assert_eq!(cs.num_inputs(), 1 + expected.len());
+			assert!(cs.verify(&expected));
+			assert_eq!(compute_pixel_packing(&[1 << *bit_depth], *bit_depth), None);
+		}
+	}
+}
@@ -1,31 +1,14 @@
 extern crate bellperson;
-extern crate fil_sapling_crypto;
 extern crate paired;
-extern crate pbr;
-extern crate rand;
-extern crate storage_proofs;
 
 use paired::Engine;
-use bellperson::groth16::*;
-use fil_sapling_crypto::circuit::multipack;
-use fil_sapling_crypto::jubjub::JubjubBls12;
-use paired::bls12_381::{Bls12, Fr};
-use rand::Rng;
-
-use storage_proofs::circuit;
-use storage_proofs::example_helper::Example;
-use storage_proofs::test_helper::random_merkle_path;
-
-use super::pixel::AllocatedPixel;
-use fil_sapling_crypto::circuit::boolean::{self, AllocatedBit, Boolean};
 use bellperson::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
-use byteorder::{BigEndian, ByteOrder};
 use std::collections::HashMap;
 use std::fmt::Write;
 
-use ff::{BitIterator, Field, PrimeField};
+use ff::{Field, PrimeField};
 
 #[derive(Debug)]
 enum NamedObject {
@@ -35,6 +18,8 @@ enum NamedObject {
 }
 
 /// Constraint system for testing purposes.
+/// Built with `new_shape`, it records circuits synthesized without a witness, as during setup,
+/// which is all the constraint and variable counts need.
 pub struct TestConstraintSystem<E: Engine> {
     named_objects: HashMap<String, NamedObject>,
     current_namespace: Vec<String>,
@@ -46,6 +31,8 @@ pub struct TestConstraintSystem<E: Engine> {
     )>,
     inputs: Vec<(E::Fr, String)>,
     aux: Vec<(E::Fr, String)>,
+    /// Record every assignment as zero without computing it
+    shape_only: bool,
 }
 #[derive(Clone, Copy)]
 struct OrderedVariable(Variable);
@@ -134,6 +121,27 @@ impl<E: Engine> TestConstraintSystem<E> {
             constraints: vec![],
             inputs: vec![(E::Fr::one(), "ONE".into())],
             aux: vec![],
+            shape_only: false,
+        }
+    }
+
+    /// Constraint system recording the shape of a circuit synthesized without a witness.
+    /// Like the parameter generator it never calls the assignment closures, every variable is
+    /// zero, so it is not meant to be satisfied.
+    pub fn new_shape() -> TestConstraintSystem<E> {
+        let mut cs = TestConstraintSystem::new();
+        cs.shape_only = true;
+        cs
+    }
+
+    fn assignment<F>(&self, f: F) -> Result<E::Fr, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+    {
+        if self.shape_only {
+            Ok(E::Fr::zero())
+        } else {
+            f()
         }
     }
 
@@ -221,6 +229,28 @@ impl<E: Engine> TestConstraintSystem<E> {
         None
     }
 
+    /// The constraint at `path` with its evaluated sides and, for every variable involved, its
+    /// path, coefficient and value
+    pub fn describe_constraint(&self, path: &str) -> Option<String> {
+        let &(ref a, ref b, ref c, _) = self.constraints.iter().find(|constraint| constraint.3 == path)?;
+        let mut s = format!("{}: a * b = c\n", path);
+        for (name, lc) in [("a", a), ("b", b), ("c", c)].iter() {
+            let value = eval_lc::<E>(lc.as_ref(), &self.inputs, &self.aux);
+            writeln!(s, "  {} = {}", name, fr_to_string::<E>(&value)).unwrap();
+            for (var, coeff) in proc_lc::<E>(lc.as_ref()) {
+                let (value, var_path) = match var.0.get_unchecked() {
+                    Index::Input(i) => &self.inputs[i],
+                    Index::Aux(i) => &self.aux[i],
+                };
+                writeln!(s, "    {} . `{}` = {}", fr_to_string::<E>(&coeff), var_path, fr_to_string::<E>(value)).unwrap();
+            }
+        }
+        let mut ab = eval_lc::<E>(a.as_ref(), &self.inputs, &self.aux);
+        ab.mul_assign(&eval_lc::<E>(b.as_ref(), &self.inputs, &self.aux));
+        writeln!(s, "  a * b = {}", fr_to_string::<E>(&ab)).unwrap();
+        Some(s)
+    }
+
     pub fn is_satisfied(&self) -> bool {
         self.which_is_unsatisfied().is_none()
     }
@@ -259,6 +289,52 @@ impl<E: Engine> TestConstraintSystem<E> {
         self.inputs.len()
     }
 
+    pub fn num_aux(&self) -> usize {
+        self.aux.len()
+    }
+
+    /// Constraints and auxiliary variables per namespace, namespaces cut to their first `depth`
+    /// levels. Objects allocated outside any namespace are counted under "".
+    pub fn namespace_breakdown(&self, depth: usize) -> BTreeMap<String, (usize, usize)> {
+        let mut breakdown = BTreeMap::new();
+        for &(_, _, _, ref path) in &self.constraints {
+            breakdown.entry(namespace_prefix(path, depth)).or_insert((0, 0)).0 += 1;
+        }
+        for &(_, ref path) in &self.aux {
+            breakdown.entry(namespace_prefix(path, depth)).or_insert((0, 0)).1 += 1;
+        }
+        breakdown
+    }
+
+    /// Constraints with their paths, every side as (wire, coefficient) terms sorted by wire.
+    /// Wire 0 is ONE, the public inputs follow, then the auxiliary variables.
+    pub fn wire_constraints(&self) -> Vec<([Vec<(usize, E::Fr)>; 3], &str)> {
+        let num_inputs = self.inputs.len();
+        let terms = |lc: &LinearCombination<E>| -> Vec<(usize, E::Fr)> {
+            proc_lc::<E>(lc.as_ref())
+                .into_iter()
+                .map(|(var, coeff)| match var.0.get_unchecked() {
+                    Index::Input(i) => (i, coeff),
+                    Index::Aux(i) => (num_inputs + i, coeff),
+                })
+                .collect()
+        };
+        self.constraints
+            .iter()
+            .map(|&(ref a, ref b, ref c, ref path)| ([terms(a), terms(b), terms(c)], path.as_str()))
+            .collect()
+    }
+
+    /// Values of the wires, in the order of `wire_constraints`
+    pub fn wire_values(&self) -> Vec<E::Fr> {
+        self.inputs.iter().chain(self.aux.iter()).map(|&(value, _)| value).collect()
+    }
+
+    /// Paths of the wires, in the order of `wire_constraints`
+    pub fn wire_labels(&self) -> Vec<&str> {
+        self.inputs.iter().chain(self.aux.iter()).map(|&(_, ref path)| path.as_str()).collect()
+    }
+
     pub fn get_input(&mut self, index: usize, path: &str) -> E::Fr {
         let (assignment, name) = self.inputs[index].clone();
 
@@ -290,6 +366,31 @@ impl<E: Engine> TestConstraintSystem<E> {
     }
 }
 
+/// Decimal value of small field elements and of their negation, the full element otherwise
+fn fr_to_string<E: Engine>(value: &E::Fr) -> String {
+    let small = |x: &E::Fr| {
+        let repr = x.into_repr();
+        match repr.as_ref().split_first() {
+            Some((low, high)) if high.iter().all(|limb| *limb == 0) => Some(*low),
+            _ => None,
+        }
+    };
+    let mut neg = *value;
+    neg.negate();
+    match (small(value), small(&neg)) {
+        (Some(x), _) => x.to_string(),
+        (_, Some(x)) => format!("-{}", x),
+        _ => format!("{}", value),
+    }
+}
+
+/// First `depth` namespaces of the path of a constraint or variable, whose last component names the object itself
+fn namespace_prefix(path: &str, depth: usize) -> String {
+    let components: Vec<&str> = path.split('/').collect();
+    let namespaces = &components[..components.len() - 1];
+    namespaces[..depth.min(namespaces.len())].join("/")
+}
+
 fn compute_path(ns: &[String], this: String) -> String {
     if this.chars().any(|a| a == '/') {
         panic!("'/' is not allowed in names");
@@ -321,7 +422,8 @@ impl<E: Engine> ConstraintSystem<E> for TestConstraintSystem<E> {
     {
         let index = self.aux.len();
         let path = compute_path(&self.current_namespace, annotation().into());
-        self.aux.push((f()?, path.clone()));
+        let value = self.assignment(f)?;
+        self.aux.push((value, path.clone()));
         let var = Variable::new_unchecked(Index::Aux(index));
         self.set_named_obj(path, NamedObject::Var(var));
 
@@ -336,7 +438,8 @@ impl<E: Engine> ConstraintSystem<E> for TestConstraintSystem<E> {
     {
         let index = self.inputs.len();
         let path = compute_path(&self.current_namespace, annotation().into());
-        self.inputs.push((f()?, path.clone()));
+        let value = self.assignment(f)?;
+        self.inputs.push((value, path.clone()));
         let var = Variable::new_unchecked(Index::Input(index));
         self.set_named_obj(path, NamedObject::Var(var));
 
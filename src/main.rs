@@ -35,20 +35,38 @@ use rustc_serialize::json::Json;
 use rustc_serialize::json;
 use fil_sapling_crypto::jubjub::{JubjubBls12, JubjubEngine, edwards::Point};
 use log::{info, trace, warn};
+use rust_phash::{HashSize, PHashes};
 
-//mod macroblock;
+mod macroblock;
 mod pixel;
 mod mb_ssim;
+mod mb_mse;
+mod mb_sad;
+mod mb_msssim;
 mod merkle_pot;
-mod constraint;
+mod gadget;
+mod fixed_point;
+mod source_commit;
+mod resample;
+mod quality;
+mod challenge;
+mod yuv;
+mod circuit_stats;
+mod r1cs;
+#[cfg(test)]
+mod test_helper;
 
 use paired::bls12_381::{Bls12, Fr, FrRepr};
 
 // For Testing
 use storage_proofs::hasher::pedersen::{PedersenDomain, PedersenFunction, PedersenHasher};
 use storage_proofs::merkle::{MerkleProof, MerkleTree, make_proof_for_test};
+use bellperson::Circuit;
 use bellperson::groth16::{Parameters, prepare_verifying_key, Proof};
 use mb_ssim::SsimApi;
+use mb_mse::MseApi;
+use mb_sad::SadApi;
+use mb_msssim::MsSsimApi;
 use merkle_pot::PorApi;
 
 use storage_proofs::hasher::{Sha256Hasher, Domain, Hasher};
@@ -60,22 +78,35 @@ use serde_json::Result;
 #[derive(Default)]
 pub struct SampleMb {
 	pixels: Vec<u16>,
+	/// 4:2:0 chroma planes, half the side of `pixels`. Absent for luma only input.
+	u: Option<Vec<u16>>,
+	v: Option<Vec<u16>>,
 }
 
-#[derive(RustcDecodable, RustcEncodable)]
-#[derive(Clone)]
-#[derive(Default)]
-pub struct PHashes {
-	phashes: Vec<u64>,
+impl SampleMb {
+	/// U and V planes of the block, exits if the input has no chroma
+	fn chroma(&self) -> (Vec<u32>, Vec<u32>) {
+		match (&self.u, &self.v) {
+			(Some(u), Some(v)) => (
+				u.iter().map(|x| *x as u32).collect(),
+				v.iter().map(|x| *x as u32).collect(),
+			),
+			_ => {
+				println!("input block has no u and v planes, extract it with --chroma");
+				process::exit(1);
+			}
+		}
+	}
 }
 
 //#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 //pub struct PedersenDomain(pub FrRepr);
 
 fn merkel_path_commit(
-	data: Vec<u64>
+	data: Vec<Vec<u8>>,
+	size: HashSize,
 ) -> Fr {
-	let leaves: Vec<Fr> = data.iter().map(|x| (Fr::from_repr(FrRepr::from(*x as u64))).unwrap()).collect();
+	let leaves: Vec<Fr> = merkle_pot::phash_leaves(&data, size);
 	let merk_tree = MerkleTree::<PedersenDomain, PedersenFunction>::from_data(leaves);
 
 	let root : Fr  = merk_tree.root().into();
@@ -86,10 +117,11 @@ fn merkel_path_commit(
 }
 
 fn merkel_path_proof(
-	data: Vec<u64>,
+	data: Vec<Vec<u8>>,
+	size: HashSize,
 	challenge: usize
 ) -> (Fr, Fr, Vec<Option<(Fr, bool)>>) {
-	let leaves: Vec<Fr> = data.iter().map(|x| (Fr::from_repr(FrRepr::from(*x as u64))).unwrap()).collect();
+	let leaves: Vec<Fr> = merkle_pot::phash_leaves(&data, size);
 	let merk_tree = MerkleTree::<PedersenDomain, PedersenFunction>::from_data(leaves);
 
 	// generate merkle path for challenged node and parents
@@ -194,17 +226,77 @@ fn merkel_path_sha256(
 	(auth_path, leaf, root, tree_depth)
 }
 
-fn setup(crs_path: String)
+fn ssim_crs_meta_path(crs_path: &str) -> String {
+	format!("{}.meta", crs_path)
+}
+
+fn save_ssim_crs_meta(crs_path: &str, meta: &mb_ssim::SsimCrsMeta)
+{
+	let mut meta_f = File::create(ssim_crs_meta_path(crs_path)).expect("faild to create crs meta file");
+	let meta_encoded = json::encode(meta).unwrap();
+	meta_f.write_all(meta_encoded.as_bytes()).expect("failed to write crs meta file");
+}
+
+fn load_ssim_crs_meta(crs_path: &str) -> mb_ssim::SsimCrsMeta
+{
+	let mut file = File::open(ssim_crs_meta_path(crs_path)).expect("faild to open crs meta file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("faild to read crs meta file");
+	json::decode(&data).expect("faild to parse crs meta file")
+}
+
+fn setup(
+	crs_path: String,
+	window_size: u32,
+	gaussian: bool,
+	tree_depth: u32,
+	ratio: resample::ResampleRatio,
+	chroma: Option<mb_ssim::YuvWeights>,
+	bit_depth: u32,
+	public_inputs: mb_ssim::PublicInputSchema)
 {	
 	let now = Instant::now();
 
-	let mut ssim= mb_ssim::SsimApp::default();
+	let window = mb_ssim::SsimWindow::new(window_size, gaussian);
+	let mut ssim= mb_ssim::SsimApp::new(window, tree_depth, ratio, chroma, bit_depth, public_inputs.clone());
 	let p = ssim.setup();
 	let mut f = File::create(&crs_path).expect("faild to open ssim_crs.dat file");
 	p.write(&mut f).expect("failed to write params to ssim_crs.dat");
+	save_ssim_crs_meta(&crs_path, &mb_ssim::SsimCrsMeta {
+		window: window_size,
+		gaussian,
+		tree_depth,
+		ratio,
+		chroma,
+		bit_depth,
+		public_inputs: public_inputs.clone(),
+	});
+	println!("public inputs {:?}", public_inputs.names());
 	println!("Setup {}", now.elapsed().as_millis());
 }
 
+/// Parses a bit depth argument, exits on depths the circuit does not support
+fn parse_bit_depth(arg: &str, supported: &[u32]) -> u32
+{
+	match arg.parse::<u32>() {
+		Ok(bit_depth) if supported.contains(&bit_depth) => bit_depth,
+		_ => {
+			println!("unsupported bit depth {}, expected one of {:?}", arg, supported);
+			process::exit(1);
+		}
+	}
+}
+
+/// Exits when a pixel of the input does not fit the bit depth of the crs
+fn check_bit_depth(mb: &[u32], bit_depth: u32)
+{
+	let max = pixel::max_pixel_value(bit_depth);
+	if let Some(pix) = mb.iter().find(|x| **x > max) {
+		println!("pixel value {} exceeds the {}-bit range of the crs", pix, bit_depth);
+		process::exit(1);
+	}
+}
+
 fn get_input_mb(input_file: String) -> Vec<u32>
 {
 	let mut file = File::open(input_file).expect("verify: faild to open input_file file");
@@ -219,37 +311,196 @@ fn get_input_mb(input_file: String) -> Vec<u32>
 	pixels
 }
 
+/// All macroblocks of a source frame, in raster order
+fn get_input_mbs(input_file: String) -> Vec<Vec<u32>>
+{
+	let mut file = File::open(input_file).expect("faild to open source file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("faild to read source file");
+
+	let frames:  Vec<SampleMb> = json::decode(&data).unwrap();
+	frames.iter().map(|mb| mb.pixels.iter().map(|x| *x as u32).collect()).collect()
+}
+
+/// Blocks as read from an input file, including chroma planes when present
+fn get_input_samples(input_file: String) -> Vec<SampleMb>
+{
+	let mut file = File::open(input_file).expect("faild to open input file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("faild to read input file");
+	json::decode(&data).unwrap()
+}
+
+/// Source frame leaves. With chroma each leaf covers the Y, U and V planes of a block.
+fn get_source_leaves(input_file: String, chroma: bool) -> Vec<Vec<u32>>
+{
+	source_leaves(&get_input_samples(input_file), chroma)
+}
+
+fn source_leaves(samples: &[SampleMb], chroma: bool) -> Vec<Vec<u32>>
+{
+	samples.iter().map(|mb| {
+		if !chroma {
+			return mb.pixels.iter().map(|x| *x as u32).collect();
+		}
+		let (u, v) = mb.chroma();
+		mb.pixels.iter().map(|x| *x as u32).chain(u.into_iter()).chain(v.into_iter()).collect()
+	}).collect()
+}
+
+fn save_source_commitment(commitment_path: String, commitment: &source_commit::SourceCommitment)
+{
+	let ser = serde_json::to_string(commitment).unwrap();
+	let mut commitment_f = File::create(&commitment_path).expect("faild to create commitment file");
+	commitment_f.write_all(ser.as_bytes()).expect("failed to write commitment file");
+}
+
+fn load_source_commitment(commitment_path: String) -> source_commit::SourceCommitment
+{
+	let mut file = File::open(&commitment_path).expect("faild to open commitment file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("faild to read commitment file");
+	serde_json::from_str(&data).expect("faild to parse commitment file")
+}
+
+/// Publisher side: commits to all source macroblocks of frame `frame`
+fn ssimcommit(crs_path: String, src_path: String, commitment_path: String, frame: u32)
+{
+	let meta = load_ssim_crs_meta(&crs_path);
+	let src_blocks = get_source_leaves(src_path, meta.chroma.is_some());
+	commit_source_blocks(&meta, &src_blocks, commitment_path, frame);
+}
+
+fn commit_source_blocks(meta: &mb_ssim::SsimCrsMeta, src_blocks: &[Vec<u32>], commitment_path: String, frame: u32)
+{
+	if src_blocks.len() > 1 << meta.tree_depth {
+		println!("{} source blocks do not fit a tree of depth {}", src_blocks.len(), meta.tree_depth);
+		process::exit(1);
+	}
+	for mb in src_blocks.iter() {
+		check_bit_depth(mb, meta.bit_depth);
+	}
+	let root = source_commit::source_commit(&src_blocks, meta.tree_depth, meta.bit_depth, frame);
+	save_source_commitment(commitment_path, &source_commit::SourceCommitment {
+		root: PedersenDomain(FrRepr::from(root)),
+		frame,
+		tree_depth: meta.tree_depth,
+		num_blocks: src_blocks.len() as u32,
+		bit_depth: meta.bit_depth,
+	});
+	println!("source root={:?}", root);
+}
+
 fn genproof(
 	crs_path: String, 
 	proof_path: String, 
 	input1_path: String, 
 	input2_path: String, 
-	witness_path: String,)
+	witness_path: String,
+	position: challenge::MbPosition,)
 {	
-	let now = Instant::now();
+	let src_samples = get_input_samples(input1_path);
+	let dst_sample = get_input_samples(input2_path)[0].clone();
+	prove_ssim(crs_path, proof_path, witness_path, &src_samples, dst_sample, position)
+}
 
-    let file_path = Path::new(&crs_path);
+/// Blocks, witness and source path of an SSIM proof, checked against the crs metadata
+struct SsimProverInput {
+	ssim: mb_ssim::SsimApp,
+	src_mb: Vec<u32>,
+	dst_mb: Vec<u32>,
+	witns: mb_ssim::Witness,
+	src_auth_path: Vec<Option<(Fr, bool)>>,
+	src_root: Fr,
+	chroma: Option<(mb_ssim::ChromaBlocks, mb_ssim::ChromaWitness)>,
+}
+
+/// Prepares the SSIM proof of `dst_sample` against the source block at `position` of the frame `src_samples`
+fn ssim_prover_input(
+	meta: &mb_ssim::SsimCrsMeta,
+	src_samples: &[SampleMb],
+	dst_sample: SampleMb,
+	position: challenge::MbPosition,) -> SsimProverInput
+{
+	let src_index = position.index();
+	let window = mb_ssim::SsimWindow::new(meta.window, meta.gaussian);
+	let mb_size = window.mb_size();
+	let src_blocks = source_leaves(src_samples, meta.chroma.is_some());
+	if src_index >= src_blocks.len() || src_blocks.len() > 1 << meta.tree_depth {
+		println!("source block {} not available in a frame of {} blocks (tree depth {})",
+			src_index, src_blocks.len(), meta.tree_depth);
+		process::exit(1);
+	}
+	let src_mb: Vec<u32> = src_samples[src_index].pixels.iter().map(|x| *x as u32).collect();
+	let dst_mb: Vec<u32> = dst_sample.pixels.iter().map(|x| *x as u32).collect();
+	for mb in src_blocks.iter().chain(std::iter::once(&dst_mb)) {
+		check_bit_depth(mb, meta.bit_depth);
+	}
+	let ssim = mb_ssim::SsimApp::new(window.clone(), meta.tree_depth, meta.ratio, meta.chroma, meta.bit_depth, meta.public_inputs.clone());
+	if src_mb.len() != ssim.src_mb_size() || dst_mb.len() != mb_size {
+		println!("input blocks have {} and {} pixels, crs expects {} source pixels and a {}x{} window ({} pixels)",
+			src_mb.len(), dst_mb.len(), ssim.src_mb_size(), meta.window, meta.window, mb_size);
+		process::exit(1);
+	}
+
+	let witns = mb_ssim::gen_witness(&resample::resample(&src_mb, &meta.ratio, meta.window), &dst_mb, &window, meta.bit_depth);
+	let chroma = meta.chroma.map(|_| {
+		let chroma_window = window.chroma();
+		let chroma_side = meta.window / 2;
+		let (src_u, src_v) = src_samples[src_index].chroma();
+		let (dst_u, dst_v) = dst_sample.chroma();
+		check_bit_depth(&dst_u, meta.bit_depth);
+		check_bit_depth(&dst_v, meta.bit_depth);
+		let src_len = (ssim.src_mb_size() / 4) as usize;
+		if src_u.len() != src_len || src_v.len() != src_len
+			|| dst_u.len() != chroma_window.mb_size() || dst_v.len() != chroma_window.mb_size() {
+			println!("chroma planes have {} and {} pixels, crs expects {} source pixels and {} destination pixels",
+				src_u.len(), dst_u.len(), src_len, chroma_window.mb_size());
+			process::exit(1);
+		}
+		let chroma_witns = mb_ssim::ChromaWitness {
+			u: mb_ssim::gen_witness(&resample::resample(&src_u, &meta.ratio, chroma_side), &dst_u, &chroma_window, meta.bit_depth),
+			v: mb_ssim::gen_witness(&resample::resample(&src_v, &meta.ratio, chroma_side), &dst_v, &chroma_window, meta.bit_depth),
+		};
+		(mb_ssim::ChromaBlocks { src_u, src_v, dst_u, dst_v }, chroma_witns)
+	});
+	let (src_auth_path, src_root) = source_commit::source_path(&src_blocks, meta.tree_depth, meta.bit_depth, position.frame, src_index);
+	SsimProverInput { ssim, src_mb, dst_mb, witns, src_auth_path, src_root, chroma }
+}
+
+/// Proves the SSIM of `dst_sample` against the source block at `position` of the frame `src_samples`
+fn prove_ssim(
+	crs_path: String,
+	proof_path: String,
+	witness_path: String,
+	src_samples: &[SampleMb],
+	dst_sample: SampleMb,
+	position: challenge::MbPosition,)
+{
+	let now = Instant::now();
+	let meta = load_ssim_crs_meta(&crs_path);
+	let SsimProverInput { mut ssim, src_mb, dst_mb, witns, src_auth_path, src_root, chroma } = ssim_prover_input(&meta, src_samples, dst_sample, position);
 
-	let mut ssim= mb_ssim::SsimApp::default();
     let groth_params: Parameters<Bls12> = {
 		let f = File::open(&crs_path).expect("failed to open rssim_crs.dat");
 		Parameters::read(&f, false).expect("failed to read rssim_crs.dat")
 	};
-	
-	let mb_size = 256;
-	let mut rng = rand::thread_rng();
-	let src_mb: Vec<u32> = get_input_mb(input1_path);
-	let dst_mb: Vec<u32> = get_input_mb(input2_path);
-		
-	let witns = mb_ssim::gen_witness(&src_mb.clone(), &dst_mb.clone());
+
+	let chroma_witns = chroma.as_ref().map(|(_, w)| w.clone());
 	let proof_start = Instant::now();		
-	let proof = ssim.create_proof(&groth_params, src_mb, dst_mb, witns.clone());
+	let proof = ssim.create_proof(&groth_params, src_mb, dst_mb, witns.clone(), src_auth_path, src_root, position, chroma);
 	println!("Proof generation {}", now.elapsed().as_millis());
 
 	// save proof to file
 	let mut proof_f = File::create(&proof_path).expect("faild to create proof file");
 	proof.write(&mut proof_f).expect("failed to serialize proof file");
 	
+	if let Some(chroma_witns) = chroma_witns {
+		let mut chroma_f = File::create(chroma_witness_path(&witness_path)).expect("failed to create chroma witness file");
+		chroma_f.write_all(json::encode(&chroma_witns).unwrap().as_bytes()).expect("failed to write chroma witness file");
+		println!("yuv weights={:?}", meta.chroma.unwrap());
+	}
+
 	let mut witness_f = File::create(witness_path).expect("failed to create witness file");
     let witness_encoded = json::encode(&witns).unwrap();
     witness_f.write_all(witness_encoded.as_bytes());
@@ -258,28 +509,201 @@ fn genproof(
 	println!("Load CRS + Proof generation {}", now.elapsed().as_millis());
 }
 
-fn get_witness(witness_path: String) -> Vec<u32> {
-	// let decoded: TestStruct = json::decode(&encoded[..]).unwrap();
+/// Parses raw video dimensions such as "1920x1080"
+fn parse_yuv_format(arg: &str, bit_depth: u32) -> yuv::YuvFormat
+{
+	let dims: Vec<Option<u32>> = arg.split('x').map(|x| x.parse::<u32>().ok()).collect();
+	match dims.as_slice() {
+		[Some(width), Some(height)] if *width > 0 && *height > 0 => yuv::YuvFormat { width: *width, height: *height, bit_depth },
+		_ => {
+			println!("invalid dimensions {}, expected widthxheight", arg);
+			process::exit(1);
+		}
+	}
+}
+
+fn read_yuv_frame(path: &str, raw_format: Option<yuv::YuvFormat>, frame: u32, bit_depth: u32) -> yuv::YuvFrame
+{
+	let mut reader = yuv::YuvReader::open(path, raw_format).unwrap_or_else(|e| {
+		println!("failed to open {}: {}", path, e);
+		process::exit(1);
+	});
+	if reader.format.bit_depth != bit_depth {
+		println!("{} holds {}-bit samples, crs expects {}-bit samples", path, reader.format.bit_depth, bit_depth);
+		process::exit(1);
+	}
+	reader.read_frame(frame).unwrap_or_else(|e| {
+		println!("failed to read frame {} of {}: {}", frame, path, e);
+		process::exit(1);
+	})
+}
+
+/// Block of `side` luma pixels at macroblock (mb_x, mb_y), with its chroma planes when `chroma` is set
+fn frame_sample(frame: &yuv::YuvFrame, mb_x: u32, mb_y: u32, side: u32, chroma: bool) -> SampleMb
+{
+	let plane = |p: usize, side: u32| -> Vec<u16> {
+		frame.block(p, mb_x * side, mb_y * side, side).expect("macroblock outside the frame").iter().map(|x| *x as u16).collect()
+	};
+	SampleMb {
+		pixels: plane(0, side),
+		u: if chroma { Some(plane(1, side / 2)) } else { None },
+		v: if chroma { Some(plane(2, side / 2)) } else { None },
+	}
+}
+
+/// Extracts the source frame and the destination block at (mb_x, mb_y) from decoded video and
+/// proves their SSIM. Writes the proof, the witness, the source commitment and the position as a
+/// challenge file. ssimverify derives the position from the seed, so the position should be one
+/// ssimchallenge derived.
+fn genproof_frames(
+	crs_path: String,
+	proof_path: String,
+	witness_path: String,
+	commitment_path: String,
+	challenge_path: String,
+	src_path: String,
+	dst_path: String,
+	frame: u32,
+	mb_x: u32,
+	mb_y: u32,
+	raw_formats: Option<(yuv::YuvFormat, yuv::YuvFormat)>,)
+{
+	let meta = load_ssim_crs_meta(&crs_path);
+	let chroma = meta.chroma.is_some();
+	let src_frame = read_yuv_frame(&src_path, raw_formats.map(|f| f.0), frame, meta.bit_depth);
+	let dst_frame = read_yuv_frame(&dst_path, raw_formats.map(|f| f.1), frame, meta.bit_depth);
+
+	// source blocks of the lower resolution ratio are larger, both grids have the same number of blocks
+	let src_side = meta.ratio.src_side(meta.window);
+	let (mbs_per_row, mbs_per_col) = (dst_frame.format.width / meta.window, dst_frame.format.height / meta.window);
+	if src_frame.format.width / src_side != mbs_per_row || src_frame.format.height / src_side != mbs_per_col {
+		println!("source grid of {}x{} blocks does not match the destination grid of {}x{} blocks",
+			src_frame.format.width / src_side, src_frame.format.height / src_side, mbs_per_row, mbs_per_col);
+		process::exit(1);
+	}
+	if mb_x >= mbs_per_row || mb_y >= mbs_per_col {
+		println!("macroblock ({}, {}) outside the {}x{} grid", mb_x, mb_y, mbs_per_row, mbs_per_col);
+		process::exit(1);
+	}
+	let mut src_samples = vec![];
+	for y in 0..mbs_per_col {
+		for x in 0..mbs_per_row {
+			src_samples.push(frame_sample(&src_frame, x, y, src_side, chroma));
+		}
+	}
+	let dst_sample = frame_sample(&dst_frame, mb_x, mb_y, meta.window, chroma);
+	let position = challenge::MbPosition { frame, mb_x, mb_y, mbs_per_row };
+
+	commit_source_blocks(&meta, &source_leaves(&src_samples, chroma), commitment_path, frame);
+	prove_ssim(crs_path, proof_path, witness_path, &src_samples, dst_sample, position);
+	let mut challenge_f = File::create(challenge_path).expect("failed to create challenge file");
+	challenge_f.write_all(json::encode(&vec![position]).unwrap().as_bytes()).expect("failed to write challenge file");
+}
+
+/// Hex seed argument
+fn parse_seed(arg: &str) -> Vec<u8>
+{
+	challenge::decode_seed(arg).unwrap_or_else(|| {
+		println!("seed {} is not a hex string", arg);
+		process::exit(1);
+	})
+}
+
+fn check_stream_dims(dims: &challenge::StreamDims)
+{
+	if dims.num_frames == 0 || dims.mb_side == 0 || dims.mbs_per_row() == 0 || dims.mbs_per_col() == 0 {
+		println!("stream of {} frames {}x{} holds no {}x{} macroblock", dims.num_frames, dims.width, dims.height, dims.mb_side, dims.mb_side);
+		process::exit(1);
+	}
+}
+
+/// Derives the audited positions of a stream from the seed and saves them
+fn ssimchallenge(challenge_path: String, seed: Vec<u8>, dims: challenge::StreamDims, count: usize)
+{
+	let positions = challenge::challenge_positions(&seed, &dims, count);
+	for (i, pos) in positions.iter().enumerate() {
+		println!("challenge {}: frame={} mb_x={} mb_y={} index={}", i, pos.frame, pos.mb_x, pos.mb_y, pos.index());
+	}
+	let mut challenge_f = File::create(challenge_path).expect("failed to create challenge file");
+	challenge_f.write_all(json::encode(&positions).unwrap().as_bytes()).expect("failed to write challenge file");
+}
+
+/// Position `n` of a challenge file
+fn load_challenge(challenge_path: String, n: usize) -> challenge::MbPosition
+{
+	let mut file = File::open(challenge_path).expect("faild to open challenge file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("faild to read challenge file");
+	let positions: Vec<challenge::MbPosition> = json::decode(&data).expect("faild to parse challenge file");
+	if n >= positions.len() {
+		println!("challenge {} not in a file of {} positions", n, positions.len());
+		process::exit(1);
+	}
+	positions[n]
+}
+
+/// The U and V witnesses are stored next to the Y witness
+fn chroma_witness_path(witness_path: &str) -> String {
+	format!("{}.chroma", witness_path)
+}
+
+fn load_chroma_witness(witness_path: &str) -> mb_ssim::ChromaWitness {
+	let mut file = File::open(chroma_witness_path(witness_path)).expect("verify: faild to open chroma witness file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("verify: faild to read chroma witness file");
+	json::decode(&data).expect("verify: faild to parse chroma witness file")
+}
+
+fn load_witness(witness_path: &str) -> mb_ssim::Witness {
 	let mut file = File::open(witness_path).expect("verify: faild to open witness file");
 	let mut data = String::new();
 	file.read_to_string(&mut data).expect("verify: faild to read witness file");
-	//let witness_j = Json::from_str(&data).expect("verify: faild to parse witness");
-	let witness: mb_ssim::Witness = json::decode(&data).unwrap();
-	let mut selected_fields: Vec<u32> = vec![];
-	selected_fields.push(witness.sum_y);
-	selected_fields.push(witness.sigma_y);
-	selected_fields.push(witness.ssim_numerator);
-	selected_fields.push(witness.ssim_denom);
-	info!("witness {:?}", selected_fields);
+	json::decode(&data).unwrap()
+}
+
+/// Public input values of the witness, in the order of the schema of the crs
+fn get_witness(witness_path: String, schema: &mb_ssim::PublicInputSchema) -> Vec<u64> {
+	let witness = load_witness(&witness_path);
+	let selected_fields = schema.values(&witness);
+	info!("witness {:?} = {:?}", schema.names(), selected_fields);
 	selected_fields
 }
 
-fn verify(crs_path: String, proof_path: String, witness_path: String,)
+/// Verifies the proof of challenge `challenge_index` of the stream, deriving the position from the
+/// seed rather than taking the prover's
+fn verify(
+	crs_path: String,
+	proof_path: String,
+	witness_path: String,
+	commitment_path: String,
+	seed: Vec<u8>,
+	mut dims: challenge::StreamDims,
+	challenge_index: usize,)
 {
 	let now = Instant::now();	
 
     let file_path = Path::new(&crs_path);
-	let mut ssim= mb_ssim::SsimApp::default();
+	let meta = load_ssim_crs_meta(&crs_path);
+	dims.mb_side = meta.window;
+	check_stream_dims(&dims);
+	let position = challenge::challenge_positions(&seed, &dims, challenge_index + 1)[challenge_index];
+	let src_index = position.index();
+	println!("challenge {}: frame={} mb_x={} mb_y={} index={}", challenge_index, position.frame, position.mb_x, position.mb_y, src_index);
+	let commitment = load_source_commitment(commitment_path);
+	if commitment.frame != position.frame {
+		println!("commitment covers frame {}, challenge {} is in frame {}", commitment.frame, challenge_index, position.frame);
+		process::exit(1);
+	}
+	if commitment.tree_depth != meta.tree_depth || src_index >= commitment.num_blocks as usize {
+		println!("source block {} not covered by the commitment ({} blocks, tree depth {}, crs tree depth {})",
+			src_index, commitment.num_blocks, commitment.tree_depth, meta.tree_depth);
+		process::exit(1);
+	}
+	if commitment.bit_depth != meta.bit_depth {
+		println!("commitment holds {}-bit blocks, crs expects {}-bit blocks", commitment.bit_depth, meta.bit_depth);
+		process::exit(1);
+	}
+	let mut ssim= mb_ssim::SsimApp::new(mb_ssim::SsimWindow::new(meta.window, meta.gaussian), meta.tree_depth, meta.ratio, meta.chroma, meta.bit_depth, meta.public_inputs.clone());
     let groth_params: Parameters<Bls12> = {
 		let f = File::open(&crs_path).expect("failed to open rssim_crs.dat");
 		Parameters::read(&f, false).expect("failed to read rssim_crs.dat")
@@ -289,9 +713,12 @@ fn verify(crs_path: String, proof_path: String, witness_path: String,)
 	let proof: Proof<Bls12> = Proof::read(&mut f).expect("failed to read proof to file ssim_proof.dat");
 	
 	let pvk = prepare_verifying_key(&groth_params.vk);
-	let public_inputs = get_witness(witness_path);
+	let yuv = meta.chroma.map(|weights| {
+		mb_ssim::yuv_fraction(&weights, &load_witness(&witness_path), &load_chroma_witness(&witness_path))
+	});
+	let public_inputs = get_witness(witness_path, &meta.public_inputs);
 	let verify_start = Instant::now();	
-	let res = ssim.verify_proof(&pvk, &proof, public_inputs).unwrap();
+	let res = ssim.verify_proof(&pvk, &proof, public_inputs, &position, commitment.root.into(), yuv).unwrap();
 	println!("Verificaiton result = {:?}", res);
 	println!("Only Verification {}", verify_start.elapsed().as_millis());	
 	println!("Load Proof+Verification {}", now.elapsed().as_millis());	
@@ -299,15 +726,359 @@ fn verify(crs_path: String, proof_path: String, witness_path: String,)
 
 
 
-fn gensample(mb_size: u32, sample1_file: String, sample2_file: String) 
+/// MSE metadata has its own file, so an MSE setup never overwrites the SSIM meta of a CRS of the same name
+fn mse_crs_meta_path(crs_path: &str) -> String {
+	format!("{}.mse.meta", crs_path)
+}
+
+fn msesetup(crs_path: String, window_size: u32, bit_depth: u32)
+{
+	let now = Instant::now();
+
+	let mut mse = mb_mse::MseApp::new((window_size * window_size) as usize, bit_depth);
+	let p = mse.setup();
+	let mut f = File::create(&crs_path).expect("faild to open mse crs file");
+	p.write(&mut f).expect("failed to write params to mse crs file");
+	let mut meta_f = File::create(mse_crs_meta_path(&crs_path)).expect("faild to create crs meta file");
+	let meta_encoded = json::encode(&mb_mse::MseCrsMeta { window: window_size, bit_depth }).unwrap();
+	meta_f.write_all(meta_encoded.as_bytes()).expect("failed to write crs meta file");
+	println!("Setup {}", now.elapsed().as_millis());
+}
+
+fn load_mse_crs_meta(crs_path: &str) -> mb_mse::MseCrsMeta
+{
+	let mut file = File::open(mse_crs_meta_path(crs_path)).expect("faild to open crs meta file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("faild to read crs meta file");
+	let meta: mb_mse::MseCrsMeta = json::decode(&data).expect("faild to parse crs meta file");
+	parse_bit_depth(&meta.bit_depth.to_string(), &pixel::SUPPORTED_BIT_DEPTHS);
+	meta
+}
+
+/// MSE threshold argument, a maximum MSE, or a minimum PSNR in dB when followed by "psnr"
+fn parse_mse_threshold(args: &[String], index: usize, crs_path: &str) -> u64 {
+	if args.len() > index + 1 && args[index + 1] == "psnr" {
+		let bit_depth = load_mse_crs_meta(crs_path).bit_depth;
+		mb_mse::psnr_to_mse_threshold(args[index].parse::<f64>().unwrap(), bit_depth)
+	} else {
+		args[index].parse::<u64>().unwrap()
+	}
+}
+
+/// Blocks and witness of an MSE proof, checked against the crs metadata and the threshold
+fn mse_prover_input(
+	meta: &mb_mse::MseCrsMeta,
+	input1_path: String,
+	input2_path: String,
+	threshold: u64,) -> (Vec<u32>, Vec<u32>, mb_mse::MseWitness)
+{
+	let mb_size = (meta.window * meta.window) as usize;
+	let src_mb: Vec<u32> = get_input_mb(input1_path);
+	let dst_mb: Vec<u32> = get_input_mb(input2_path);
+	if src_mb.len() != mb_size || dst_mb.len() != mb_size {
+		println!("input blocks have {} and {} pixels, crs expects {}x{} blocks ({} pixels)",
+			src_mb.len(), dst_mb.len(), meta.window, meta.window, mb_size);
+		process::exit(1);
+	}
+
+	check_bit_depth(&src_mb, meta.bit_depth);
+	check_bit_depth(&dst_mb, meta.bit_depth);
+	let witns = mb_mse::gen_witness(&src_mb, &dst_mb, threshold);
+	if witns.mse > threshold {
+		println!("mse={} (psnr={:.2}) exceeds the threshold {}", witns.mse, mb_mse::mse_to_psnr(witns.mse as f64, meta.bit_depth), threshold);
+		process::exit(1);
+	}
+	(src_mb, dst_mb, witns)
+}
+
+fn msegenproof(
+	crs_path: String,
+	proof_path: String,
+	input1_path: String,
+	input2_path: String,
+	witness_path: String,
+	threshold: u64,)
+{
+	let now = Instant::now();
+
+	let meta = load_mse_crs_meta(&crs_path);
+	let (src_mb, dst_mb, witns) = mse_prover_input(&meta, input1_path, input2_path, threshold);
+
+	let mut mse = mb_mse::MseApp::new((meta.window * meta.window) as usize, meta.bit_depth);
+	let groth_params: Parameters<Bls12> = {
+		let f = File::open(&crs_path).expect("failed to open mse crs file");
+		Parameters::read(&f, false).expect("failed to read mse crs file")
+	};
+	let proof = mse.create_proof(&groth_params, src_mb, dst_mb, witns.clone());
+
+	let mut proof_f = File::create(&proof_path).expect("faild to create proof file");
+	proof.write(&mut proof_f).expect("failed to serialize proof file");
+
+	let mut witness_f = File::create(witness_path).expect("failed to create witness file");
+	let witness_encoded = json::encode(&witns).unwrap();
+	witness_f.write_all(witness_encoded.as_bytes()).expect("failed to write witness file");
+
+	println!("mse={:?} threshold={:?}", witns.mse, witns.threshold);
+	println!("Load CRS + Proof generation {}", now.elapsed().as_millis());
+}
+
+/// The threshold is the verifier's, never read from the prover's witness file
+fn mseverify(crs_path: String, proof_path: String, input1_path: String, input2_path: String, threshold: u64)
+{
+	let now = Instant::now();
+
+	let meta = load_mse_crs_meta(&crs_path);
+	let mut mse = mb_mse::MseApp::new((meta.window * meta.window) as usize, meta.bit_depth);
+	let groth_params: Parameters<Bls12> = {
+		let f = File::open(&crs_path).expect("failed to open mse crs file");
+		Parameters::read(&f, false).expect("failed to read mse crs file")
+	};
+
+	let mut f = File::open(&proof_path).expect("faild to open proof file");
+	let proof: Proof<Bls12> = Proof::read(&mut f).expect("failed to read proof file");
+
+	let pvk = prepare_verifying_key(&groth_params.vk);
+	let src_mb: Vec<u32> = get_input_mb(input1_path);
+	let dst_mb: Vec<u32> = get_input_mb(input2_path);
+	check_bit_depth(&src_mb, meta.bit_depth);
+	check_bit_depth(&dst_mb, meta.bit_depth);
+	let res = mse.verify_proof(&pvk, &proof, src_mb, dst_mb, threshold).unwrap_or_else(|| {
+		println!("input blocks exceed the {}-bit range of the crs", meta.bit_depth);
+		process::exit(1);
+	});
+	println!("Verificaiton result = {:?}", res);
+	println!("Load Proof+Verification {}", now.elapsed().as_millis());
+}
+
+/// SAD metadata has its own file, as MSE metadata does
+fn sad_crs_meta_path(crs_path: &str) -> String {
+	format!("{}.sad.meta", crs_path)
+}
+
+fn sadsetup(crs_path: String, window_size: u32, bit_depth: u32)
+{
+	let now = Instant::now();
+
+	let mut sad = mb_sad::SadApp::new((window_size * window_size) as usize, bit_depth);
+	let p = sad.setup();
+	let mut f = File::create(&crs_path).expect("faild to open sad crs file");
+	p.write(&mut f).expect("failed to write params to sad crs file");
+	let mut meta_f = File::create(sad_crs_meta_path(&crs_path)).expect("faild to create crs meta file");
+	let meta_encoded = json::encode(&mb_sad::SadCrsMeta { window: window_size, bit_depth }).unwrap();
+	meta_f.write_all(meta_encoded.as_bytes()).expect("failed to write crs meta file");
+	println!("Setup {}", now.elapsed().as_millis());
+}
+
+fn load_sad_crs_meta(crs_path: &str) -> mb_sad::SadCrsMeta
+{
+	let mut file = File::open(sad_crs_meta_path(crs_path)).expect("faild to open crs meta file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("faild to read crs meta file");
+	let meta: mb_sad::SadCrsMeta = json::decode(&data).expect("faild to parse crs meta file");
+	parse_bit_depth(&meta.bit_depth.to_string(), &pixel::SUPPORTED_BIT_DEPTHS);
+	meta
+}
+
+fn sadgenproof(
+	crs_path: String,
+	proof_path: String,
+	input1_path: String,
+	input2_path: String,
+	witness_path: String,
+	bound: u64,)
+{
+	let now = Instant::now();
+
+	let meta = load_sad_crs_meta(&crs_path);
+	let mb_size = (meta.window * meta.window) as usize;
+	let src_mb: Vec<u32> = get_input_mb(input1_path);
+	let dst_mb: Vec<u32> = get_input_mb(input2_path);
+	if src_mb.len() != mb_size || dst_mb.len() != mb_size {
+		println!("input blocks have {} and {} pixels, crs expects {}x{} blocks ({} pixels)",
+			src_mb.len(), dst_mb.len(), meta.window, meta.window, mb_size);
+		process::exit(1);
+	}
+
+	check_bit_depth(&src_mb, meta.bit_depth);
+	check_bit_depth(&dst_mb, meta.bit_depth);
+	let witns = mb_sad::gen_witness(&src_mb, &dst_mb, bound);
+	if witns.sad > bound {
+		println!("sad={} exceeds the bound {}", witns.sad, bound);
+		process::exit(1);
+	}
+
+	let mut sad = mb_sad::SadApp::new(mb_size, meta.bit_depth);
+	let groth_params: Parameters<Bls12> = {
+		let f = File::open(&crs_path).expect("failed to open sad crs file");
+		Parameters::read(&f, false).expect("failed to read sad crs file")
+	};
+	let proof = sad.create_proof(&groth_params, src_mb, dst_mb, bound);
+
+	let mut proof_f = File::create(&proof_path).expect("faild to create proof file");
+	proof.write(&mut proof_f).expect("failed to serialize proof file");
+
+	let mut witness_f = File::create(witness_path).expect("failed to create witness file");
+	let witness_encoded = json::encode(&witns).unwrap();
+	witness_f.write_all(witness_encoded.as_bytes()).expect("failed to write witness file");
+
+	println!("sad={:?} bound={:?}", witns.sad, witns.bound);
+	println!("Load CRS + Proof generation {}", now.elapsed().as_millis());
+}
+
+/// The bound is the verifier's, never read from the prover's witness file
+fn sadverify(crs_path: String, proof_path: String, input1_path: String, input2_path: String, bound: u64)
+{
+	let now = Instant::now();
+
+	let meta = load_sad_crs_meta(&crs_path);
+	let mut sad = mb_sad::SadApp::new((meta.window * meta.window) as usize, meta.bit_depth);
+	let groth_params: Parameters<Bls12> = {
+		let f = File::open(&crs_path).expect("failed to open sad crs file");
+		Parameters::read(&f, false).expect("failed to read sad crs file")
+	};
+
+	let mut f = File::open(&proof_path).expect("faild to open proof file");
+	let proof: Proof<Bls12> = Proof::read(&mut f).expect("failed to read proof file");
+
+	let pvk = prepare_verifying_key(&groth_params.vk);
+	let src_mb: Vec<u32> = get_input_mb(input1_path);
+	let dst_mb: Vec<u32> = get_input_mb(input2_path);
+	check_bit_depth(&src_mb, meta.bit_depth);
+	check_bit_depth(&dst_mb, meta.bit_depth);
+	let res = sad.verify_proof(&pvk, &proof, src_mb, dst_mb, bound).unwrap_or_else(|| {
+		println!("input blocks exceed the {}-bit range of the crs", meta.bit_depth);
+		process::exit(1);
+	});
+	println!("Verificaiton result = {:?}", res);
+	println!("Load Proof+Verification {}", now.elapsed().as_millis());
+}
+
+/// MS-SSIM metadata has its own file, as MSE metadata does
+fn msssim_crs_meta_path(crs_path: &str) -> String {
+	format!("{}.msssim.meta", crs_path)
+}
+
+fn msssimsetup(crs_path: String, window_size: u32, bit_depth: u32)
+{
+	let now = Instant::now();
+
+	let mut msssim = mb_msssim::MsSsimApp::new(window_size, bit_depth);
+	let p = msssim.setup();
+	let mut f = File::create(&crs_path).expect("faild to open msssim crs file");
+	p.write(&mut f).expect("failed to write params to msssim crs file");
+	let mut meta_f = File::create(msssim_crs_meta_path(&crs_path)).expect("faild to create crs meta file");
+	let meta_encoded = json::encode(&mb_msssim::MsSsimCrsMeta { window: window_size, bit_depth }).unwrap();
+	meta_f.write_all(meta_encoded.as_bytes()).expect("failed to write crs meta file");
+	println!("Setup {}", now.elapsed().as_millis());
+}
+
+fn load_msssim_crs_meta(crs_path: &str) -> mb_msssim::MsSsimCrsMeta
+{
+	let mut file = File::open(msssim_crs_meta_path(crs_path)).expect("faild to open crs meta file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("faild to read crs meta file");
+	let meta: mb_msssim::MsSsimCrsMeta = json::decode(&data).expect("faild to parse crs meta file");
+	parse_bit_depth(&meta.bit_depth.to_string(), &mb_msssim::MSSSIM_BIT_DEPTHS);
+	meta
+}
+
+fn msssimgenproof(
+	crs_path: String,
+	proof_path: String,
+	input1_path: String,
+	input2_path: String,
+	witness_path: String,)
+{
+	let now = Instant::now();
+
+	let meta = load_msssim_crs_meta(&crs_path);
+	let mb_size = (meta.window * meta.window) as usize;
+	let src_mb: Vec<u32> = get_input_mb(input1_path);
+	let dst_mb: Vec<u32> = get_input_mb(input2_path);
+	if src_mb.len() != mb_size || dst_mb.len() != mb_size {
+		println!("input blocks have {} and {} pixels, crs expects {}x{} blocks ({} pixels)",
+			src_mb.len(), dst_mb.len(), meta.window, meta.window, mb_size);
+		process::exit(1);
+	}
+
+	check_bit_depth(&src_mb, meta.bit_depth);
+	check_bit_depth(&dst_mb, meta.bit_depth);
+
+	let mut msssim = mb_msssim::MsSsimApp::new(meta.window, meta.bit_depth);
+	let groth_params: Parameters<Bls12> = {
+		let f = File::open(&crs_path).expect("failed to open msssim crs file");
+		Parameters::read(&f, false).expect("failed to read msssim crs file")
+	};
+	let witns = mb_msssim::gen_witness(&src_mb, &dst_mb, meta.window, meta.bit_depth);
+	let proof = msssim.create_proof(&groth_params, src_mb, dst_mb, witns.clone());
+
+	let mut proof_f = File::create(&proof_path).expect("faild to create proof file");
+	proof.write(&mut proof_f).expect("failed to serialize proof file");
+
+	let mut witness_f = File::create(witness_path).expect("failed to create witness file");
+	let witness_encoded = json::encode(&witns).unwrap();
+	witness_f.write_all(witness_encoded.as_bytes()).expect("failed to write witness file");
+
+	println!("msssim={:.4}", mb_msssim::msssim_value(&witns));
+	println!("Load CRS + Proof generation {}", now.elapsed().as_millis());
+}
+
+/// The public sum and sigma of the destination block come from the verifier's copy of the block,
+/// and the proven MS-SSIM is checked against the verifier's threshold
+fn msssimverify(crs_path: String, proof_path: String, input2_path: String, witness_path: String, threshold: f64)
+{
+	let now = Instant::now();
+
+	let meta = load_msssim_crs_meta(&crs_path);
+	let mut msssim = mb_msssim::MsSsimApp::new(meta.window, meta.bit_depth);
+	let groth_params: Parameters<Bls12> = {
+		let f = File::open(&crs_path).expect("failed to open msssim crs file");
+		Parameters::read(&f, false).expect("failed to read msssim crs file")
+	};
+
+	let mut f = File::open(&proof_path).expect("faild to open proof file");
+	let proof: Proof<Bls12> = Proof::read(&mut f).expect("failed to read proof file");
+
+	let mut file = File::open(witness_path).expect("verify: faild to open witness file");
+	let mut data = String::new();
+	file.read_to_string(&mut data).expect("verify: faild to read witness file");
+	let witness: mb_msssim::MsSsimWitness = json::decode(&data).unwrap();
+	if witness.size != meta.window || witness.scales.len() != mb_msssim::MSSSIM_SCALES {
+		println!("witness does not match the crs");
+		process::exit(1);
+	}
+	let dst_mb: Vec<u32> = get_input_mb(input2_path);
+	if dst_mb.len() != (meta.window * meta.window) as usize {
+		println!("input block has {} pixels, crs expects {}x{} blocks", dst_mb.len(), meta.window, meta.window);
+		process::exit(1);
+	}
+	check_bit_depth(&dst_mb, meta.bit_depth);
+
+	let pvk = prepare_verifying_key(&groth_params.vk);
+	let (numerator, denom) = mb_msssim::msssim_fraction(&witness);
+	let res = msssim.verify_proof(&pvk, &proof, &dst_mb, (numerator, denom)).unwrap();
+	let value = mb_msssim::fraction_value(&numerator, &denom);
+	if res && value < threshold {
+		println!("proven msssim {:.4} is below the threshold {:.4}", value, threshold);
+	}
+	println!("Verificaiton result = {:?} msssim={:.4}", res && value >= threshold, value);
+	println!("Load Proof+Verification {}", now.elapsed().as_millis());
+}
+
+fn gensample(mb_size: u32, bit_depth: u32, sample1_file: String, sample2_file: String) 
 {
 	let mut rng = rand::thread_rng();
+	let mut plane = |n: u32| -> Vec<u16> { (0..n).map(|x| rng.gen_range(0, 1 << bit_depth) as u16).collect() };
 	
 	let sample_mb1 = SampleMb{
-						pixels: (0..mb_size).map(|x| (rng.gen::<u8>()) as u16).collect()
+						pixels: plane(mb_size),
+						u: Some(plane(mb_size / 4)),
+						v: Some(plane(mb_size / 4)),
 					 };
 	let sample_mb2 = SampleMb{
-						pixels: (0..mb_size).map(|x| (rng.gen::<u8>()) as u16).collect()
+						pixels: plane(mb_size),
+						u: Some(plane(mb_size / 4)),
+						v: Some(plane(mb_size / 4)),
 					 };
 	
 	let sample1_encoded = json::encode(&sample_mb1).unwrap();
@@ -319,6 +1090,247 @@ fn gensample(mb_size: u32, sample1_file: String, sample2_file: String)
 	input2_f.write_all(sample2_encoded.as_bytes());	
 }
 
+/// Native quality report of a frame pair, without proofs. Lists reference and circuit values
+/// of every block and the blocks below `min_ssim` that are worth challenging with a SNARK.
+fn quality(src_path: String, dst_path: String, window_size: u32, gaussian: bool, bit_depth: u32, min_ssim: f64)
+{
+	let src_blocks = get_input_mbs(src_path);
+	let dst_blocks = get_input_mbs(dst_path);
+	if src_blocks.len() != dst_blocks.len() || src_blocks.is_empty() {
+		println!("frames have {} and {} blocks", src_blocks.len(), dst_blocks.len());
+		process::exit(1);
+	}
+	let window = mb_ssim::SsimWindow::new(window_size, gaussian);
+	for mb in dst_blocks.iter() {
+		if mb.len() != window.mb_size() {
+			println!("block of {} pixels does not match window size {}", mb.len(), window_size);
+			process::exit(1);
+		}
+		check_bit_depth(mb, bit_depth);
+	}
+	// renditions of a lower resolution carry larger source blocks
+	let src_side = (src_blocks[0].len() as f64).sqrt() as u32;
+	let ratio = match resample::ResampleRatio::parse(&format!("{}:{}", src_side, window_size)) {
+		Some(ratio) if src_side * src_side == src_blocks[0].len() as u32 && ratio.src_side(window_size) == src_side => ratio,
+		_ => {
+			println!("source block of {} pixels does not resample onto window size {}", src_blocks[0].len(), window_size);
+			process::exit(1);
+		}
+	};
+	for mb in src_blocks.iter() {
+		check_bit_depth(mb, bit_depth);
+	}
+
+	let frame = quality::FrameQuality::new(&src_blocks, &dst_blocks, &window, &ratio, bit_depth);
+	println!("block\tssim\tssim_circuit\tmse\tmse_circuit\tpsnr\tpsnr_circuit");
+	for (i, b) in frame.blocks.iter().enumerate() {
+		println!("{}\t{:.6}\t{:.6}\t{:.3}\t{}\t{:.3}\t{:.3}", i, b.ssim, b.ssim_circuit, b.mse, b.mse_circuit, b.psnr, b.psnr_circuit);
+	}
+	println!("mean ssim {:.6} mean psnr {:.3}", frame.mean_ssim(), frame.mean_psnr());
+	println!("max discrepancy ssim {:.6} psnr {:.3}", frame.max_ssim_discrepancy(), frame.max_psnr_discrepancy());
+	println!("suspicious blocks (ssim < {}): {:?}", min_ssim, frame.suspicious(min_ssim));
+}
+
+/// Synthesizes the circuit with its witness and reports the first constraint it does not satisfy,
+/// with the values of the variables involved. Returns whether every constraint holds.
+fn report_unsatisfied<C: Circuit<Bls12>>(name: &str, circuit: C) -> bool
+{
+	let mut cs = macroblock::TestConstraintSystem::<Bls12>::new();
+	if let Err(err) = circuit.synthesize(&mut cs) {
+		println!("{} circuit failed to synthesize: {:?}", name, err);
+		return false;
+	}
+	println!("{} circuit: {} constraints, {} inputs, {} aux", name, cs.num_constraints(), cs.num_inputs() - 1, cs.num_aux());
+	match cs.which_is_unsatisfied() {
+		Some(path) => {
+			println!("first unsatisfied constraint {}", cs.describe_constraint(path).unwrap());
+			false
+		},
+		None => {
+			println!("all constraints satisfied");
+			true
+		}
+	}
+}
+
+/// Fields of two witnesses that differ, with the value in each
+fn witness_diff(saved: &mb_ssim::Witness, generated: &mb_ssim::Witness) -> Vec<(String, Json, Json)>
+{
+	let fields = |witns: &mb_ssim::Witness| match Json::from_str(&json::encode(witns).unwrap()) {
+		Ok(Json::Object(fields)) => fields,
+		_ => unreachable!("witness encodes to a json object"),
+	};
+	let generated = fields(generated);
+	fields(saved).into_iter()
+		.filter(|(name, value)| generated.get(name) != Some(value))
+		.map(|(name, value)| {
+			let generated_value = generated.get(&name).cloned().unwrap_or(Json::Null);
+			(name, value, generated_value)
+		})
+		.collect()
+}
+
+fn print_witness_diff(plane: &str, saved: &mb_ssim::Witness, generated: &mb_ssim::Witness)
+{
+	let diff = witness_diff(saved, generated);
+	if diff.is_empty() {
+		println!("{} witness matches gen_witness", plane);
+	}
+	for (field, saved_value, generated_value) in diff {
+		println!("{} witness {}: saved {} gen_witness {}", plane, field, saved_value, generated_value);
+	}
+}
+
+/// Checks the SSIM circuit of a challenge against its witness, and the saved witness against the
+/// one generated from the blocks
+fn debug_witness_ssim(crs_path: String, input1_path: String, input2_path: String, position: challenge::MbPosition, witness_path: Option<String>)
+{
+	let meta = load_ssim_crs_meta(&crs_path);
+	let src_samples = get_input_samples(input1_path);
+	let dst_sample = get_input_samples(input2_path)[0].clone();
+	let SsimProverInput { ssim, src_mb, dst_mb, witns, src_auth_path, src_root, chroma } = ssim_prover_input(&meta, &src_samples, dst_sample, position);
+	if let Some(witness_path) = witness_path {
+		print_witness_diff("y", &load_witness(&witness_path), &witns);
+		if let Some((_, chroma_witns)) = chroma.as_ref() {
+			let saved = load_chroma_witness(&witness_path);
+			print_witness_diff("u", &saved.u, &chroma_witns.u);
+			print_witness_diff("v", &saved.v, &chroma_witns.v);
+		}
+	}
+	let circuit = ssim.circuit(src_mb, dst_mb, witns, src_auth_path, src_root, position, chroma);
+	if !report_unsatisfied("ssim", circuit) {
+		process::exit(1);
+	}
+}
+
+/// Witness saved by zkporgenproof, as the path, leaf and root the circuit takes
+fn load_por_witness(witness_path: String) -> (Vec<Option<(Fr, bool)>>, Fr, Fr)
+{
+	let (root, leaf, saved_path) = load_merkle_proof(witness_path);
+	let auth_path = saved_path.into_iter().map(|(node, is_right)| Some((Fr::from(node), is_right))).collect();
+	(auth_path, Fr::from(leaf), Fr::from(root))
+}
+
+fn por_circuit(auth_path: Vec<Option<(Fr, bool)>>, leaf: Fr, root: Fr) -> merkle_pot::ProofOfRetrievability<'static, Bls12>
+{
+	merkle_pot::ProofOfRetrievability::<Bls12> {
+		params: &JUBJUB_BLS_PARAMS,
+		value: Some(leaf),
+		auth_path,
+		root: Some(root),
+	}
+}
+
+/// Checks the proof of retrievability circuit against a saved witness, and the saved witness
+/// against the path computed from the phash input
+fn debug_witness_por(witness_path: String, input_path: Option<String>)
+{
+	let (auth_path, leaf, root) = load_por_witness(witness_path);
+	if let Some(input_path) = input_path {
+		let (hashes, size) = get_input_phash(input_path);
+		let (native_path, native_leaf, native_root) = merkle_pot::merkel_path(&hashes, size);
+		if native_leaf != leaf {
+			println!("witness leaf: saved {} native {}", leaf, native_leaf);
+		}
+		if native_root != root {
+			println!("witness root: saved {} native {}", root, native_root);
+		}
+		for (i, (saved, native)) in auth_path.iter().zip(native_path.iter()).enumerate() {
+			if saved != native {
+				println!("witness path element {}: saved {:?} native {:?}", i, saved, native);
+			}
+		}
+		if native_path.len() != auth_path.len() {
+			println!("witness path: saved {} levels native {}", auth_path.len(), native_path.len());
+		}
+	}
+	if !report_unsatisfied("por", por_circuit(auth_path, leaf, root)) {
+		process::exit(1);
+	}
+}
+
+/// Flags of the commands taking a circuit by name
+struct CircuitArgs {
+	circuit: Option<String>,
+	tree_depth: Option<u32>,
+	window_size: u32,
+	level: usize,
+	as_json: bool,
+	out: Option<String>,
+}
+
+/// Parses --circuit, --depth, --mb-size, --level, --json and --out, exits on invalid flags
+fn parse_circuit_args(args: &[String], usage: &str) -> CircuitArgs
+{
+	let mut parsed = CircuitArgs {
+		circuit: None,
+		tree_depth: None,
+		window_size: 16,
+		level: circuit_stats::DEFAULT_STATS_LEVEL,
+		as_json: false,
+		out: None,
+	};
+	let mut i = 0;
+	while i < args.len() {
+		let value = args.get(i + 1);
+		match (args[i].as_ref(), value) {
+			("--json", _) => { parsed.as_json = true; i += 1; continue; },
+			("--circuit", Some(value)) if circuit_stats::STATS_CIRCUITS.contains(&value.as_ref()) => parsed.circuit = Some(value.clone()),
+			("--depth", Some(value)) if value.parse::<u32>().is_ok() => parsed.tree_depth = value.parse::<u32>().ok(),
+			("--mb-size", Some(value)) if mb_ssim::SSIM_WINDOW_SIZES.contains(&value.parse::<u32>().unwrap_or(0)) => parsed.window_size = value.parse::<u32>().unwrap(),
+			("--level", Some(value)) if value.parse::<usize>().is_ok() => parsed.level = value.parse::<usize>().unwrap(),
+			("--out", Some(value)) => parsed.out = Some(value.clone()),
+			_ => {
+				println!("invalid argument {}{}", args[i], value.map(|v| format!(" {}", v)).unwrap_or_default());
+				println!("{}", usage);
+				process::exit(1);
+			}
+		}
+		i += 2;
+	}
+	parsed
+}
+
+/// Writes the R1CS of the circuit to prefix.r1cs and prefix.json, and its witness to prefix.wtns
+fn export_r1cs<C: Circuit<Bls12>>(circuit: C, with_witness: bool, out_prefix: &str)
+{
+	let mut cs = if with_witness {
+		macroblock::TestConstraintSystem::<Bls12>::new()
+	} else {
+		macroblock::TestConstraintSystem::<Bls12>::new_shape()
+	};
+	circuit.synthesize(&mut cs).expect("failed to synthesize circuit");
+	if let Some(path) = cs.which_is_unsatisfied().filter(|_| with_witness) {
+		println!("witness does not satisfy {}, see debug-witness", path);
+	}
+	let r1cs = r1cs::R1cs::from_cs(&cs, with_witness);
+
+	let r1cs_f = File::create(format!("{}.r1cs", out_prefix)).expect("failed to create r1cs file");
+	r1cs.write_r1cs(std::io::BufWriter::new(r1cs_f)).expect("failed to write r1cs file");
+	if with_witness {
+		let wtns_f = File::create(format!("{}.wtns", out_prefix)).expect("failed to create wtns file");
+		r1cs.write_wtns(std::io::BufWriter::new(wtns_f)).expect("failed to write wtns file");
+	}
+	let mut json_f = File::create(format!("{}.json", out_prefix)).expect("failed to create r1cs json file");
+	json_f.write_all(json::encode(&r1cs.to_json()).unwrap().as_bytes()).expect("failed to write r1cs json file");
+	println!("{} constraints, {} wires, {} public inputs", r1cs.constraints.len(), r1cs.num_wires(), r1cs.num_inputs - 1);
+}
+
+/// Prints the cost of a circuit, as a table or as JSON
+fn circuit_stats(circuit: String, tree_depth: Option<u32>, window_size: u32, level: usize, as_json: bool)
+{
+	let stats = match circuit.as_ref() {
+		"por" => circuit_stats::por_stats(tree_depth.unwrap_or(9) as usize, level),
+		"ssim" => circuit_stats::ssim_stats(window_size, tree_depth.unwrap_or(source_commit::DEFAULT_SOURCE_TREE_DEPTH), level),
+		_ => circuit_stats::mse_stats(window_size, level),
+	};
+	if as_json {
+		println!("{}", json::encode(&stats).unwrap());
+	} else {
+		print!("{}", stats.to_table());
+	}
+}
+
 lazy_static! {
     static ref JUBJUB_BLS_PARAMS: JubjubBls12 = JubjubBls12::new();
 }
@@ -335,16 +1347,17 @@ fn zkporsetup(crs_path: String)
 	println!("Setup {}", now.elapsed().as_millis());
 }
 
-fn get_input_phash(input_file: String) -> Vec<u64>
+/// Bytes of every phash of a phashes file, of any hash size, along with the size
+fn get_input_phash(input_file: String) -> (Vec<Vec<u8>>, HashSize)
 {
 	let mut file = File::open(input_file).expect("verify: faild to open phashes file");
 	let mut data = String::new();
 	file.read_to_string(&mut data).expect("verify: faild to read phashes file");
 
 	let phashes_in:  PHashes = json::decode(&data).unwrap();
-	let phashes =  phashes_in.phashes.iter().map(|x| *x as u64).collect();
+	let phashes = phashes_in.hash_bytes();
 	//println!("phashes={:?}", phashes);
-	phashes
+	(phashes, phashes_in.hash_size())
 }
 
 #[derive(Serialize, Deserialize)]
@@ -362,8 +1375,8 @@ fn zkporchallenge(
 	let mut por = merkle_pot::MerklePorApp::default();
 	
 	// data
-	let data: Vec<u64> = get_input_phash(input_path);
-	let (auth_path, leaf, root) = merkle_pot::merkel_path(data);
+	let (data, size) = get_input_phash(input_path);
+	let (auth_path, leaf, root) = merkle_pot::merkel_path(&data, size);
 	
 	let mut challenge = VcMerkleChallenge{
 		leaf: PedersenDomain(FrRepr::from(leaf)),
@@ -397,8 +1410,8 @@ fn zkporgenproof(
 	};
 	
 	// data
-	let data: Vec<u64> = get_input_phash(input_path);
-	let (auth_path, leaf, root) = merkle_pot::merkel_path(data);
+	let (data, size) = get_input_phash(input_path);
+	let (auth_path, leaf, root) = merkle_pot::merkel_path(&data, size);
 		
 	let proof_start = Instant::now();	
 	
@@ -480,36 +1493,317 @@ fn main()
 			println!("ssimsetup");
 			if args.len() >= 3 {
     			let crs_file = args[2].clone();
-				setup(crs_file)
+				let mut window_size = 16;
+				if args.len() >= 4 {
+					window_size = args[3].parse::<u32>().unwrap();
+				}
+				if !mb_ssim::SSIM_WINDOW_SIZES.contains(&window_size) {
+					println!("unsupported window size {}, expected one of {:?}", window_size, mb_ssim::SSIM_WINDOW_SIZES);
+					process::exit(1);
+				}
+				let gaussian = args.len() >= 5 && args[4] == "gaussian";
+				let mut tree_depth = source_commit::DEFAULT_SOURCE_TREE_DEPTH;
+				if args.len() >= 6 {
+					tree_depth = args[5].parse::<u32>().unwrap();
+				}
+				let mut ratio = resample::ResampleRatio::identity();
+				if args.len() >= 7 {
+					ratio = match resample::ResampleRatio::parse(&args[6]) {
+						Some(ratio) if resample::LADDER_RATIOS.contains(&ratio) => ratio,
+						_ => {
+							println!("unsupported ratio {}, expected 1080:720, 720:480 or 1080:540", args[6]);
+							process::exit(1);
+						}
+					};
+				}
+				let mut chroma = None;
+				if args.len() >= 8 {
+					chroma = match mb_ssim::YuvWeights::parse(&args[7]) {
+						Some(weights) => Some(weights),
+						None if args[7] == "yuv" => Some(mb_ssim::YuvWeights::default()),
+						None if args[7] == "luma" => None,
+						None => {
+							println!("invalid yuv weights {}, expected luma, yuv or wy:wu:wv", args[7]);
+							process::exit(1);
+						}
+					};
+				}
+				// chroma blocks are half the window and resampled with the same ratio
+				let step = if chroma.is_some() { 2 * ratio.dst } else { ratio.dst };
+				if window_size % step != 0 {
+					println!("window size {} is not a multiple of {} for ratio {}:{}", window_size, step, ratio.src, ratio.dst);
+					process::exit(1);
+				}
+				let mut bit_depth = pixel::DEFAULT_BIT_DEPTH;
+				if args.len() >= 9 {
+					bit_depth = parse_bit_depth(&args[8], &pixel::SUPPORTED_BIT_DEPTHS);
+				}
+				let mut public_inputs = mb_ssim::PublicInputSchema::default();
+				if args.len() >= 10 {
+					public_inputs = match mb_ssim::PublicInputSchema::parse(&args[9]) {
+						Some(schema) => schema,
+						None => {
+							let names: Vec<&str> = mb_ssim::SSIM_STATS.iter().map(|stat| stat.name()).collect();
+							println!("invalid public inputs {}, expected distinct comma separated names of {:?}", args[9], names);
+							process::exit(1);
+						}
+					};
+				}
+				setup(crs_file, window_size, gaussian, tree_depth, ratio, chroma, bit_depth, public_inputs)
 			} else {
-				println!("zkptrans setup crs_file");
+				println!("zkptrans ssimsetup crs_file [window_size] [gaussian|box] [tree_depth] [src_height:dst_height] [luma|yuv|wy:wu:wv] [bit_depth] [public_inputs]");
 				process::exit(1);
 			}			
 		},
+		"ssimcommit" => {
+			println!("ssimcommit");
+			if args.len() >= 6 {
+    			let crs_file = args[2].clone();
+				let src_file = args[3].clone();
+				let commitment_file = args[4].clone();
+				let frame = args[5].parse::<u32>().unwrap();
+				ssimcommit(crs_file, src_file, commitment_file, frame)
+			} else {
+				println!("zkptrans ssimcommit crs_file src_frame_file commitment_file frame");
+				process::exit(1);
+			}
+		},
+		"ssimchallenge" => {
+			println!("ssimchallenge");
+			if args.len() >= 7 {
+				let challenge_file = args[2].clone();
+				let seed = parse_seed(&args[3]);
+				let mut dims = challenge::StreamDims {
+					num_frames: args[4].parse::<u32>().unwrap(),
+					width: args[5].parse::<u32>().unwrap(),
+					height: args[6].parse::<u32>().unwrap(),
+					mb_side: 16,
+				};
+				if args.len() >= 8 {
+					dims.mb_side = args[7].parse::<u32>().unwrap();
+				}
+				let mut count = 1;
+				if args.len() >= 9 {
+					count = args[8].parse::<usize>().unwrap();
+				}
+				check_stream_dims(&dims);
+				ssimchallenge(challenge_file, seed, dims, count)
+			} else {
+				println!("zkptrans ssimchallenge challenge_file seed num_frames width height [window_size] [count]");
+				process::exit(1);
+			}
+		},
 		"ssimgenproof" => {
 			println!("ssimgenproof");
-			if args.len() >= 7 {
+			if args.len() >= 8 {
     			let crs_file = args[2].clone();
 				let proof_file = args[3].clone();
 				let input1_file = args[4].clone();
 				let input2_file = args[5].clone();
 				let witness_file = args[6].clone();
-				genproof(crs_file, proof_file, input1_file, input2_file, witness_file)
+				let challenge_file = args[7].clone();
+				let mut challenge_index = 0;
+				if args.len() >= 9 {
+					challenge_index = args[8].parse::<usize>().unwrap();
+				}
+				genproof(crs_file, proof_file, input1_file, input2_file, witness_file, load_challenge(challenge_file, challenge_index))
 			} else {
-				println!("zkptrans genproof crs_file proof_file input1_file input2_file witness_file");
+				println!("zkptrans ssimgenproof crs_file proof_file src_frame_file input2_file witness_file challenge_file [challenge_index]");
 				process::exit(1);
 			}
 
 		},
+		"ssimgenproof-frames" => {
+			println!("ssimgenproof-frames");
+			if args.len() >= 12 {
+				let crs_file = args[2].clone();
+				let proof_file = args[3].clone();
+				let witness_file = args[4].clone();
+				let commitment_file = args[5].clone();
+				let challenge_file = args[6].clone();
+				let src_video = args[7].clone();
+				let dst_video = args[8].clone();
+				let frame = args[9].parse::<u32>().unwrap();
+				let mb_x = args[10].parse::<u32>().unwrap();
+				let mb_y = args[11].parse::<u32>().unwrap();
+				let mut raw_formats = None;
+				if args.len() >= 14 {
+					let bit_depth = load_ssim_crs_meta(&crs_file).bit_depth;
+					raw_formats = Some((parse_yuv_format(&args[12], bit_depth), parse_yuv_format(&args[13], bit_depth)));
+				}
+				genproof_frames(crs_file, proof_file, witness_file, commitment_file, challenge_file,
+					src_video, dst_video, frame, mb_x, mb_y, raw_formats)
+			} else {
+				println!("zkptrans ssimgenproof-frames crs_file proof_file witness_file commitment_file challenge_file src_video dst_video frame mb_x mb_y [src_widthxheight dst_widthxheight]");
+				process::exit(1);
+			}
+		},
 		"ssimverify" => {
 			println!("ssimverify");
-			if args.len() >= 5 {
+			if args.len() >= 10 {
     			let crs_file = args[2].clone();
 				let proof_file = args[3].clone();
 				let witness_file = args[4].clone();
-				verify(crs_file, proof_file, witness_file)
+				let commitment_file = args[5].clone();
+				let seed = parse_seed(&args[6]);
+				// the window comes from the crs meta
+				let dims = challenge::StreamDims {
+					num_frames: args[7].parse::<u32>().unwrap(),
+					width: args[8].parse::<u32>().unwrap(),
+					height: args[9].parse::<u32>().unwrap(),
+					mb_side: 0,
+				};
+				let mut challenge_index = 0;
+				if args.len() >= 11 {
+					challenge_index = args[10].parse::<usize>().unwrap();
+				}
+				verify(crs_file, proof_file, witness_file, commitment_file, seed, dims, challenge_index)
+			} else {
+				println!("zkptrans ssimverify crs_file proof_file witness_file commitment_file seed num_frames width height [challenge_index]");
+				process::exit(1);
+			}
+		},
+		"msesetup" => {
+			println!("msesetup");
+			if args.len() >= 3 {
+				let crs_file = args[2].clone();
+				let mut window_size = 16;
+				if args.len() >= 4 {
+					window_size = args[3].parse::<u32>().unwrap();
+				}
+				let mut bit_depth = pixel::DEFAULT_BIT_DEPTH;
+				if args.len() >= 5 {
+					bit_depth = parse_bit_depth(&args[4], &pixel::SUPPORTED_BIT_DEPTHS);
+				}
+				msesetup(crs_file, window_size, bit_depth)
+			} else {
+				println!("zkptrans msesetup crs_file [window_size] [bit_depth]");
+				process::exit(1);
+			}
+		},
+		"msegenproof" => {
+			println!("msegenproof");
+			if args.len() >= 8 {
+				let crs_file = args[2].clone();
+				let proof_file = args[3].clone();
+				let input1_file = args[4].clone();
+				let input2_file = args[5].clone();
+				let witness_file = args[6].clone();
+				let threshold = parse_mse_threshold(&args, 7, &crs_file);
+				msegenproof(crs_file, proof_file, input1_file, input2_file, witness_file, threshold)
 			} else {
-				println!("zkptrans verify crs_file proof_file witness_file");
+				println!("zkptrans msegenproof crs_file proof_file input1_file input2_file witness_file threshold [psnr]");
+				process::exit(1);
+			}
+		},
+		"mseverify" => {
+			println!("mseverify");
+			if args.len() >= 7 {
+				let crs_file = args[2].clone();
+				let proof_file = args[3].clone();
+				let input1_file = args[4].clone();
+				let input2_file = args[5].clone();
+				let threshold = parse_mse_threshold(&args, 6, &crs_file);
+				mseverify(crs_file, proof_file, input1_file, input2_file, threshold)
+			} else {
+				println!("zkptrans mseverify crs_file proof_file input1_file input2_file threshold [psnr]");
+				process::exit(1);
+			}
+		},
+		"sadsetup" => {
+			println!("sadsetup");
+			if args.len() >= 3 {
+				let crs_file = args[2].clone();
+				let mut window_size = 16;
+				if args.len() >= 4 {
+					window_size = args[3].parse::<u32>().unwrap();
+				}
+				let mut bit_depth = pixel::DEFAULT_BIT_DEPTH;
+				if args.len() >= 5 {
+					bit_depth = parse_bit_depth(&args[4], &pixel::SUPPORTED_BIT_DEPTHS);
+				}
+				sadsetup(crs_file, window_size, bit_depth)
+			} else {
+				println!("zkptrans sadsetup crs_file [window_size] [bit_depth]");
+				process::exit(1);
+			}
+		},
+		"sadgenproof" => {
+			println!("sadgenproof");
+			if args.len() >= 8 {
+				let crs_file = args[2].clone();
+				let proof_file = args[3].clone();
+				let input1_file = args[4].clone();
+				let input2_file = args[5].clone();
+				let witness_file = args[6].clone();
+				let bound = args[7].parse::<u64>().unwrap();
+				sadgenproof(crs_file, proof_file, input1_file, input2_file, witness_file, bound)
+			} else {
+				println!("zkptrans sadgenproof crs_file proof_file input1_file input2_file witness_file bound");
+				process::exit(1);
+			}
+		},
+		"sadverify" => {
+			println!("sadverify");
+			if args.len() >= 7 {
+				let crs_file = args[2].clone();
+				let proof_file = args[3].clone();
+				let input1_file = args[4].clone();
+				let input2_file = args[5].clone();
+				let bound = args[6].parse::<u64>().unwrap();
+				sadverify(crs_file, proof_file, input1_file, input2_file, bound)
+			} else {
+				println!("zkptrans sadverify crs_file proof_file input1_file input2_file bound");
+				process::exit(1);
+			}
+		},
+		"msssimsetup" => {
+			println!("msssimsetup");
+			if args.len() >= 3 {
+				let crs_file = args[2].clone();
+				let mut window_size = 16;
+				if args.len() >= 4 {
+					window_size = args[3].parse::<u32>().unwrap();
+				}
+				if !mb_msssim::MSSSIM_WINDOW_SIZES.contains(&window_size) {
+					println!("unsupported window size {}, expected one of {:?}", window_size, mb_msssim::MSSSIM_WINDOW_SIZES);
+					process::exit(1);
+				}
+				let mut bit_depth = pixel::DEFAULT_BIT_DEPTH;
+				if args.len() >= 5 {
+					bit_depth = parse_bit_depth(&args[4], &mb_msssim::MSSSIM_BIT_DEPTHS);
+				}
+				msssimsetup(crs_file, window_size, bit_depth)
+			} else {
+				println!("zkptrans msssimsetup crs_file [window_size] [bit_depth]");
+				process::exit(1);
+			}
+		},
+		"msssimgenproof" => {
+			println!("msssimgenproof");
+			if args.len() >= 7 {
+				let crs_file = args[2].clone();
+				let proof_file = args[3].clone();
+				let input1_file = args[4].clone();
+				let input2_file = args[5].clone();
+				let witness_file = args[6].clone();
+				msssimgenproof(crs_file, proof_file, input1_file, input2_file, witness_file)
+			} else {
+				println!("zkptrans msssimgenproof crs_file proof_file input1_file input2_file witness_file");
+				process::exit(1);
+			}
+		},
+		"msssimverify" => {
+			println!("msssimverify");
+			if args.len() >= 7 {
+				let crs_file = args[2].clone();
+				let proof_file = args[3].clone();
+				let input2_file = args[4].clone();
+				let witness_file = args[5].clone();
+				let threshold = args[6].parse::<f64>().unwrap();
+				msssimverify(crs_file, proof_file, input2_file, witness_file, threshold)
+			} else {
+				println!("zkptrans msssimverify crs_file proof_file input2_file witness_file threshold");
 				process::exit(1);
 			}
 		},
@@ -564,25 +1858,64 @@ fn main()
 			if args.len() >= 4 {
     			let input1 = args[2].clone();
 				let input2 = args[3].clone();
-				gensample(256, input1, input2)
+				let mut window_size = 16;
+				if args.len() >= 5 {
+					window_size = args[4].parse::<u32>().unwrap();
+				}
+				let mut bit_depth = pixel::DEFAULT_BIT_DEPTH;
+				if args.len() >= 6 {
+					bit_depth = parse_bit_depth(&args[5], &pixel::SUPPORTED_BIT_DEPTHS);
+				}
+				gensample(window_size * window_size, bit_depth, input1, input2)
+			} else {
+				println!("zkptrans gensample input1_file input2_file [window_size] [bit_depth]");
+				process::exit(1);
+			}
+		},
+		"quality" => {
+			println!("quality");
+			if args.len() >= 4 {
+				let src_file = args[2].clone();
+				let dst_file = args[3].clone();
+				let mut window_size = 16;
+				if args.len() >= 5 {
+					window_size = args[4].parse::<u32>().unwrap();
+				}
+				if !mb_ssim::SSIM_WINDOW_SIZES.contains(&window_size) {
+					println!("unsupported window size {}, expected one of {:?}", window_size, mb_ssim::SSIM_WINDOW_SIZES);
+					process::exit(1);
+				}
+				let gaussian = args.len() >= 6 && args[5] == "gaussian";
+				let mut bit_depth = pixel::DEFAULT_BIT_DEPTH;
+				if args.len() >= 7 {
+					bit_depth = parse_bit_depth(&args[6], &pixel::SUPPORTED_BIT_DEPTHS);
+				}
+				let mut min_ssim = 0.9;
+				if args.len() >= 8 {
+					min_ssim = args[7].parse::<f64>().unwrap();
+				}
+				quality(src_file, dst_file, window_size, gaussian, bit_depth, min_ssim)
 			} else {
-				println!("zkptrans gensample input1_file input2_file");
+				println!("zkptrans quality src_frame_file dst_frame_file [window_size] [gaussian|box] [bit_depth] [min_ssim]");
 				process::exit(1);
 			}
 		},
 		"porpedercommit" => {
 			println!("porpedercommit");
 			if args.len() >= 3 {
-				let mut data:Vec<u64> = Vec::new();
+				let mut data:Vec<Vec<u8>> = Vec::new();
+				let mut size = HashSize::default();
 				let input_file = args[2].clone();
 				if(input_file == "random") {
-					for i in 0..512 {
-						data.push(i);
+					for i in 0..512u64 {
+						data.push(i.to_le_bytes().to_vec());
 					}
 				} else {
-					data = get_input_phash(input_file);
+					let (hashes, hash_size) = get_input_phash(input_file);
+					data = hashes;
+					size = hash_size;
 				}
-				let root = merkel_path_commit(data);
+				let root = merkel_path_commit(data, size);
 				//save_merkle_proof(proof_file, root, leaf, auth_path);
 			} else {
 				println!("zkptrans porpeder porpedercommit input_file");
@@ -592,20 +1925,23 @@ fn main()
 		"porpedergenproof" => {
 			println!("porpedergenproof");
 			if args.len() >= 5 {
-				let mut data:Vec<u64> = Vec::new();
+				let mut data:Vec<Vec<u8>> = Vec::new();
+				let mut size = HashSize::default();
 
 				let end = 0;
 				let proof_file = args[2].clone();
 				let input_file = args[3].clone();
 				let challenge = args[4].clone().parse::<usize>().unwrap();
 				if(input_file == "random") {
-					for i in 0..512 {
-						data.push(i);
+					for i in 0..512u64 {
+						data.push(i.to_le_bytes().to_vec());
 					}
 				} else {
-					data = get_input_phash(input_file);
+					let (hashes, hash_size) = get_input_phash(input_file);
+					data = hashes;
+					size = hash_size;
 				}
-				let (root, leaf, auth_path, ) = merkel_path_proof(data, challenge);
+				let (root, leaf, auth_path, ) = merkel_path_proof(data, size, challenge);
 				save_merkle_proof(proof_file, root, leaf, auth_path);
 			} else {
 				println!("zkptrans porpeder proof_file input_file challenge_index");
@@ -627,6 +1963,105 @@ fn main()
 				process::exit(1);
 			}			
 		},			
+		"debug-witness" => {
+			println!("debug-witness");
+			match (args.get(2).map(|arg| arg.as_ref()), args.len()) {
+				(Some("ssim"), len) if len >= 7 => {
+					let crs_file = args[3].clone();
+					let input1_file = args[4].clone();
+					let input2_file = args[5].clone();
+					let challenge_file = args[6].clone();
+					let mut challenge_index = 0;
+					if args.len() >= 8 {
+						challenge_index = args[7].parse::<usize>().unwrap();
+					}
+					let witness_file = args.get(8).cloned();
+					debug_witness_ssim(crs_file, input1_file, input2_file, load_challenge(challenge_file, challenge_index), witness_file)
+				},
+				(Some("por"), len) if len >= 4 => {
+					let witness_file = args[3].clone();
+					let input_file = args.get(4).cloned();
+					debug_witness_por(witness_file, input_file)
+				},
+				_ => {
+					println!("zkptrans debug-witness ssim crs_file src_frame_file input2_file challenge_file [challenge_index] [witness_file]");
+					println!("zkptrans debug-witness por witness_file [input_file]");
+					process::exit(1);
+				}
+			}
+		},
+		"circuit-stats" => {
+			let usage = "zkptrans circuit-stats --circuit por|ssim|mse [--depth tree_depth] [--mb-size window_size] [--level namespace_levels] [--json]";
+			let circuit_args = parse_circuit_args(&args[2..], usage);
+			match circuit_args.circuit {
+				Some(circuit) => circuit_stats(circuit, circuit_args.tree_depth, circuit_args.window_size, circuit_args.level, circuit_args.as_json),
+				None => {
+					println!("{}", usage);
+					process::exit(1);
+				}
+			}
+		},
+		"r1cs-export" => {
+			println!("r1cs-export");
+			let usage = "zkptrans r1cs-export --circuit por|ssim|mse --out file_prefix [--depth tree_depth] [--mb-size window_size]";
+			let circuit_args = parse_circuit_args(&args[2..], usage);
+			match (circuit_args.circuit, circuit_args.out) {
+				(Some(circuit), Some(out)) => {
+					let out = out.as_ref();
+					match circuit.as_ref() {
+						"por" => export_r1cs(circuit_stats::blank_por(circuit_args.tree_depth.unwrap_or(9) as usize), false, out),
+						"ssim" => export_r1cs(circuit_stats::blank_ssim(circuit_args.window_size,
+							circuit_args.tree_depth.unwrap_or(source_commit::DEFAULT_SOURCE_TREE_DEPTH)), false, out),
+						_ => export_r1cs(circuit_stats::blank_mse(circuit_args.window_size), false, out),
+					}
+				},
+				_ => {
+					println!("{}", usage);
+					process::exit(1);
+				}
+			}
+		},
+		"r1cs-export-witness" => {
+			println!("r1cs-export-witness");
+			match (args.get(3).map(|arg| arg.as_ref()), args.len()) {
+				(Some("ssim"), len) if len >= 8 => {
+					let out = args[2].clone();
+					let crs_file = args[4].clone();
+					let input1_file = args[5].clone();
+					let input2_file = args[6].clone();
+					let challenge_file = args[7].clone();
+					let mut challenge_index = 0;
+					if args.len() >= 9 {
+						challenge_index = args[8].parse::<usize>().unwrap();
+					}
+					let position = load_challenge(challenge_file, challenge_index);
+					let meta = load_ssim_crs_meta(&crs_file);
+					let SsimProverInput { ssim, src_mb, dst_mb, witns, src_auth_path, src_root, chroma } =
+						ssim_prover_input(&meta, &get_input_samples(input1_file), get_input_samples(input2_file)[0].clone(), position);
+					export_r1cs(ssim.circuit(src_mb, dst_mb, witns, src_auth_path, src_root, position, chroma), true, &out)
+				},
+				(Some("mse"), len) if len >= 8 => {
+					let out = args[2].clone();
+					let crs_file = args[4].clone();
+					let threshold = parse_mse_threshold(&args, 7, &crs_file);
+					let meta = load_mse_crs_meta(&crs_file);
+					let (src_mb, dst_mb, witns) = mse_prover_input(&meta, args[5].clone(), args[6].clone(), threshold);
+					let mse = mb_mse::MseApp::new((meta.window * meta.window) as usize, meta.bit_depth);
+					export_r1cs(mse.circuit(src_mb, dst_mb, witns), true, &out)
+				},
+				(Some("por"), len) if len >= 5 => {
+					let out = args[2].clone();
+					let (auth_path, leaf, root) = load_por_witness(args[4].clone());
+					export_r1cs(por_circuit(auth_path, leaf, root), true, &out)
+				},
+				_ => {
+					println!("zkptrans r1cs-export-witness file_prefix ssim crs_file src_frame_file input2_file challenge_file [challenge_index]");
+					println!("zkptrans r1cs-export-witness file_prefix mse crs_file input1_file input2_file threshold [psnr]");
+					println!("zkptrans r1cs-export-witness file_prefix por witness_file");
+					process::exit(1);
+				}
+			}
+		},
 		"sha256pordbg" => {
 			let mut data:Vec<u64> = Vec::new();
 			for i in 0..512 {
@@ -637,9 +2072,9 @@ fn main()
 			let end = 0;
 		},			
 		"zkportest" => {
-			let mut data:Vec<u64> = Vec::new();
-			for i in 0..512 {
-				data.push(i);
+			let mut data:Vec<Vec<u8>> = Vec::new();
+			for i in 0..512u64 {
+				data.push(i.to_le_bytes().to_vec());
 			}
 			merkle_pot::test_zkpor(data);
 			let end = 0;
@@ -12,23 +12,48 @@ use ff::Field;
 use ff::PrimeField;
 use fil_sapling_crypto::circuit::{boolean, multipack, num, pedersen_hash};
 use storage_proofs::fr32::fr_into_bytes;
-use rand::{Rng, SeedableRng, XorShiftRng};
+use rand::{Rng, SeedableRng, XorShiftRng, thread_rng};
 use std::sync::{Arc, RwLock};
 use super::pixel;
+use super::gadget::{compute_pixel_packing, div_const, less_or_equal, pack_pixels_into_inputs, range_check, sub, sum_vec, witness_fr};
 
 // We're going to use the Groth16 proving system.
 use self::bellperson::groth16::{
     Proof,
+    Parameters,
+    PreparedVerifyingKey,
     generate_random_parameters,
     prepare_verifying_key,
     create_random_proof,
     verify_proof,
 };
 
+/// Bits of the range check between the MSE and the public threshold.
+/// Thresholds must be below 2^MSE_THRESHOLD_BITS.
+pub const MSE_THRESHOLD_BITS: usize = 32;
+
+/// Metadata stored along with the MSE CRS
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+pub struct MseCrsMeta {
+	pub window: u32,
+	/// Bits per pixel, the width of the packed pixel inputs and the peak value of PSNR targets
+	pub bit_depth: u32,
+}
+
+/// Mean squared error of a macroblock pair, proven below a public threshold.
+/// Public inputs are the packed source pixels, the packed destination pixels and the threshold, in that order.
+/// Blocks, witness and threshold are `None` during setup.
 #[derive(Clone)]
 pub struct Mse<E: Engine> {
-    pub srcPixels: Vec<Option<E::Fr>>,
-	pub dstPixels: Vec<Option<E::Fr>>
+	pub src_mb: Option<Vec<u32>>,
+	pub dst_mb: Option<Vec<u32>>,
+	pub mb_size: usize,
+	/// Bits per pixel, pixels are packed into the public inputs at this width
+	pub bit_depth: u32,
+	pub threshold: Option<u64>,
+	pub witns: Option<MseWitness>,
+	phantom: std::marker::PhantomData<E>,
 }
 
 impl <E: Engine> Circuit<E> for Mse<E> {
@@ -37,213 +62,285 @@ impl <E: Engine> Circuit<E> for Mse<E> {
         cs: &mut CS
     ) -> Result<(), SynthesisError>
     {
-		let mbSize = self.srcPixels.len();
-	    let mut varvecSrc: Vec<_> = Vec::new();
-		let mut varvecDst: Vec<_> = Vec::new();
-		let mut varvecDiff: Vec<_> = Vec::new();
-		let mut varvecDiffSq: Vec<_> = Vec::new();
-		let mut varSum : pixel::AllocatedPixel<E>;
-		let mut varMean : pixel::AllocatedPixel<E>;
-		// Source pixel data
-        for i in 0..mbSize {
-            let mut cs = cs.namespace(|| format!("src {}", i));
-            let value = self.srcPixels[i];
-            let value_num = pixel::AllocatedPixel::alloc(cs.namespace(|| "value"), || {
-                value.ok_or_else(|| SynthesisError::AssignmentMissing)
-            })?;
-			value_num.inputize(cs.namespace(|| "value num"))?;
-			varvecSrc.push(value_num);
- 		}
-		// Destination pixel data	
-        for i in 0..mbSize {
-            let mut cs = cs.namespace(|| format!("dst {}", i));
-            let value = self.srcPixels[i];
-            let value_num = pixel::AllocatedPixel::alloc(cs.namespace(|| "value"), || {
-                value.ok_or_else(|| SynthesisError::AssignmentMissing)
-            })?;
-			value_num.inputize(cs.namespace(|| "value num"))?;
-			varvecDst.push(value_num);
-		}
-		// Difference
-        for i in 0..mbSize {
-            let mut cs = cs.namespace(|| format!("diff {}", i));
-            let value_num = sub(cs.namespace(|| "c^5 - k"), &varvecSrc[i], &varvecDst[i])?;
-
-			varvecDiff.push(value_num);
- 		}
-		// square		
-	    for i in 0..mbSize {
-            let mut cs = cs.namespace(|| format!("diffsq {}", i));
-            let value_num = varvecDiff[i].square(cs).unwrap();
-			varvecDiffSq.push(value_num);
- 		}		
-		// sum squares		
-	    {
-			let value = self.srcPixels[0]; // TODO replace
-            let value_num = pixel::AllocatedPixel::alloc(cs.namespace(|| "sum"), || {
-                value.ok_or_else(|| SynthesisError::AssignmentMissing)
-            })?;
-            let res = sumVec(cs, || format!("sum"), &varvecDiffSq, &value_num);
-			varSum = value_num;
- 		}		
-		// mean square error
-	    {
-			let value = self.srcPixels[0]; // TODO replace
-            let value_num = pixel::AllocatedPixel::alloc(cs.namespace(|| "mean"), || {
-                value.ok_or_else(|| SynthesisError::AssignmentMissing)
-            })?;
-            let res = meanVec(cs, || format!("sum"), &varSum, &value_num);
-			varMean = value_num;
- 		}		
-		
+        mse_circuit(cs.namespace(|| "mse"), self)?;
         Ok(())
     }
 }
 
-/// Adds a constraint to CS, enforcing an equality relationship between the allocated numbers a and b.
-///
-/// a == b
-pub fn equal<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-    cs: &mut CS,
-    annotation: A,
-    a: &pixel::AllocatedPixel<E>,
-    b: &pixel::AllocatedPixel<E>,
-) where
-    A: FnOnce() -> AR,
-    AR: Into<String>,
-{
-    // a * 1 = b
-    cs.enforce(
-        annotation,
-        |lc| lc + a.get_variable(),
-        |lc| lc + CS::one(),
-        |lc| lc + b.get_variable(),
-    );
-}
-
-/// Adds a constraint to CS, enforcing a difference relationship between the allocated numbers a, b, and difference.
-///
-/// a - b = difference
-pub fn difference<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-    cs: &mut CS,
-    annotation: A,
-    a: &pixel::AllocatedPixel<E>,
-    b: &pixel::AllocatedPixel<E>,
-    difference: &pixel::AllocatedPixel<E>,
-) where
-    A: FnOnce() -> AR,
-    AR: Into<String>,
-{
-    //    difference = a-b
-    // => difference + b = a
-    // => (difference + b) * 1 = a
-    cs.enforce(
-        annotation,
-        |lc| lc + difference.get_variable() + b.get_variable(),
-        |lc| lc + CS::one(),
-        |lc| lc + a.get_variable(),
-    );
-}
-
-// From storage-proofs/src/circuit/sloths.rs
-fn sub<E: Engine, CS: ConstraintSystem<E>>(
-    mut cs: CS,
-    a: &pixel::AllocatedPixel<E>,
-    b: &pixel::AllocatedPixel<E>,
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct MseWitness {
+	/// Sum of the squared pixel differences
+	pub sum_sq: u64,
+	/// floor(sum_sq / mb_size)
+	pub mse: u64,
+	pub mse_rem: u64,
+	/// Public upper bound on mse
+	pub threshold: u64,
+}
+
+/// Allocates the macroblock pixels and exposes them packed at `bit_depth` bits per pixel.
+/// `mb` is `None` during setup.
+pub fn gen_input_sample<E: Engine, CS: ConstraintSystem<E>>(mut cs: CS, mb: &Option<Vec<u32>>, mb_size: usize, bit_depth: u32) -> Result<Vec<pixel::AllocatedPixel<E>>, SynthesisError>  {
+	let mut var_pix: Vec<pixel::AllocatedPixel<E>> = Vec::new();
+	for i in 0..mb_size {
+		let mut cs = cs.namespace(|| format!("val {}", i));
+		let value_num = pixel::AllocatedPixel::alloc(cs.namespace(|| "value"), || {
+			witness_fr::<E>(mb.as_ref().map(|mb| mb[i] as u64))
+		})?;
+		var_pix.push(value_num);
+	}
+	pack_pixels_into_inputs(cs.namespace(|| "packed"), &var_pix, bit_depth)?;
+	Ok(var_pix)
+}
+
+/// Public inputs of a block pair as `gen_input_sample` exposes them, followed by the bound.
+/// `None` when a pixel exceeds `bit_depth` bits, no proof has such inputs.
+pub fn block_pair_inputs(src_pixel: &[u32], dst_pixel: &[u32], bit_depth: u32, bound: u64) -> Option<Vec<Fr>> {
+	let mut inputs = compute_pixel_packing(src_pixel, bit_depth)?;
+	inputs.extend(compute_pixel_packing(dst_pixel, bit_depth)?);
+	inputs.push(Fr::from_repr(bound.into()).unwrap());
+	Some(inputs)
+}
+
+/// Returns the allocated mse
+pub fn mse_circuit<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	c: Mse<E>,
 ) -> Result<pixel::AllocatedPixel<E>, SynthesisError> {
-    let res = pixel::AllocatedPixel::alloc(cs.namespace(|| "sub num"), || {
-        let mut tmp = a
-            .get_value()
-            .ok_or_else(|| SynthesisError::AssignmentMissing)?;
-        tmp.sub_assign(
-            &b.get_value()
-                .ok_or_else(|| SynthesisError::AssignmentMissing)?,
-        );
-
-        Ok(tmp)
-    })?;
-
-    // a - b = res
-    difference(&mut cs, || "subtraction constraint", &a, &b, &res);
-
-    Ok(res)
-}
-
-pub fn sumVec<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-    cs: &mut CS,
-    annotation: A,
-    a: &Vec<pixel::AllocatedPixel<E>>,
-    sum: &pixel::AllocatedPixel<E>,
-) where
-    A: FnOnce() -> AR,
-    AR: Into<String>,
-{
-    cs.enforce(
-        annotation,
-        |mut lc| {for i in 1..a.len() {lc = lc + a[i].get_variable()} lc},
-        |lc| lc + CS::one(),
-        |lc| lc + sum.get_variable(),
-    );
-}
-
-pub fn meanVec<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-    cs: &mut CS,
-    annotation: A,
-    sum: &pixel::AllocatedPixel<E>,
-    mean: &pixel::AllocatedPixel<E>,
-) where
-    A: FnOnce() -> AR,
-    AR: Into<String>,
-{
-    cs.enforce(
-        annotation,
-        |lc| lc + sum.get_variable(),
-        |lc| lc + CS::one(),
-        |lc| lc + mean.get_variable(),
-    );
-}
-
-pub fn main(_srcPixel: Vec<u32>, _dstPixel: Vec<u32>){
-    use paired::bls12_381::{Bls12, Fr};
-    use rand::thread_rng;
-    use bellperson::groth16::{
-        create_random_proof, generate_random_parameters, prepare_verifying_key, verify_proof, Proof,
-    };
-    
-    let rng = &mut thread_rng();
- 	let mbSize = 256;
-     // Create an instance of circuit
-	let tmpsrcPixels: Vec<Option<Fr>> = _srcPixel.iter().map(|x| Fr::from_str("7")).collect();
-	let tmpdstPixels: Vec<Option<Fr>> = _dstPixel.iter().map(|x| Fr::from_str("7")).collect();
-
-    let c = Mse::<Bls12> {
-		srcPixels: tmpsrcPixels.clone(),
-		dstPixels: tmpdstPixels.clone()
-    };
-
-    // Create parameters for our circuit
-    let params = {
-        let c = Mse::<Bls12> {
-            srcPixels: tmpsrcPixels.clone(),
-			dstPixels: tmpdstPixels.clone()
-        };
-
-        generate_random_parameters(c, rng).unwrap()
-    };
-    
-    // Prepare the verification key (for proof verification)
-    let pvk = prepare_verifying_key(&params.vk);
-
-    println!("Creating proofs...");
-    
-
-    
-    // Create a groth16 proof with our parameters.
-    let proof = create_random_proof(c, &params, rng).unwrap();
-    let expected_inputs: Vec<Fr> = tmpsrcPixels.into_iter().map(|n| n.unwrap()).collect();  
-    assert!(verify_proof(
-        &pvk,
-        &proof,
-        &expected_inputs[..]
-    ).unwrap());
-}
\ No newline at end of file
+	let witns = c.witns.as_ref();
+	let mb_size = c.mb_size;
+	let circ_src = gen_input_sample(cs.namespace(|| "src mb"), &c.src_mb, mb_size, c.bit_depth)?;
+	let circ_dst = gen_input_sample(cs.namespace(|| "dst mb"), &c.dst_mb, mb_size, c.bit_depth)?;
+
+	let mut circ_diff_sq: Vec<pixel::AllocatedPixel<E>> = Vec::new();
+	for i in 0..mb_size {
+		let mut cs = cs.namespace(|| format!("diff {}", i));
+		let diff = sub(cs.namespace(|| "diff"), &circ_src[i], &circ_dst[i])?;
+		circ_diff_sq.push(diff.square(cs.namespace(|| "diff sq"))?);
+	}
+
+	let circ_sum_sq = sum_vec(cs.namespace(|| "sum sq"), &circ_diff_sq, &vec![1; mb_size])?;
+	let circ_mse = div_const(cs.namespace(|| "mse"), &circ_sum_sq, witns.map(|w| w.sum_sq), mb_size as u64)?;
+	range_check(cs.namespace(|| "mse range"), &circ_mse, 2 * c.bit_depth as usize)?;
+
+	let circ_threshold = pixel::AllocatedPixel::alloc(cs.namespace(|| "threshold"), || {
+		witness_fr::<E>(c.threshold)
+	})?;
+	less_or_equal(cs.namespace(|| "mse threshold"), &circ_mse, &circ_threshold, MSE_THRESHOLD_BITS)?;
+	circ_threshold.inputize(cs.namespace(|| "threshold input"))?;
+
+	Ok(circ_mse)
+}
+
+pub fn gen_witness(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, threshold: u64) -> MseWitness {
+	let sum_sq: u64 = src_mb.iter().zip(dst_mb.iter())
+		.map(|(x, y)| {
+			let d = (*x as i64) - (*y as i64);
+			(d * d) as u64
+		})
+		.sum();
+	let n = src_mb.len() as u64;
+	MseWitness {
+		sum_sq,
+		mse: sum_sq / n,
+		mse_rem: sum_sq % n,
+		threshold,
+	}
+}
+
+/// Largest integer MSE meeting a PSNR target in dB, PSNR = 10 * log10(L^2 / MSE) with L the
+/// largest pixel value of the bit depth
+pub fn psnr_to_mse_threshold(psnr: f64, bit_depth: u32) -> u64 {
+	let peak = pixel::max_pixel_value(bit_depth) as f64;
+	let peak_sq = peak * peak;
+	(peak_sq / 10f64.powf(psnr / 10.0)).floor() as u64
+}
+
+/// PSNR in dB of an MSE, infinite for identical blocks
+pub fn mse_to_psnr(mse: f64, bit_depth: u32) -> f64 {
+	let peak = pixel::max_pixel_value(bit_depth) as f64;
+	let peak_sq = peak * peak;
+	10.0 * (peak_sq / mse).log10()
+}
+
+/// Wrapper for MSE API
+pub struct MseApp {
+	mb_size: usize,
+	bit_depth: u32,
+}
+
+impl MseApp {
+	pub fn new(mb_size: usize, bit_depth: u32) -> Self {
+		MseApp {
+			mb_size,
+			bit_depth,
+		}
+	}
+
+	/// Circuit of this configuration without blocks or witness, as synthesized during setup
+	pub fn blank_circuit(&self) -> Mse<Bls12> {
+		Mse::<Bls12> {
+			src_mb: None,
+			dst_mb: None,
+			mb_size: self.mb_size,
+			bit_depth: self.bit_depth,
+			threshold: None,
+			witns: None,
+			phantom: Default::default(),
+		}
+	}
+
+	/// Circuit of this configuration over a block pair, as synthesized by the prover
+	pub fn circuit(&self, src_pixel: Vec<u32>, dst_pixel: Vec<u32>, witns: MseWitness) -> Mse<Bls12> {
+		Mse::<Bls12> {
+			src_mb: Some(src_pixel),
+			dst_mb: Some(dst_pixel),
+			mb_size: self.mb_size,
+			bit_depth: self.bit_depth,
+			threshold: Some(witns.threshold),
+			witns: Some(witns),
+			phantom: Default::default(),
+		}
+	}
+}
+
+impl Default for MseApp {
+	fn default() -> Self {
+		MseApp::new(256, pixel::DEFAULT_BIT_DEPTH)
+	}
+}
+
+/// A trait that makes it easy to implement MSE API
+pub trait MseApi<'a, C: Circuit<Bls12>>: Default {
+	/// The name of the application. Used for identifying caches.
+	fn name() -> String;
+
+	/// Generate groth parameters. They depend only on the block size and the bit depth.
+	fn setup(
+		&mut self,
+	) -> Parameters<Bls12>;
+
+	fn create_proof(
+		&mut self,
+		groth_params: &Parameters<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		witns: MseWitness,
+	) -> Proof<Bls12>;
+
+	/// Verify the given proof, return `None` if not implemented.
+	fn verify_proof(
+		&mut self,
+		pvk: &PreparedVerifyingKey<Bls12>,
+		proof: &Proof<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		threshold: u64) -> Option<bool>;
+}
+
+impl<'a> MseApi<'a, Mse<Bls12>> for MseApp {
+	fn name() -> String {
+		"Mse".to_string()
+	}
+
+	fn setup(
+		&mut self,
+	) -> Parameters<Bls12> {
+		let rng = &mut thread_rng();
+		let c = self.blank_circuit();
+		generate_random_parameters(c, rng).unwrap()
+	}
+
+	fn create_proof(
+		&mut self,
+		groth_params: &Parameters<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		witns: MseWitness,
+	) -> Proof<Bls12> {
+		let rng = &mut thread_rng();
+		let c = self.circuit(src_pixel, dst_pixel, witns);
+		create_random_proof(c, groth_params, rng).expect("failed to create proof")
+	}
+
+	fn verify_proof(
+		&mut self,
+		pvk: &PreparedVerifyingKey<Bls12>,
+		proof: &Proof<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		threshold: u64,
+	) -> Option<bool> {
+		let expected_inputs = block_pair_inputs(&src_pixel, &dst_pixel, self.bit_depth, threshold)?;
+		Some(verify_proof(pvk, proof, &expected_inputs).expect("failed to verify proof"))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::test_helper::gen_mb;
+	use storage_proofs::circuit::test::*;
+
+	#[test]
+	fn test_mse_threshold() {
+		let src_mb = gen_mb(256, pixel::DEFAULT_BIT_DEPTH);
+		let dst_mb: Vec<u32> = src_mb.iter().map(|x| if *x > 3 { x - 3 } else { x + 3 }).collect();
+		let witns = gen_witness(&src_mb, &dst_mb, 9);
+		assert_eq!(witns.mse, 9);
+
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let c = Mse::<Bls12> {
+			src_mb: Some(src_mb.clone()),
+			dst_mb: Some(dst_mb.clone()),
+			mb_size: 256,
+			bit_depth: pixel::DEFAULT_BIT_DEPTH,
+			threshold: Some(witns.threshold),
+			witns: Some(witns.clone()),
+			phantom: Default::default(),
+		};
+		mse_circuit(cs.namespace(|| "mse"), c).unwrap();
+		assert!(cs.is_satisfied());
+		// 256 8-bit pixels pack into 9 inputs per block
+		assert_eq!(cs.num_inputs(), 1 + 2 * 9 + 1);
+		assert!(cs.verify(&block_pair_inputs(&src_mb, &dst_mb, pixel::DEFAULT_BIT_DEPTH, witns.threshold).unwrap()));
+
+		// A threshold below the MSE cannot be met
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let c = Mse::<Bls12> {
+			src_mb: Some(src_mb),
+			dst_mb: Some(dst_mb),
+			mb_size: 256,
+			bit_depth: pixel::DEFAULT_BIT_DEPTH,
+			threshold: Some(8),
+			witns: Some(witns),
+			phantom: Default::default(),
+		};
+		mse_circuit(cs.namespace(|| "mse"), c).unwrap();
+		assert!(!cs.is_satisfied());
+	}
+
+	#[test]
+	fn test_mse_proof() {
+		let mb_size = 16;
+		let mut mse = MseApp::new(mb_size, pixel::DEFAULT_BIT_DEPTH);
+		let groth_params = mse.setup();
+
+		let src_mb = gen_mb(mb_size, pixel::DEFAULT_BIT_DEPTH);
+		let dst_mb = gen_mb(mb_size, pixel::DEFAULT_BIT_DEPTH);
+		let witns = gen_witness(&src_mb, &dst_mb, psnr_to_mse_threshold(0.0, pixel::DEFAULT_BIT_DEPTH));
+		let threshold = witns.threshold;
+		let proof = mse.create_proof(&groth_params, src_mb.clone(), dst_mb.clone(), witns);
+
+		let pvk = prepare_verifying_key(&groth_params.vk);
+		assert_eq!(mse.verify_proof(&pvk, &proof, src_mb.clone(), dst_mb.clone(), threshold), Some(true));
+		assert_eq!(mse.verify_proof(&pvk, &proof, src_mb.clone(), dst_mb.clone(), threshold + 1), Some(false));
+
+		// a pixel above the bit depth has no packed inputs
+		let mut wide_mb = src_mb;
+		wide_mb[0] = 1 << pixel::DEFAULT_BIT_DEPTH;
+		assert_eq!(mse.verify_proof(&pvk, &proof, wide_mb, dst_mb, threshold), None);
+	}
+}
@@ -0,0 +1,527 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+extern crate bellperson;
+extern crate paired;
+extern crate rand;
+use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+use paired::Engine;
+use paired::bls12_381::{Bls12, Fr};
+use std::marker::PhantomData;
+
+use ff::{Field, PrimeField};
+use rand::{Rng, thread_rng};
+use super::pixel::*;
+use super::gadget::{div_const, range_check, sum_vec, witness_fr};
+use super::mb_ssim::{
+	absdiff_vec, covairance_constraint, gen_sample_range, gen_sample_sign, get_mb_sum,
+	get_witness_sigma, get_witness_sigma_xy, ssim_constants, ssim_lum_or_contrast, ssim_struct_constraint,
+	vairance_constraint,
+};
+
+// We're going to use the Groth16 proving system.
+use self::bellperson::groth16::{
+	create_random_proof, generate_random_parameters, prepare_verifying_key, verify_proof, Proof, Parameters, PreparedVerifyingKey
+};
+
+/// Number of scales. The block is halved between two scales, so 16x16 blocks are compared at 16x16, 8x8 and 4x4.
+pub const MSSSIM_SCALES: usize = 3;
+
+/// Integer exponents of the contrast and structure terms, finest scale first.
+/// They approximate the weights 0.0448, 0.2856, 0.3001 of reference MS-SSIM over three scales,
+/// kept small so the combined numerator stays well inside the field.
+pub const MSSSIM_CS_EXPONENTS: [u32; MSSSIM_SCALES] = [1, 2, 2];
+
+/// Integer exponent of the luminance term, computed at the coarsest scale only
+pub const MSSSIM_L_EXPONENT: u32 = 2;
+
+/// Supported block dimensions. The coarsest scale must still hold a 2x2 block.
+pub const MSSSIM_WINDOW_SIZES: [u32; 3] = [8, 16, 32];
+
+/// Supported bit depths. The product of the scale terms grows by about 20 bits per pixel bit,
+/// 12-bit pixels would take the MS-SSIM numerator past the field size.
+pub const MSSSIM_BIT_DEPTHS: [u32; 2] = [8, 10];
+
+/// Metadata stored along with the MS-SSIM CRS
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+pub struct MsSsimCrsMeta {
+	pub window: u32,
+	pub bit_depth: u32,
+}
+
+/// Terms of one scale
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct MsSsimScaleWitness {
+	pub sum_x: u64,
+	pub sum_y: u64,
+
+	sigma_x_sq_sum: u64,
+	sigma_x: u64,
+	sigma_x_frac: u64,
+
+	sigma_y_sq_sum: u64,
+	pub sigma_y: u64,
+	sigma_y_frac: u64,
+
+	sigma_xy_sum: u64,
+	sigma_xy: u64,
+
+	pub c_numerator: u64,
+	pub c_denom: u64,
+	pub s_numerator: u64,
+	pub s_denom: u64,
+	pub l_numerator: u64,
+	pub l_denom: u64,
+}
+
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct MsSsimWitness {
+	/// Block dimension at the finest scale
+	pub size: u32,
+	/// Finest scale first
+	pub scales: Vec<MsSsimScaleWitness>,
+}
+
+/// MS-SSIM circuit. Blocks and witness are `None` during setup.
+/// Public inputs are sum y and sigma y of the finest scale, then the MS-SSIM numerator and denominator.
+#[derive(Clone)]
+pub struct MsSsim<E: Engine> {
+	src_mb: Option<Vec<u32>>,
+	dst_mb: Option<Vec<u32>>,
+	size: u32,
+	bit_depth: u32,
+	pub witns: Option<MsSsimWitness>,
+	phantom: PhantomData<E>,
+}
+
+impl<E: Engine> Circuit<E> for MsSsim<E> {
+
+	fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+		msssim_circuit(cs.namespace(|| "msssim"), self)?;
+		Ok(())
+	}
+
+}
+
+/// Averages every 2x2 block of a `size`x`size` block
+pub fn downsample(mb: &Vec<u32>, size: u32) -> Vec<u32> {
+	let size = size as usize;
+	let half = size / 2;
+	let mut out = Vec::with_capacity(half * half);
+	for y in 0..half {
+		for x in 0..half {
+			let i = 2 * y * size + 2 * x;
+			out.push((mb[i] + mb[i + 1] + mb[i + size] + mb[i + size + 1]) / 4);
+		}
+	}
+	out
+}
+
+/// In-circuit 2x2 averaging. Every output pixel satisfies a + b + c + d = 4 * avg + rem with rem in [0, 4)
+/// and avg in the `bit_depth` range, which makes avg the floor of the average.
+/// `mb` holds the native values of `pixels` and is `None` during setup.
+pub fn downsample_constraint<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	pixels: &Vec<AllocatedPixel<E>>,
+	mb: &Option<Vec<u32>>,
+	size: u32,
+	bit_depth: u32,
+) -> Result<Vec<AllocatedPixel<E>>, SynthesisError>
+{
+	let size = size as usize;
+	let half = size / 2;
+	let four = E::Fr::from_repr(4u64.into()).unwrap();
+	let mut out = Vec::with_capacity(half * half);
+	for y in 0..half {
+		for x in 0..half {
+			let mut cs = cs.namespace(|| format!("avg {}", y * half + x));
+			let i = 2 * y * size + 2 * x;
+			let quad = [i, i + 1, i + size, i + size + 1];
+			let sum = mb.as_ref().map(|mb| quad.iter().map(|j| mb[*j] as u64).sum::<u64>());
+
+			let avg = AllocatedPixel::alloc(cs.namespace(|| "avg"), || witness_fr::<E>(sum.map(|s| s / 4)))?;
+			let rem = AllocatedPixel::alloc(cs.namespace(|| "rem"), || witness_fr::<E>(sum.map(|s| s % 4)))?;
+			rem.into_bits_le_fixed(cs.namespace(|| "rem range"), 2)?;
+			range_check(cs.namespace(|| "avg range"), &avg, bit_depth as usize)?;
+
+			cs.enforce(
+				|| "avg enforce",
+				|lc| lc + avg.get_variable(),
+				|lc| lc + (four, CS::one()),
+				|mut lc| {
+					for j in quad.iter() {
+						lc = lc + pixels[*j].get_variable();
+					}
+					lc - rem.get_variable()
+				},
+			);
+			out.push(avg);
+		}
+	}
+	Ok(out)
+}
+
+/// x^exp by repeated multiplication, exp >= 1
+fn pow_constraint<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	x: &AllocatedPixel<E>,
+	exp: u32,
+) -> Result<AllocatedPixel<E>, SynthesisError>
+{
+	let mut acc = x.clone();
+	for i in 1..exp {
+		acc = acc.mul(cs.namespace(|| format!("pow {}", i)), x)?;
+	}
+	Ok(acc)
+}
+
+/// Returns the MS-SSIM numerator and denominator
+pub fn msssim_circuit<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	c: MsSsim<E>,
+) -> Result<(AllocatedPixel<E>, AllocatedPixel<E>), SynthesisError> {
+	let witns = c.witns.as_ref();
+	let (c1, c2, c3) = ssim_constants(c.bit_depth);
+	let mut size = c.size;
+	let mut src_mb = c.src_mb.clone();
+	let mut dst_mb = c.dst_mb.clone();
+	let mut circ_mb_x = gen_sample_range(cs.namespace(|| "src mb"), &src_mb, (size * size) as usize, c.bit_depth)?;
+	let mut circ_mb_y = gen_sample_range(cs.namespace(|| "dst mb"), &dst_mb, (size * size) as usize, c.bit_depth)?;
+
+	let mut factors_numerator: Vec<AllocatedPixel<E>> = Vec::new();
+	let mut factors_denom: Vec<AllocatedPixel<E>> = Vec::new();
+	let mut public_sum_y = None;
+	let mut public_sigma_y = None;
+
+	for scale in 0..MSSSIM_SCALES {
+		let mut cs = cs.namespace(|| format!("scale {}", scale));
+		if scale > 0 {
+			circ_mb_x = downsample_constraint(cs.namespace(|| "src downsample"), &circ_mb_x, &src_mb, size, c.bit_depth)?;
+			circ_mb_y = downsample_constraint(cs.namespace(|| "dst downsample"), &circ_mb_y, &dst_mb, size, c.bit_depth)?;
+			src_mb = src_mb.map(|mb| downsample(&mb, size));
+			dst_mb = dst_mb.map(|mb| downsample(&mb, size));
+			size /= 2;
+		}
+		let w = witns.map(|w| &w.scales[scale]);
+		let mb_size = (size * size) as usize;
+		let weights = vec![1; mb_size];
+		let num_samples = mb_size as u64;
+
+		let circ_sum_x = sum_vec(cs.namespace(|| "src sum mb"), &circ_mb_x, &weights)?;
+		let circ_sum_y = sum_vec(cs.namespace(|| "dst sum mb"), &circ_mb_y, &weights)?;
+		let circ_mean_x = div_const(cs.namespace(|| "src mean mb"), &circ_sum_x, w.map(|w| w.sum_x), num_samples)?;
+		let circ_mean_y = div_const(cs.namespace(|| "dst mean mb"), &circ_sum_y, w.map(|w| w.sum_y), num_samples)?;
+		range_check(cs.namespace(|| "src mean range"), &circ_mean_x, c.bit_depth as usize)?;
+		range_check(cs.namespace(|| "dst mean range"), &circ_mean_y, c.bit_depth as usize)?;
+
+		let circ_sign_x = gen_sample_sign(cs.namespace(|| "sign src"), &src_mb, w.map(|w| w.sum_x / num_samples), mb_size)?;
+		let circ_sign_y = gen_sample_sign(cs.namespace(|| "sign dst"), &dst_mb, w.map(|w| w.sum_y / num_samples), mb_size)?;
+		let circ_diff_x = absdiff_vec(cs.namespace(|| "absdiff x"), &circ_mb_x, &circ_mean_x, &circ_sign_x, c.bit_depth)?;
+		let circ_diff_y = absdiff_vec(cs.namespace(|| "absdiff y"), &circ_mb_y, &circ_mean_y, &circ_sign_y, c.bit_depth)?;
+
+		let (_, _, circ_sigma_x) = vairance_constraint(cs.namespace(|| "sigma x const"),
+				&circ_diff_x, &circ_diff_x, &weights, w.map(|w| w.sigma_x_sq_sum), c.bit_depth)?;
+		let (_, _, circ_sigma_y) = vairance_constraint(cs.namespace(|| "sigma y const"),
+				&circ_diff_y, &circ_diff_y, &weights, w.map(|w| w.sigma_y_sq_sum), c.bit_depth)?;
+		let (_, circ_sigma_xy) = covairance_constraint(cs.namespace(|| "sigma xy const"),
+				&circ_diff_x, &circ_diff_y, &weights, w.map(|w| w.sigma_xy_sum), w.map(|w| w.sigma_xy), c.bit_depth)?;
+
+		let (circ_c_numerator, circ_c_denom, _) = ssim_lum_or_contrast(cs.namespace(|| "ssim contrast"), &circ_sigma_x, &circ_sigma_y,
+				c2, w.map(|w| w.c_numerator), w.map(|w| w.c_denom))?;
+		let (circ_s_numerator, circ_s_denom) = ssim_struct_constraint(cs.namespace(|| "ssim struct"), &circ_sigma_xy, &circ_sigma_x, &circ_sigma_y,
+				c3, w.map(|w| w.s_numerator), w.map(|w| w.s_denom))?;
+
+		let exp = MSSSIM_CS_EXPONENTS[scale];
+		factors_numerator.push(pow_constraint(cs.namespace(|| "c numerator pow"), &circ_c_numerator, exp)?);
+		factors_numerator.push(pow_constraint(cs.namespace(|| "s numerator pow"), &circ_s_numerator, exp)?);
+		factors_denom.push(pow_constraint(cs.namespace(|| "c denom pow"), &circ_c_denom, exp)?);
+		factors_denom.push(pow_constraint(cs.namespace(|| "s denom pow"), &circ_s_denom, exp)?);
+
+		if scale == MSSSIM_SCALES - 1 {
+			let (circ_l_numerator, circ_l_denom, _) = ssim_lum_or_contrast(cs.namespace(|| "ssim lum"), &circ_mean_x, &circ_mean_y,
+					c1, w.map(|w| w.l_numerator), w.map(|w| w.l_denom))?;
+			factors_numerator.push(pow_constraint(cs.namespace(|| "l numerator pow"), &circ_l_numerator, MSSSIM_L_EXPONENT)?);
+			factors_denom.push(pow_constraint(cs.namespace(|| "l denom pow"), &circ_l_denom, MSSSIM_L_EXPONENT)?);
+		}
+		if scale == 0 {
+			public_sum_y = Some(circ_sum_y);
+			public_sigma_y = Some(circ_sigma_y);
+		}
+	}
+
+	let mut circ_numerator = factors_numerator[0].clone();
+	let mut circ_denom = factors_denom[0].clone();
+	for i in 1..factors_numerator.len() {
+		circ_numerator = circ_numerator.mul(cs.namespace(|| format!("msssim numerator {}", i)), &factors_numerator[i])?;
+		circ_denom = circ_denom.mul(cs.namespace(|| format!("msssim denom {}", i)), &factors_denom[i])?;
+	}
+
+	public_sum_y.unwrap().inputize(cs.namespace(|| "sum y"))?;
+	public_sigma_y.unwrap().inputize(cs.namespace(|| "sigma_y"))?;
+	circ_numerator.inputize(cs.namespace(|| "msssim_numerator"))?;
+	circ_denom.inputize(cs.namespace(|| "msssim_denom"))?;
+
+	Ok((circ_numerator, circ_denom))
+}
+
+fn gen_scale_witness(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, bit_depth: u32) -> MsSsimScaleWitness {
+	let (c1, c2, c3) = ssim_constants(bit_depth);
+	let weights = vec![1; src_mb.len()];
+	let num_samples = src_mb.len() as u64;
+	let sum_x = get_mb_sum(src_mb, &weights);
+	let sum_y = get_mb_sum(dst_mb, &weights);
+	let (mean_x, mean_y) = (sum_x / num_samples, sum_y / num_samples);
+	let (sigma_x_sq_sum, _, sigma_x, sigma_x_frac) = get_witness_sigma(src_mb, src_mb, &weights);
+	let (sigma_y_sq_sum, _, sigma_y, sigma_y_frac) = get_witness_sigma(dst_mb, dst_mb, &weights);
+	let (sigma_xy_sum, sigma_xy) = get_witness_sigma_xy(src_mb, dst_mb, &weights);
+	MsSsimScaleWitness {
+		sum_x,
+		sum_y,
+		sigma_x_sq_sum,
+		sigma_x,
+		sigma_x_frac,
+		sigma_y_sq_sum,
+		sigma_y,
+		sigma_y_frac,
+		sigma_xy_sum,
+		sigma_xy,
+		c_numerator: 2 * sigma_x * sigma_y + c2,
+		c_denom: sigma_x * sigma_x + sigma_y * sigma_y + c2,
+		s_numerator: sigma_xy + c3,
+		s_denom: sigma_x * sigma_y + c3,
+		l_numerator: 2 * mean_x * mean_y + c1,
+		l_denom: mean_x * mean_x + mean_y * mean_y + c1,
+	}
+}
+
+pub fn gen_witness(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, size: u32, bit_depth: u32) -> MsSsimWitness {
+	let mut src_mb = src_mb.clone();
+	let mut dst_mb = dst_mb.clone();
+	let mut scale_size = size;
+	let mut scales = Vec::with_capacity(MSSSIM_SCALES);
+	for scale in 0..MSSSIM_SCALES {
+		if scale > 0 {
+			src_mb = downsample(&src_mb, scale_size);
+			dst_mb = downsample(&dst_mb, scale_size);
+			scale_size /= 2;
+		}
+		scales.push(gen_scale_witness(&src_mb, &dst_mb, bit_depth));
+	}
+	MsSsimWitness {
+		size,
+		scales,
+	}
+}
+
+fn fr_pow(x: u64, exp: u32) -> Fr {
+	let x = Fr::from_repr(x.into()).unwrap();
+	let mut acc = Fr::one();
+	for _ in 0..exp {
+		acc.mul_assign(&x);
+	}
+	acc
+}
+
+/// MS-SSIM numerator and denominator as the circuit computes them
+pub fn msssim_fraction(witns: &MsSsimWitness) -> (Fr, Fr) {
+	let mut numerator = Fr::one();
+	let mut denom = Fr::one();
+	for (w, exp) in witns.scales.iter().zip(MSSSIM_CS_EXPONENTS.iter()) {
+		numerator.mul_assign(&fr_pow(w.c_numerator, *exp));
+		numerator.mul_assign(&fr_pow(w.s_numerator, *exp));
+		denom.mul_assign(&fr_pow(w.c_denom, *exp));
+		denom.mul_assign(&fr_pow(w.s_denom, *exp));
+	}
+	let last = &witns.scales[MSSSIM_SCALES - 1];
+	numerator.mul_assign(&fr_pow(last.l_numerator, MSSSIM_L_EXPONENT));
+	denom.mul_assign(&fr_pow(last.l_denom, MSSSIM_L_EXPONENT));
+	(numerator, denom)
+}
+
+/// MS-SSIM value of a numerator and denominator, as a verifier reads them from the public inputs.
+/// The exponents are normalised to sum to one.
+pub fn fraction_value(numerator: &Fr, denom: &Fr) -> f64 {
+	let total = (MSSSIM_CS_EXPONENTS.iter().sum::<u32>() + MSSSIM_L_EXPONENT) as f64;
+	let to_f64 = |x: &Fr| x.into_repr().as_ref().iter().rev().fold(0.0, |acc, limb| acc * 2f64.powi(64) + *limb as f64);
+	(to_f64(numerator) / to_f64(denom)).powf(1.0 / total)
+}
+
+/// MS-SSIM value of the integer terms, for reporting. The exponents are normalised to sum to one.
+pub fn msssim_value(witns: &MsSsimWitness) -> f64 {
+	let total = (MSSSIM_CS_EXPONENTS.iter().sum::<u32>() + MSSSIM_L_EXPONENT) as f64;
+	let ratio = |n: u64, d: u64| if d == 0 { 1.0 } else { n as f64 / d as f64 };
+	let mut value = 1.0;
+	for (w, exp) in witns.scales.iter().zip(MSSSIM_CS_EXPONENTS.iter()) {
+		let cs = ratio(w.c_numerator, w.c_denom) * ratio(w.s_numerator, w.s_denom);
+		value *= cs.powf(*exp as f64 / total);
+	}
+	let last = &witns.scales[MSSSIM_SCALES - 1];
+	value * ratio(last.l_numerator, last.l_denom).powf(MSSSIM_L_EXPONENT as f64 / total)
+}
+
+/// Wrapper for MS-SSIM API
+pub struct MsSsimApp {
+	size: u32,
+	bit_depth: u32,
+}
+
+impl MsSsimApp {
+	pub fn new(size: u32, bit_depth: u32) -> Self {
+		MsSsimApp {
+			size,
+			bit_depth,
+		}
+	}
+}
+
+impl Default for MsSsimApp {
+	fn default() -> Self {
+		MsSsimApp::new(16, DEFAULT_BIT_DEPTH)
+	}
+}
+
+/// A trait that makes it easy to implement MS-SSIM API
+pub trait MsSsimApi<'a, C: Circuit<Bls12>>: Default {
+	/// The name of the application. Used for identifying caches.
+	fn name() -> String;
+
+	/// Generate groth parameters. They depend only on the block size and the bit depth.
+	fn setup(
+		&mut self,
+	) -> Parameters<Bls12>;
+
+	fn create_proof(
+		&mut self,
+		groth_params: &Parameters<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		witns: MsSsimWitness,
+	) -> Proof<Bls12>;
+
+	/// Verify the given proof against the verifier's copy of the destination block and the claimed
+	/// MS-SSIM numerator and denominator, return `None` if not implemented.
+	fn verify_proof(
+		&mut self,
+		pvk: &PreparedVerifyingKey<Bls12>,
+		proof: &Proof<Bls12>,
+		dst_pixel: &Vec<u32>,
+		fraction: (Fr, Fr)) -> Option<bool>;
+}
+
+impl<'a> MsSsimApi<'a, MsSsim<Bls12>> for MsSsimApp {
+	fn name() -> String {
+		"MsSsim".to_string()
+	}
+
+	fn setup(
+		&mut self,
+	) -> Parameters<Bls12> {
+		let rng = &mut thread_rng();
+		let c = MsSsim::<Bls12> {
+			src_mb: None,
+			dst_mb: None,
+			size: self.size,
+			bit_depth: self.bit_depth,
+			witns: None,
+			phantom: Default::default(),
+		};
+		generate_random_parameters(c, rng).unwrap()
+	}
+
+	fn create_proof(
+		&mut self,
+		groth_params: &Parameters<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		witns: MsSsimWitness,
+	) -> Proof<Bls12> {
+		let rng = &mut thread_rng();
+		let c = MsSsim::<Bls12> {
+			src_mb: Some(src_pixel),
+			dst_mb: Some(dst_pixel),
+			size: self.size,
+			bit_depth: self.bit_depth,
+			witns: Some(witns),
+			phantom: Default::default(),
+		};
+		create_random_proof(c, groth_params, rng).expect("failed to create proof")
+	}
+
+	fn verify_proof(
+		&mut self,
+		pvk: &PreparedVerifyingKey<Bls12>,
+		proof: &Proof<Bls12>,
+		dst_pixel: &Vec<u32>,
+		fraction: (Fr, Fr),
+	) -> Option<bool> {
+		let weights = vec![1; dst_pixel.len()];
+		let sum_y = get_mb_sum(dst_pixel, &weights);
+		let (_, _, sigma_y, _) = get_witness_sigma(dst_pixel, dst_pixel, &weights);
+		let (numerator, denom) = fraction;
+		let expected_inputs = vec![
+			Fr::from_repr(sum_y.into()).unwrap(),
+			Fr::from_repr(sigma_y.into()).unwrap(),
+			numerator,
+			denom,
+		];
+		Some(verify_proof(pvk, proof, &expected_inputs).expect("failed to verify proof"))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::test_helper::gen_mb;
+	use storage_proofs::circuit::test::*;
+
+	#[test]
+	fn test_msssim_circuit() {
+		let size = 16;
+		for bit_depth in MSSSIM_BIT_DEPTHS.iter() {
+			let src_mb = gen_mb((size * size) as usize, *bit_depth);
+			let dst_mb = gen_mb((size * size) as usize, *bit_depth);
+			let witns = gen_witness(&src_mb, &dst_mb, size, *bit_depth);
+
+			let mut cs = TestConstraintSystem::<Bls12>::new();
+			let c = MsSsim::<Bls12> {
+				src_mb: Some(src_mb),
+				dst_mb: Some(dst_mb),
+				size,
+				bit_depth: *bit_depth,
+				witns: Some(witns.clone()),
+				phantom: Default::default(),
+			};
+			let (circ_numerator, circ_denom) = msssim_circuit(cs.namespace(|| "msssim"), c).unwrap();
+			assert!(cs.is_satisfied(), "bit depth {}", bit_depth);
+
+			let (numerator, denom) = msssim_fraction(&witns);
+			assert_eq!(circ_numerator.get_value().unwrap(), numerator);
+			assert_eq!(circ_denom.get_value().unwrap(), denom);
+		}
+	}
+
+	#[test]
+	fn test_msssim_proof() {
+		let size = 8;
+		let mut msssim = MsSsimApp::new(size, DEFAULT_BIT_DEPTH);
+		let groth_params = msssim.setup();
+
+		let src_mb = gen_mb((size * size) as usize, DEFAULT_BIT_DEPTH);
+		let dst_mb = gen_mb((size * size) as usize, DEFAULT_BIT_DEPTH);
+		let witns = gen_witness(&src_mb, &dst_mb, size, DEFAULT_BIT_DEPTH);
+		let proof = msssim.create_proof(&groth_params, src_mb, dst_mb.clone(), witns.clone());
+
+		let pvk = prepare_verifying_key(&groth_params.vk);
+		let (numerator, denom) = msssim_fraction(&witns);
+		assert_eq!(msssim.verify_proof(&pvk, &proof, &dst_mb, (numerator, denom)), Some(true));
+		assert!((fraction_value(&numerator, &denom) - msssim_value(&witns)).abs() < 1e-9);
+
+		let mut other_mb = dst_mb.clone();
+		other_mb[0] ^= 1;
+		assert_eq!(msssim.verify_proof(&pvk, &proof, &other_mb, (numerator, denom)), Some(false));
+	}
+}
@@ -0,0 +1,274 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+extern crate bellperson;
+extern crate paired;
+extern crate rand;
+use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+use paired::{Engine};
+use paired::bls12_381::{Bls12, Fr};
+
+use ff::{Field, PrimeField};
+use fil_sapling_crypto::circuit::boolean;
+use rand::{Rng, thread_rng};
+use super::pixel;
+use super::gadget::{absdiff, less_or_equal, range_check, sum_vec, witness_fr};
+use super::mb_mse::{block_pair_inputs, gen_input_sample};
+
+// We're going to use the Groth16 proving system.
+use self::bellperson::groth16::{
+	Proof,
+	Parameters,
+	PreparedVerifyingKey,
+	generate_random_parameters,
+	prepare_verifying_key,
+	create_random_proof,
+	verify_proof,
+};
+
+/// Bits of the range check between the SAD and the public bound.
+/// Bounds must be below 2^SAD_BOUND_BITS.
+pub const SAD_BOUND_BITS: usize = 32;
+
+/// Metadata stored along with the SAD CRS
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+pub struct SadCrsMeta {
+	pub window: u32,
+	pub bit_depth: u32,
+}
+
+/// Sum of absolute differences of a macroblock pair, proven below a public bound.
+/// Public inputs are the packed source pixels, the packed destination pixels and the bound, in that order.
+/// Blocks and bound are `None` during setup.
+#[derive(Clone)]
+pub struct Sad<E: Engine> {
+	pub src_mb: Option<Vec<u32>>,
+	pub dst_mb: Option<Vec<u32>>,
+	pub mb_size: usize,
+	/// Bits of a pixel difference. Range checking every absolute difference makes a wrong sign witness unsatisfiable.
+	pub bit_depth: u32,
+	pub bound: Option<u64>,
+	phantom: std::marker::PhantomData<E>,
+}
+
+impl <E: Engine> Circuit<E> for Sad<E> {
+	fn synthesize<CS: ConstraintSystem<E>>(
+		self,
+		cs: &mut CS
+	) -> Result<(), SynthesisError>
+	{
+		sad_circuit(cs.namespace(|| "sad"), self)?;
+		Ok(())
+	}
+}
+
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct SadWitness {
+	pub sad: u64,
+	/// Public upper bound on sad
+	pub bound: u64,
+}
+
+/// Returns the allocated sad
+pub fn sad_circuit<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	c: Sad<E>,
+) -> Result<pixel::AllocatedPixel<E>, SynthesisError> {
+	let mb_size = c.mb_size;
+	let circ_src = gen_input_sample(cs.namespace(|| "src mb"), &c.src_mb, mb_size, c.bit_depth)?;
+	let circ_dst = gen_input_sample(cs.namespace(|| "dst mb"), &c.dst_mb, mb_size, c.bit_depth)?;
+
+	let mut circ_abs_diff: Vec<pixel::AllocatedPixel<E>> = Vec::new();
+	for i in 0..mb_size {
+		let mut cs = cs.namespace(|| format!("diff {}", i));
+		let sign = match (&c.src_mb, &c.dst_mb) {
+			(Some(src), Some(dst)) => Some(src[i] <= dst[i]),
+			_ => None,
+		};
+		let sign = boolean::AllocatedBit::alloc(cs.namespace(|| "sign"), sign)?;
+		let diff = absdiff(cs.namespace(|| "absdiff"), &circ_src[i], &circ_dst[i], sign)?;
+		range_check(cs.namespace(|| "absdiff range"), &diff, c.bit_depth as usize)?;
+		circ_abs_diff.push(diff);
+	}
+
+	let circ_sad = sum_vec(cs.namespace(|| "sad sum"), &circ_abs_diff, &vec![1; mb_size])?;
+
+	let circ_bound = pixel::AllocatedPixel::alloc(cs.namespace(|| "bound"), || {
+		witness_fr::<E>(c.bound)
+	})?;
+	less_or_equal(cs.namespace(|| "sad bound"), &circ_sad, &circ_bound, SAD_BOUND_BITS)?;
+	circ_bound.inputize(cs.namespace(|| "bound input"))?;
+
+	Ok(circ_sad)
+}
+
+pub fn gen_witness(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, bound: u64) -> SadWitness {
+	let sad: u64 = src_mb.iter().zip(dst_mb.iter())
+		.map(|(x, y)| if x > y { (x - y) as u64 } else { (y - x) as u64 })
+		.sum();
+	SadWitness {
+		sad,
+		bound,
+	}
+}
+
+/// Wrapper for SAD API
+pub struct SadApp {
+	mb_size: usize,
+	bit_depth: u32,
+}
+
+impl SadApp {
+	pub fn new(mb_size: usize, bit_depth: u32) -> Self {
+		SadApp {
+			mb_size,
+			bit_depth,
+		}
+	}
+}
+
+impl Default for SadApp {
+	fn default() -> Self {
+		SadApp::new(256, pixel::DEFAULT_BIT_DEPTH)
+	}
+}
+
+/// A trait that makes it easy to implement SAD API
+pub trait SadApi<'a, C: Circuit<Bls12>>: Default {
+	/// The name of the application. Used for identifying caches.
+	fn name() -> String;
+
+	/// Generate groth parameters. They depend only on the block size and the bit depth.
+	fn setup(
+		&mut self,
+	) -> Parameters<Bls12>;
+
+	fn create_proof(
+		&mut self,
+		groth_params: &Parameters<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		bound: u64,
+	) -> Proof<Bls12>;
+
+	/// Verify the given proof, return `None` if not implemented.
+	fn verify_proof(
+		&mut self,
+		pvk: &PreparedVerifyingKey<Bls12>,
+		proof: &Proof<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		bound: u64) -> Option<bool>;
+}
+
+impl<'a> SadApi<'a, Sad<Bls12>> for SadApp {
+	fn name() -> String {
+		"Sad".to_string()
+	}
+
+	fn setup(
+		&mut self,
+	) -> Parameters<Bls12> {
+		let rng = &mut thread_rng();
+		let c = Sad::<Bls12> {
+			src_mb: None,
+			dst_mb: None,
+			mb_size: self.mb_size,
+			bit_depth: self.bit_depth,
+			bound: None,
+			phantom: Default::default(),
+		};
+		generate_random_parameters(c, rng).unwrap()
+	}
+
+	fn create_proof(
+		&mut self,
+		groth_params: &Parameters<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		bound: u64,
+	) -> Proof<Bls12> {
+		let rng = &mut thread_rng();
+		let c = Sad::<Bls12> {
+			src_mb: Some(src_pixel),
+			dst_mb: Some(dst_pixel),
+			mb_size: self.mb_size,
+			bit_depth: self.bit_depth,
+			bound: Some(bound),
+			phantom: Default::default(),
+		};
+		create_random_proof(c, groth_params, rng).expect("failed to create proof")
+	}
+
+	fn verify_proof(
+		&mut self,
+		pvk: &PreparedVerifyingKey<Bls12>,
+		proof: &Proof<Bls12>,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		bound: u64,
+	) -> Option<bool> {
+		let expected_inputs = block_pair_inputs(&src_pixel, &dst_pixel, self.bit_depth, bound)?;
+		Some(verify_proof(pvk, proof, &expected_inputs).expect("failed to verify proof"))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::test_helper::gen_mb;
+	use storage_proofs::circuit::test::*;
+
+	#[test]
+	fn test_sad_bound() {
+		// fixed blocks differing by 3 everywhere, so the bound below the SAD exists
+		let src_mb: Vec<u32> = (0..256).map(|i| (i * 37) % 256).collect();
+		let dst_mb: Vec<u32> = src_mb.iter().map(|x| if *x > 3 { x - 3 } else { x + 3 }).collect();
+		let witns = gen_witness(&src_mb, &dst_mb, 0);
+		assert_eq!(witns.sad, 3 * 256);
+
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let c = Sad::<Bls12> {
+			src_mb: Some(src_mb.clone()),
+			dst_mb: Some(dst_mb.clone()),
+			mb_size: 256,
+			bit_depth: pixel::DEFAULT_BIT_DEPTH,
+			bound: Some(witns.sad),
+			phantom: Default::default(),
+		};
+		let circ_sad = sad_circuit(cs.namespace(|| "sad"), c).unwrap();
+		assert!(cs.is_satisfied());
+		assert_eq!(circ_sad.get_value().unwrap(), Fr::from_repr(witns.sad.into()).unwrap());
+
+		// A bound below the SAD cannot be met
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let c = Sad::<Bls12> {
+			src_mb: Some(src_mb),
+			dst_mb: Some(dst_mb),
+			mb_size: 256,
+			bit_depth: pixel::DEFAULT_BIT_DEPTH,
+			bound: Some(witns.sad - 1),
+			phantom: Default::default(),
+		};
+		sad_circuit(cs.namespace(|| "sad"), c).unwrap();
+		assert!(!cs.is_satisfied());
+	}
+
+	#[test]
+	fn test_sad_proof() {
+		let mb_size = 16;
+		let mut sad = SadApp::new(mb_size, pixel::DEFAULT_BIT_DEPTH);
+		let groth_params = sad.setup();
+
+		let src_mb = gen_mb(mb_size, pixel::DEFAULT_BIT_DEPTH);
+		let dst_mb = gen_mb(mb_size, pixel::DEFAULT_BIT_DEPTH);
+		let witns = gen_witness(&src_mb, &dst_mb, 0);
+		let proof = sad.create_proof(&groth_params, src_mb.clone(), dst_mb.clone(), witns.sad + 10);
+
+		let pvk = prepare_verifying_key(&groth_params.vk);
+		assert_eq!(sad.verify_proof(&pvk, &proof, src_mb.clone(), dst_mb.clone(), witns.sad + 10), Some(true));
+		assert_eq!(sad.verify_proof(&pvk, &proof, src_mb, dst_mb, witns.sad), Some(false));
+	}
+}
@@ -10,8 +10,13 @@ use paired::Engine;
 use std::marker::PhantomData;
 
 use super::pixel::*;
+use super::challenge::{self, MbPosition};
+use super::source_commit;
+use super::gadget::{self, absdiff, div_const, mul, range_check, sqrt_floor, sum_vec, sum_vec_enforce, witness_fr};
+use super::resample::{self, ResampleRatio};
 use ff::{BitIterator, Field, PrimeField, PrimeFieldRepr};
 use fil_sapling_crypto::circuit::{boolean, multipack, num, pedersen_hash};
+use fil_sapling_crypto::jubjub::JubjubEngine;
 
 use rand::{Rng, SeedableRng, XorShiftRng, thread_rng};
 use std::sync::{Arc, RwLock};
@@ -22,434 +27,316 @@ use self::bellperson::groth16::{
 	create_random_proof, generate_random_parameters, prepare_verifying_key, verify_proof, Proof, Parameters, PreparedVerifyingKey
 };
 
-#[derive(Clone)]
-pub struct Ssim<E: Engine> {
-	src_mb: Vec<u32>, 
-	dst_mb: Vec<u32>,	
-	pub witns: Witness,
-	phantom: PhantomData<E>,	
-}
+/// Supported SSIM window (macroblock) dimensions. 11x11 is the window used by reference SSIM.
+pub const SSIM_WINDOW_SIZES: [u32; 5] = [4, 8, 11, 16, 32];
 
-impl<E: Engine> Circuit<E> for Ssim<E> {
+/// Weight of the centre pixel of a Gaussian window. Other weights are scaled and rounded from it.
+const GAUSSIAN_CENTER_WEIGHT: f64 = 16.0;
 
-	fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
-		let (circ_ssim_numerator, circ_ssim_denom) = ssim_circuit(cs.namespace(|| "ssim"), self).unwrap();
-		Ok(())
-	}
-	
+/// SSIM window. Holds the block dimension and the integer weight of every pixel in it,
+/// all ones for the plain block window.
+#[derive(Clone)]
+pub struct SsimWindow {
+	pub size: u32,
+	pub gaussian: bool,
+	pub weights: Vec<u32>,
 }
 
-pub fn sum_vec<E: Engine,  CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	a: &Vec<AllocatedPixel<E>>,
-) -> Result<AllocatedPixel<E>, SynthesisError>
-{
-	let num_value = AllocatedPixel::alloc(cs.namespace(|| "sum"), || {
-		let mb_size = a.len();
-		let mut value = E::Fr::zero();
-		for i in 0..mb_size {
-			let pix = a[i].get_value();
-			value.add_assign(&pix.unwrap());
+impl SsimWindow {
+	pub fn new(size: u32, gaussian: bool) -> Self {
+		let weights = if gaussian {
+			gaussian_weights(size)
+		} else {
+			vec![1; (size * size) as usize]
+		};
+		SsimWindow {
+			size,
+			gaussian,
+			weights,
 		}
-		Ok(value)
-	})?;
-	sum_vec_enforce(cs.namespace(|| "sum enforce"), || "sum enforce", &a, &num_value);
-
-	Ok(num_value)
-}
+	}
 
-pub fn sum_vec_enforce<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-	mut cs:  CS,
-	annotation: A,
-	a: &Vec<AllocatedPixel<E>>,
-	sum: &AllocatedPixel<E>,
-) where
-	A: FnOnce() -> AR,
-	AR: Into<String>,
-{
-	cs.enforce(
-		annotation,
-		|mut lc| {
-			for x in a.iter() {
-				lc = lc + x.get_variable()
-			}
-			lc
-		},
-		|lc| lc + CS::one(),
-		|lc| lc + sum.get_variable(),
-	);
-}
+	/// Number of pixels in the window
+	pub fn mb_size(&self) -> usize {
+		(self.size * self.size) as usize
+	}
 
-/// Adds a constraint to CS, enforcing a divide relation as multiplicaion.
-/// quotient * denom = numerator - reminder
-pub fn div_constraint_enforce<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	annotation: A,
-	numerator: &AllocatedPixel<E>,
-	denom: &AllocatedPixel<E>,
-	quotient: &AllocatedPixel<E>,
-	reminder: &AllocatedPixel<E>,
-) where
-	A: FnOnce() -> AR,
-	AR: Into<String>,
-{
-	//  div_constraint * num_elems = sum
+	/// Sum of the pixel weights. Equals the number of pixels for the block window.
+	pub fn total_weight(&self) -> u32 {
+		self.weights.iter().sum()
+	}
 
-	cs.enforce(
-		annotation,
-		|lc| { lc + quotient.variable },	
-		|lc| { lc + denom.variable },
-		|lc| { lc + numerator.variable -  reminder.variable},
-	);
+	/// Window of the 4:2:0 chroma planes, half the size per side
+	pub fn chroma(&self) -> Self {
+		SsimWindow::new(self.size / 2, self.gaussian)
+	}
 }
 
-
-/// Implements div (i.e. (numerator - reminder) / denom = quotient ) as mul (div_constraint * n = sum)
-pub fn div_constraint<E: Engine, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	numerator: &AllocatedPixel<E>,
-	numerator_u64: u64,
-	denom_u64: u64,
-) -> Result<AllocatedPixel<E>, SynthesisError>
-{
-	let num_samples = AllocatedPixel::alloc(cs.namespace(|| "div"), || {
-		let value: E::Fr = (E::Fr::from_repr((denom_u64 as u64).into())).unwrap();
-		Ok(value)
-	})?;
-	
-	let quotient = AllocatedPixel::alloc(cs.namespace(|| "quotient"), || {
-		let mean_val: u64 = numerator_u64 / denom_u64 as u64;
-		let value: E::Fr = (E::Fr::from_repr(mean_val.into())).unwrap();
-		Ok(value)
-	})?;
-	
-
-	let reminder = AllocatedPixel::alloc(cs.namespace(|| "rem"), || {
-		let reminder: u64 = numerator_u64 % denom_u64 as u64;//sum.get_value();
-		let value: E::Fr = (E::Fr::from_repr(reminder.into())).unwrap();
-		Ok(value)
-	})?;
-	
-	div_constraint_enforce(cs, || "div_constraint enforce", &numerator, &num_samples, &quotient, &reminder);
-
-	Ok(quotient)
+/// Integer approximation of the circular Gaussian window of reference SSIM.
+/// sigma is 1.5 for the 11x11 window and scales with the window size.
+pub fn gaussian_weights(size: u32) -> Vec<u32> {
+	let sigma = 1.5 * size as f64 / 11.0;
+	let center = (size as f64 - 1.0) / 2.0;
+	let mut weights = Vec::with_capacity((size * size) as usize);
+	for y in 0..size {
+		for x in 0..size {
+			let dx = x as f64 - center;
+			let dy = y as f64 - center;
+			let w = GAUSSIAN_CENTER_WEIGHT * (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp();
+			weights.push(w.round() as u32);
+		}
+	}
+	weights
 }
 
+/// Per-plane weights of the combined YUV SSIM. They are fixed at setup.
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Copy, Debug)]
+pub struct YuvWeights {
+	pub y: u32,
+	pub u: u32,
+	pub v: u32,
+}
 
-pub fn sqrt_constraint_enforce<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	annotation: A,
-	sqr: &AllocatedPixel<E>,
-	sqrt: &AllocatedPixel<E>,
-	fract: &AllocatedPixel<E>,
-) where
-	A: FnOnce() -> AR,
-	AR: Into<String>,
-{
-	//  div_constraint * num_elems = sum
+impl YuvWeights {
+	pub fn total(&self) -> u32 {
+		self.y + self.u + self.v
+	}
 
-	cs.enforce(
-		annotation,
-		|lc| { lc + sqrt.variable },	
-		|lc| { lc + sqrt.variable },
-		|lc| { lc + sqr.variable -  fract.variable},
-	);
+	/// Parses "wy:wu:wv", e.g. "4:1:1"
+	pub fn parse(s: &str) -> Option<Self> {
+		let w: Vec<u32> = s.split(':').map(|x| x.parse::<u32>().ok()).collect::<Option<Vec<u32>>>()?;
+		if w.len() != 3 || w.iter().sum::<u32>() == 0 {
+			return None;
+		}
+		Some(YuvWeights { y: w[0], u: w[1], v: w[2] })
+	}
 }
-/// Implements div (i.e. (numerator - reminder) / denom = quotient ) as mul (div_constraint * n = sum)
-pub fn sqrt_constraint<E: Engine, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	sqr: &AllocatedPixel<E>,
-	sqrt_u64: u64,
-	fract_u64: u64,
-) -> Result<AllocatedPixel<E>, SynthesisError>
-{
-	let squrt = AllocatedPixel::alloc(cs.namespace(|| "squrt"), || {
-		let value: E::Fr = (E::Fr::from_repr((sqrt_u64 as u64).into())).unwrap();
-		Ok(value)
-	})?;
-	
-	let fract = AllocatedPixel::alloc(cs.namespace(|| "quotient"), || {
-		let value: E::Fr = (E::Fr::from_repr(fract_u64.into())).unwrap();
-		Ok(value)
-	})?;
-	
-	sqrt_constraint_enforce(cs, || "sqrt_constraint enforce", &sqr, &squrt, &fract);
 
-	Ok(squrt)
+impl Default for YuvWeights {
+	/// Weights proportional to the number of pixels of each 4:2:0 plane
+	fn default() -> Self {
+		YuvWeights { y: 4, u: 1, v: 1 }
+	}
 }
 
-/// Adds a constraint to CS, enforcing an equality relationship between the allocated numbers a and b.
-///
-/// a == b
-#[allow(dead_code)]
-pub fn equal<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-	cs: &mut CS,
-	annotation: A,
-	a: &AllocatedPixel<E>,
-	b: &AllocatedPixel<E>,
-) where
-	A: FnOnce() -> AR,
-	AR: Into<String>,
-{
-	// a * 1 = b
-	cs.enforce(
-		annotation,
-		|lc| lc + a.get_variable(),
-		|lc| lc + CS::one(),
-		|lc| lc + b.get_variable(),
-	);
+/// Luma statistic of the SSIM circuit that can be made public
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SsimStat {
+	SumX,
+	SumY,
+	SigmaX,
+	SigmaY,
+	SigmaXy,
+	SsimNumerator,
+	SsimDenom,
+	SsimMNumerator,
+	SsimMDenom,
 }
 
-/// Adds a constraint to CS, enforcing a difference relationship between the allocated numbers a, b, and difference.
-///
-/// a - b = difference
-/// 
-#[allow(dead_code)]
-pub fn difference<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-	cs: &mut CS,
-	annotation: A,
-	a: &AllocatedPixel<E>,
-	b: &AllocatedPixel<E>,
-	difference: &AllocatedPixel<E>,
-) where
-	A: FnOnce() -> AR,
-	AR: Into<String>,
-{
-	//    difference = a-b
-	// => difference + b = a
-	// => (difference + b) * 1 = a
-	cs.enforce(
-		annotation,
-		|lc| lc + difference.get_variable() + b.get_variable(),
-		|lc| lc + CS::one(),
-		|lc| lc + a.get_variable(),
-	);
-}
+pub const SSIM_STATS: [SsimStat; 9] = [
+	SsimStat::SumX,
+	SsimStat::SumY,
+	SsimStat::SigmaX,
+	SsimStat::SigmaY,
+	SsimStat::SigmaXy,
+	SsimStat::SsimNumerator,
+	SsimStat::SsimDenom,
+	SsimStat::SsimMNumerator,
+	SsimStat::SsimMDenom,
+];
+
+impl SsimStat {
+	/// Name of the statistic, the `Witness` field holding its value
+	pub fn name(&self) -> &'static str {
+		match self {
+			SsimStat::SumX => "sum_x",
+			SsimStat::SumY => "sum_y",
+			SsimStat::SigmaX => "sigma_x",
+			SsimStat::SigmaY => "sigma_y",
+			SsimStat::SigmaXy => "sigma_xy",
+			SsimStat::SsimNumerator => "ssim_numerator",
+			SsimStat::SsimDenom => "ssim_denom",
+			SsimStat::SsimMNumerator => "ssim_m_numerator",
+			SsimStat::SsimMDenom => "ssim_m_denom",
+		}
+	}
 
-// From storage-proofs/src/circuit/sloths.rs
-// Allocates a new variable as difference of two variables
-#[allow(dead_code)]
-fn sub<E: Engine, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	a: &AllocatedPixel<E>,
-	b: &AllocatedPixel<E>,
-) -> Result<AllocatedPixel<E>, SynthesisError> {
-	let res = AllocatedPixel::alloc(cs.namespace(|| "sub num"), || {
-		let mut tmp = a
-			.get_value()
-			.ok_or_else(|| SynthesisError::AssignmentMissing)?;
-		tmp.sub_assign(
-			&b.get_value()
-				.ok_or_else(|| SynthesisError::AssignmentMissing)?,
-		);
-
-		Ok(tmp)
-	})?;
+	pub fn parse(name: &str) -> Option<Self> {
+		SSIM_STATS.iter().find(|stat| stat.name() == name).cloned()
+	}
 
-	// a - b = res
-	difference(&mut cs, || "subtraction constraint", &a, &b, &res);
+	pub fn value(&self, witns: &Witness) -> u64 {
+		match self {
+			SsimStat::SumX => witns.sum_x,
+			SsimStat::SumY => witns.sum_y,
+			SsimStat::SigmaX => witns.sigma_x,
+			SsimStat::SigmaY => witns.sigma_y,
+			SsimStat::SigmaXy => witns.sigma_xy,
+			SsimStat::SsimNumerator => witns.ssim_numerator,
+			SsimStat::SsimDenom => witns.ssim_denom,
+			SsimStat::SsimMNumerator => witns.ssim_m_numerator,
+			SsimStat::SsimMDenom => witns.ssim_m_denom,
+		}
+	}
 
-	Ok(res)
+	fn allocated<'p, E: Engine>(&self, plane: &'p SsimPlane<E>) -> &'p AllocatedPixel<E> {
+		match self {
+			SsimStat::SumX => &plane.sum_x,
+			SsimStat::SumY => &plane.sum_y,
+			SsimStat::SigmaX => &plane.sigma_x,
+			SsimStat::SigmaY => &plane.sigma_y,
+			SsimStat::SigmaXy => &plane.sigma_xy,
+			SsimStat::SsimNumerator => &plane.ssim_numerator,
+			SsimStat::SsimDenom => &plane.ssim_denom,
+			SsimStat::SsimMNumerator => &plane.ssim_m_numerator,
+			SsimStat::SsimMDenom => &plane.ssim_m_denom,
+		}
+	}
 }
 
-/// Adds a constraint to CS, enforcing a difference square relationship between the allocated numbers a, b, and difference.
-/// (a - b)^2 = difference^2
-#[allow(dead_code)]
-pub fn diff_sq_enforce<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-	cs: &mut CS,
-	annotation: A,
-	a: &AllocatedPixel<E>,
-	b: &AllocatedPixel<E>,
-	diffsq: &AllocatedPixel<E>,
-) where
-	A: FnOnce() -> AR,
-	AR: Into<String>,
-{
-	//  a-b * a-b = diffsq
-
-	cs.enforce(
-		annotation,
-		|lc| lc + a.get_variable() - b.get_variable(),
-		|lc| lc + a.get_variable() - b.get_variable(),
-		|lc| lc + diffsq.get_variable(),
-	);
+/// Luma statistics exposed as public inputs, in the order the circuit inputizes them.
+/// It is fixed at setup and stored in the CRS metadata, verifiers build their inputs from it.
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublicInputSchema {
+	pub stats: Vec<SsimStat>,
 }
 
-// From storage-proofs/src/circuit/sloths.rs
-// Allocates a new variable as difference of two variables
-#[allow(dead_code)]
-fn diffsq<E: Engine, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	a: &AllocatedPixel<E>,
-	b: &AllocatedPixel<E>,
-) -> Result<AllocatedPixel<E>, SynthesisError> {
-	let res = AllocatedPixel::alloc(cs.namespace(|| "diffsq"), || {
-		let mut tmp = a
-			.get_value()
-			.ok_or_else(|| SynthesisError::AssignmentMissing)?;
-		tmp.sub_assign(
-			&b.get_value()
-				.ok_or_else(|| SynthesisError::AssignmentMissing)?,
-		);
-		tmp.square();
-		Ok(tmp)
-	})?;
+impl PublicInputSchema {
+	/// Parses a comma separated list of statistic names, e.g. "sum_y,ssim_m_numerator,ssim_m_denom"
+	pub fn parse(s: &str) -> Option<Self> {
+		let stats = s.split(',').map(SsimStat::parse).collect::<Option<Vec<SsimStat>>>()?;
+		if stats.iter().enumerate().any(|(i, stat)| stats[..i].contains(stat)) {
+			return None;
+		}
+		Some(PublicInputSchema { stats })
+	}
 
-	// (a - b) = res
-	diff_sq_enforce(&mut cs, || "diffsq constraint", &a, &b, &res);
+	pub fn names(&self) -> Vec<&'static str> {
+		self.stats.iter().map(|stat| stat.name()).collect()
+	}
 
-	Ok(res)
+	/// Public input values of a witness
+	pub fn values(&self, witns: &Witness) -> Vec<u64> {
+		self.stats.iter().map(|stat| stat.value(witns)).collect()
+	}
 }
 
-/// Adds a constraint to CS, enforcing a difference square relationship between the allocated numbers a, b, and difference.
-/// (a - b)^2 = difference^2
-#[allow(dead_code)]
-pub fn diff_mul_enforce<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-	cs: &mut CS,
-	annotation: A,
-	a: &AllocatedPixel<E>,
-	mean_a: &AllocatedPixel<E>,
-	b: &AllocatedPixel<E>,
-	mean_b: &AllocatedPixel<E>,
-	res: &AllocatedPixel<E>,
-) where
-	A: FnOnce() -> AR,
-	AR: Into<String>,
-{
-	//  a-mean_a * b-mean_b = res
-
-	cs.enforce(
-		annotation,
-		|lc| lc + a.get_variable() - mean_a.get_variable(),
-		|lc| lc + b.get_variable() - mean_b.get_variable(),
-		|lc| lc + res.get_variable(),
-	);
+impl Default for PublicInputSchema {
+	fn default() -> Self {
+		PublicInputSchema {
+			stats: vec![SsimStat::SumY, SsimStat::SigmaY, SsimStat::SsimNumerator, SsimStat::SsimDenom],
+		}
+	}
 }
-// From storage-proofs/src/circuit/sloths.rs
-// Allocates a new variable as difference of two variables
-#[allow(dead_code)]
-fn diffmul<E: Engine, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	a: &AllocatedPixel<E>,
-	mean_a: &AllocatedPixel<E>,
-	b: &AllocatedPixel<E>,
-	mean_b: &AllocatedPixel<E>,
-) -> Result<AllocatedPixel<E>, SynthesisError> {
-	let res = AllocatedPixel::alloc(cs.namespace(|| "diffmul"), || {
-		let mut tmpa = a.get_value().ok_or_else(|| SynthesisError::AssignmentMissing)?;
-		tmpa.sub_assign(&mean_a.get_value().ok_or_else(|| SynthesisError::AssignmentMissing)?,);
-		let mut tmpb = b.get_value().ok_or_else(|| SynthesisError::AssignmentMissing)?;
-		tmpb.sub_assign(&mean_b.get_value().ok_or_else(|| SynthesisError::AssignmentMissing)?,);
-		let mut tmp = tmpa;
-		tmp.mul_assign(&tmpb);
-		Ok(tmp)
-	})?;
-	// ()a - mean_a) * ()b - mean_b) = res
-	diff_mul_enforce(&mut cs,|| "diffsq constraint", &a, &mean_a, &b, &mean_b, &res,);
 
-	Ok(res)
+/// 4:2:0 chroma blocks of a source and destination macroblock pair
+#[derive(Clone)]
+pub struct ChromaBlocks {
+	pub src_u: Vec<u32>,
+	pub src_v: Vec<u32>,
+	pub dst_u: Vec<u32>,
+	pub dst_v: Vec<u32>,
 }
 
-//=======
-
-pub fn mul_enforce<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-	cs: &mut CS,
-	annotation: A,
-	a: &AllocatedPixel<E>,
-	b: &AllocatedPixel<E>,
-	res: &AllocatedPixel<E>,
-) where
-	A: FnOnce() -> AR,
-	AR: Into<String>,
-{
-	//  a-mean_a * b-mean_b = res
-
-	cs.enforce(
-		annotation,
-		|lc| lc + a.get_variable(),
-		|lc| lc + b.get_variable(),
-		|lc| lc + res.get_variable(),
-	);
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+pub struct ChromaWitness {
+	pub u: Witness,
+	pub v: Witness,
 }
 
-
-fn mul<E: Engine, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	a: &AllocatedPixel<E>,
-	b: &AllocatedPixel<E>,
-) -> Result<AllocatedPixel<E>, SynthesisError> {
-	let res = AllocatedPixel::alloc(cs.namespace(|| "mul"), || {
-		let mut tmpa = a.get_value().ok_or_else(|| SynthesisError::AssignmentMissing)?;
-		let mut tmpb = b.get_value().ok_or_else(|| SynthesisError::AssignmentMissing)?;
-		tmpa.mul_assign(&tmpb);
-		Ok(tmpa)
-	})?;
-	// ()a - mean_a) * ()b - mean_b) = res
-	mul_enforce(&mut cs,|| "diffsq constraint", &a, &b, &res,);
-
-	Ok(res)
+/// Metadata stored along with the SSIM CRS. Proofs can only be generated for blocks matching it.
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone)]
+pub struct SsimCrsMeta {
+	pub window: u32,
+	pub gaussian: bool,
+	/// Depth of the merkle tree of the source frame commitment
+	pub tree_depth: u32,
+	/// Source to destination resolution ratio
+	pub ratio: ResampleRatio,
+	/// Plane weights of the combined YUV SSIM, `None` for luma only proofs
+	pub chroma: Option<YuvWeights>,
+	/// Bits per pixel of source and destination blocks
+	pub bit_depth: u32,
+	/// Public luma statistics of the proofs
+	pub public_inputs: PublicInputSchema,
 }
 
+/// SSIM stabilising constants C1 = (0.01 L)^2, C2 = (0.03 L)^2 and C3 = C2 / 2, rounded to integers,
+/// with L the largest pixel value of the bit depth. They are fixed by the circuit and known at setup.
+pub fn ssim_constants(bit_depth: u32) -> (u64, u64, u64) {
+	let l = max_pixel_value(bit_depth) as u64;
+	let c1 = (l * l + 5000) / 10000;
+	let c2 = (9 * l * l + 5000) / 10000;
+	(c1, c2, c2 / 2)
+}
 
-pub fn absdiff_enforce<E: Engine, A, AR, CS: ConstraintSystem<E>>(
-	cs: &mut CS,
-	annotation: A,
-	a: &AllocatedPixel<E>,
-	mean_a: &AllocatedPixel<E>,
-	absdiff: &AllocatedPixel<E>,
-) where
-	A: FnOnce() -> AR,
-	AR: Into<String>,
-{
-
-	cs.enforce(
-		annotation,
-		|lc| lc + a.get_variable() - mean_a.get_variable(),
-		|lc| lc + CS::one(),
-		|lc| lc + absdiff.get_variable(),
-	);
+/// SSIM circuit. Blocks, witness and source path are `None` during setup.
+/// The source block is private; it is bound to the publisher's frame commitment through
+/// `src_auth_path` and `src_root`. For renditions of a lower resolution the source block covers
+/// `ratio.src_side(window.size)` pixels per side and is resampled onto the window in-circuit.
+/// The luma statistics of `public_inputs` are exposed first. With `chroma`, the U and V blocks are compared too
+/// and the weighted YUV SSIM is exposed after the luma inputs.
+/// The challenged position is exposed last and must match the position of the source block in the commitment.
+#[derive(Clone)]
+pub struct Ssim<'a, E: JubjubEngine> {
+	src_mb: Option<Vec<u32>>, 
+	dst_mb: Option<Vec<u32>>,	
+	window: SsimWindow,
+	ratio: ResampleRatio,
+	/// Bits per pixel, every private pixel is range checked to it
+	bit_depth: u32,
+	public_inputs: PublicInputSchema,
+	pub witns: Option<Witness>,
+	chroma: Option<YuvWeights>,
+	chroma_mb: Option<ChromaBlocks>,
+	chroma_witns: Option<ChromaWitness>,
+	/// Authentication path of the source block in the frame commitment, one entry per tree level
+	src_auth_path: Vec<Option<(E::Fr, bool)>>,
+	/// Frame commitment of the publisher
+	src_root: Option<E::Fr>,
+	/// Challenged frame and macroblock
+	position: Option<MbPosition>,
+	params: &'a E::Params,
 }
 
-fn absdiff<E: Engine, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	a: &AllocatedPixel<E>,
-	mean_a: &AllocatedPixel<E>,
-	sign: boolean::AllocatedBit,
-) -> Result<AllocatedPixel<E>, SynthesisError> {
-	//print!("sign={:?}\n", boolean::Boolean::Is(sign.clone()).get_value());	
-	let (c, d) = AllocatedPixel::conditionally_reverse(&mut cs, &a, &mean_a,  &boolean::Boolean::Is(sign)).unwrap();
-	let res = AllocatedPixel::alloc(cs.namespace(|| "absdiff"), || {
-		let mut tmp = c.get_value().ok_or_else(|| SynthesisError::AssignmentMissing)?;
-		tmp.sub_assign(&d.get_value().ok_or_else(|| SynthesisError::AssignmentMissing)?,);
-		//print!("absdiff c={:?} d={:?} a={:} mean={:}\n",  c.get_value().unwrap(), d.get_value().unwrap(), a.get_value().unwrap(), mean_a.get_value().unwrap());
-		Ok(tmp)
-	})?;
-	// ()a - mean_a) * ()b - mean_b) = res
-	absdiff_enforce(&mut cs,|| "diffsq constraint", &c, &d, &res,);
+impl<'a, E: JubjubEngine> Circuit<E> for Ssim<'a, E> {
 
-	Ok(res)
+	fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+		let (circ_ssim_numerator, circ_ssim_denom) = ssim_circuit(cs.namespace(|| "ssim"), self)?;
+		Ok(())
+	}
+	
 }
 
+/// Absolute differences to the mean, range checked to `bit_depth` bits so the witness signs are binding
 pub fn absdiff_vec<E: Engine, CS: ConstraintSystem<E>>(
 	mut cs: CS,
 	a: &Vec<AllocatedPixel<E>>,
 	mean_a: &AllocatedPixel<E>,
 	sign_a: &Vec<boolean::AllocatedBit>,
-) -> Vec<AllocatedPixel<E>>
+	bit_depth: u32,
+) -> Result<Vec<AllocatedPixel<E>>, SynthesisError>
 {
 	let mb_size = a.len();
 	let mut diff_vec: Vec<_> = Vec::new();
 	for i in 0..mb_size {
-		let abs_diff = absdiff(cs.namespace(|| format!("diff a {}", i)), &a[i], &mean_a, sign_a[i].clone()).unwrap();
+		let abs_diff = absdiff(cs.namespace(|| format!("diff a {}", i)), &a[i], &mean_a, sign_a[i].clone())?;
+		range_check(cs.namespace(|| format!("diff a range {}", i)), &abs_diff, bit_depth as usize)?;
 		diff_vec.push(abs_diff);
 	}
-	diff_vec
+	Ok(diff_vec)
 }
 
 pub fn variance<E: Engine, CS: ConstraintSystem<E>>(
 	mut cs: CS,
 	diff_vec_a: &Vec<AllocatedPixel<E>>,
 	diff_vec_b: &Vec<AllocatedPixel<E>>,	
+	weights: &[u32],
 ) -> Result<AllocatedPixel<E>, SynthesisError>
 {
 	let mb_size = diff_vec_a.len();
@@ -463,7 +350,7 @@ pub fn variance<E: Engine, CS: ConstraintSystem<E>>(
 		let abs_diff_a = &diff_vec_a[i];//absdiff(cs.namespace(|| format!("diff a {}", i)), &a[i], &mean_a, sign_a[i].clone()).unwrap();
 		let abs_diff_b = &diff_vec_b[i];//absdiff(cs.namespace(|| format!("diff b {}", i)), &b[i], &mean_b, sign_b[i].clone()).unwrap();
 		//let value_num = abs_diff_a.mul(cs.namespace(|| format!("diff ab {}", i)), &abs_diff_b).unwrap();
-		let value_num = mul(cs.namespace(|| format!("diff ab {}", i)), &abs_diff_a, &abs_diff_b).unwrap();
+		let value_num = mul(cs.namespace(|| format!("diff ab {}", i)), abs_diff_a, abs_diff_b)?;
 		//print!("variance elem pass1 = {:?}\n", value_num.get_value().unwrap());
 		diff_prod_vec.push(value_num);
 	}
@@ -471,13 +358,13 @@ pub fn variance<E: Engine, CS: ConstraintSystem<E>>(
 	let num_value = AllocatedPixel::alloc(cs.namespace(|| "sum var"), || {
 		let mut value = E::Fr::zero();
 		for i in 0..mb_size {
-			let pix = diff_prod_vec[i].get_value();
-			print!("variance elem pass 2= {:?}\n", pix.unwrap());
-			value.add_assign(&pix.unwrap());
+			let mut pix = diff_prod_vec[i].get_value().ok_or(SynthesisError::AssignmentMissing)?;
+			pix.mul_assign(&E::Fr::from_repr((weights[i] as u64).into()).unwrap());
+			value.add_assign(&pix);
 		}
-		print!("variance value = {:?}\n", value);
 		Ok(value)
 	})?;
+	sum_vec_enforce(cs.namespace(|| "sum var enforce"), || "sum var enforce", &diff_prod_vec, weights, &num_value);
 
 	Ok(num_value)
 }
@@ -486,28 +373,35 @@ pub fn vairance_constraint<E: Engine, CS: ConstraintSystem<E>>(
 	mut cs: CS, 
 	circ_abs_diff_vec_x: &Vec<AllocatedPixel<E>>, 
 	circ_abs_diff_vec_y: &Vec<AllocatedPixel<E>>,
-	witness_sigma_sq_sum: u32,
-	withness_sigma: u32,
-	withness_sigma_frac: u32,
-	) -> (AllocatedPixel<E>, AllocatedPixel<E>, AllocatedPixel<E>) {
-	let witness_num_samples = circ_abs_diff_vec_x.len();
-	let circ_sigma_sq_sum = variance(cs.namespace(|| "covariance sum"), &circ_abs_diff_vec_x, &circ_abs_diff_vec_y).unwrap();
-	let circ_sigma_sq = div_constraint(cs.namespace(|| "sigma sq sum"), &circ_sigma_sq_sum, witness_sigma_sq_sum as u64, witness_num_samples as u64).unwrap();
-	let circ_sigma = sqrt_constraint(cs.namespace(|| "sigma"), &circ_sigma_sq, withness_sigma as u64, withness_sigma_frac as u64).unwrap();
-	(circ_sigma_sq_sum, circ_sigma_sq, circ_sigma)
+	weights: &[u32],
+	witness_sigma_sq_sum: Option<u64>,
+	bit_depth: u32,
+	) -> Result<(AllocatedPixel<E>, AllocatedPixel<E>, AllocatedPixel<E>), SynthesisError> {
+	let witness_num_samples: u32 = weights.iter().sum();
+	let circ_sigma_sq_sum = variance(cs.namespace(|| "covariance sum"), &circ_abs_diff_vec_x, &circ_abs_diff_vec_y, weights)?;
+	let circ_sigma_sq = div_const(cs.namespace(|| "sigma sq sum"), &circ_sigma_sq_sum, witness_sigma_sq_sum, witness_num_samples as u64)?;
+	// the variance is at most the square of the largest pixel value.
+	// sqrt_floor range checks the root and the fraction, which range checks the variance too
+	let circ_sigma = sqrt_floor(cs.namespace(|| "sigma"), &circ_sigma_sq,
+		witness_sigma_sq_sum.map(|s| s / witness_num_samples as u64), 2 * bit_depth as usize)?;
+	Ok((circ_sigma_sq_sum, circ_sigma_sq, circ_sigma))
 }
 
 pub fn covairance_constraint<E: Engine, CS: ConstraintSystem<E>>(
 	mut cs: CS, 
 	circ_abs_diff_vec_x: &Vec<AllocatedPixel<E>>, 
 	circ_abs_diff_vec_y: &Vec<AllocatedPixel<E>>,
-	witness_sigma_xy_sum: u32,
-	withness_sigma: u32,
-	) -> (AllocatedPixel<E>, AllocatedPixel<E>) {
-	let witness_num_samples = circ_abs_diff_vec_x.len();
-	let circ_sigma_xy_sum = variance(cs.namespace(|| "covariance sum"), &circ_abs_diff_vec_x, &circ_abs_diff_vec_y).unwrap();
-	let circ_sigma_xy = div_constraint(cs.namespace(|| "sigma xy sum"), &circ_sigma_xy_sum, witness_sigma_xy_sum as u64, witness_num_samples as u64).unwrap();
-	(circ_sigma_xy_sum, circ_sigma_xy)
+	weights: &[u32],
+	witness_sigma_xy_sum: Option<u64>,
+	withness_sigma: Option<u64>,
+	bit_depth: u32,
+	) -> Result<(AllocatedPixel<E>, AllocatedPixel<E>), SynthesisError> {
+	let witness_num_samples: u32 = weights.iter().sum();
+	let circ_sigma_xy_sum = variance(cs.namespace(|| "covariance sum"), &circ_abs_diff_vec_x, &circ_abs_diff_vec_y, weights)?;
+	let circ_sigma_xy = div_const(cs.namespace(|| "sigma xy sum"), &circ_sigma_xy_sum, witness_sigma_xy_sum, witness_num_samples as u64)?;
+	// the products of absolute differences are below the square of the largest pixel value
+	range_check(cs.namespace(|| "sigma xy range"), &circ_sigma_xy, 2 * bit_depth as usize)?;
+	Ok((circ_sigma_xy_sum, circ_sigma_xy))
 }
 
 ///
@@ -519,18 +413,24 @@ pub fn ssim_lum_or_contrast<E: Engine, CS: ConstraintSystem<E>>(
 	src_mean: &AllocatedPixel<E>,
 	dst_mean: &AllocatedPixel<E>,
 	c1_u64: u64,
-	l_numerator_u64: u64,
-	l_denominator_u64: u64,
+	l_numerator_u64: Option<u64>,
+	l_denominator_u64: Option<u64>,
 ) -> Result<(AllocatedPixel<E>,AllocatedPixel<E>,AllocatedPixel<E>), SynthesisError>
 {
 	let c1 = AllocatedPixel::alloc(cs.namespace(|| "c1"), || {
 		let value: E::Fr = (E::Fr::from_repr((c1_u64 as u64).into())).unwrap();
 		Ok(value)
 	})?;
+	// the constant depends on the bit depth only and is fixed at setup
+	cs.enforce(	|| "enforce c1", 
+		|lc| { lc + c1.variable }, 	
+		|lc| { lc + CS::one() },
+		|lc| { lc + (E::Fr::from_repr(c1_u64.into()).unwrap(), CS::one()) },
+	);
 	
 	let uxuy = AllocatedPixel::alloc(cs.namespace(|| "uxuy"), || {
-		let mut value: E::Fr = src_mean.get_value().unwrap();
-		let mut value2: E::Fr = dst_mean.get_value().unwrap();
+		let mut value: E::Fr = src_mean.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+		let mut value2: E::Fr = dst_mean.get_value().ok_or(SynthesisError::AssignmentMissing)?;
 		value.mul_assign(&value2);		
 		Ok(value)
 	})?;
@@ -541,8 +441,8 @@ pub fn ssim_lum_or_contrast<E: Engine, CS: ConstraintSystem<E>>(
 	);
 		
 	let ux_square = AllocatedPixel::alloc(cs.namespace(|| "ux_square"), || {
-		let mut value: E::Fr = src_mean.get_value().unwrap();
-		let mut value2: E::Fr = src_mean.get_value().unwrap();
+		let mut value: E::Fr = src_mean.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+		let mut value2: E::Fr = src_mean.get_value().ok_or(SynthesisError::AssignmentMissing)?;
 		value.mul_assign(&value2);		
 		Ok(value)
 	})?;
@@ -554,10 +454,9 @@ pub fn ssim_lum_or_contrast<E: Engine, CS: ConstraintSystem<E>>(
 	);
 			
 	let uy_square = AllocatedPixel::alloc(cs.namespace(|| "uy_square"), || {
-		let mut value: E::Fr = dst_mean.get_value().unwrap();
-		let mut value2: E::Fr = dst_mean.get_value().unwrap();
+		let mut value: E::Fr = dst_mean.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+		let mut value2: E::Fr = dst_mean.get_value().ok_or(SynthesisError::AssignmentMissing)?;
 		value.mul_assign(&value2);
-		print!("uy_square {:?}  {:?}  {:?}\n", dst_mean.get_value().unwrap(), value2, value);
 		Ok(value)
 	})?;	
 
@@ -568,14 +467,12 @@ pub fn ssim_lum_or_contrast<E: Engine, CS: ConstraintSystem<E>>(
 	);
 
 	let lum_numerator = AllocatedPixel::alloc(cs.namespace(|| "lum numerator"), || {
-		let value: E::Fr = (E::Fr::from_repr((l_numerator_u64 as u64).into())).unwrap();
-		Ok(value)
+		witness_fr::<E>(l_numerator_u64)
 	})?;	
 	cs.enforce(	|| "enforce lum numerator", 
 		|lc| { 
 			let mut coeff = E::Fr::one();
 			coeff.double();
-			print!("coeff {:?}  \n",  coeff);	
 			lc + (coeff, uxuy.variable) + c1.variable
 		}, 	
 		|lc| { lc + CS::one() },
@@ -583,8 +480,7 @@ pub fn ssim_lum_or_contrast<E: Engine, CS: ConstraintSystem<E>>(
 	);	
 
 	let lum_denom = AllocatedPixel::alloc(cs.namespace(|| "lum denom"), || {
-		let value: E::Fr = (E::Fr::from_repr((l_denominator_u64 as u64).into())).unwrap();
-		Ok(value)
+		witness_fr::<E>(l_denominator_u64)
 	})?;	
 	cs.enforce(	|| "enforce lum denom", 
 		|lc| { lc + ux_square.variable + uy_square.variable + c1.variable}, 	
@@ -601,19 +497,22 @@ pub fn ssim_struct_constraint<E: Engine, CS: ConstraintSystem<E>>(
 	sigma_x: &AllocatedPixel<E>,
 	sigma_y: &AllocatedPixel<E>,
 	witness_c3: u64,
-	witness_s_numerator: u64,
-	witness_s_denominator: u64,
+	witness_s_numerator: Option<u64>,
+	witness_s_denominator: Option<u64>,
 ) -> Result<(AllocatedPixel<E>, AllocatedPixel<E>), SynthesisError>//-> (AllocatedPixel<E>, AllocatedPixel<E>)
 {
 	let circ_c3 = AllocatedPixel::alloc(cs.namespace(|| "c3"), || {
 		let value: E::Fr = (E::Fr::from_repr((witness_c3 as u64).into())).unwrap();
 		Ok(value)
 	})?;
+	cs.enforce(	|| "enforce c3", 
+		|lc| { lc + circ_c3.variable }, 	
+		|lc| { lc + CS::one() },
+		|lc| { lc + (E::Fr::from_repr(witness_c3.into()).unwrap(), CS::one()) },
+	);
 
 	let s_numerator = AllocatedPixel::alloc(cs.namespace(|| "lum numerator"), || {
-		let value: E::Fr = (E::Fr::from_repr((witness_s_numerator as u64).into())).unwrap();
-		print!("s_numerator value = {:?}\n", value);
-		Ok(value)
+		witness_fr::<E>(witness_s_numerator)
 	})?;	
 	cs.enforce(	|| "enforce lum numerator", 
 		|lc| { 
@@ -624,9 +523,7 @@ pub fn ssim_struct_constraint<E: Engine, CS: ConstraintSystem<E>>(
 	);	
 
 	let s_denom = AllocatedPixel::alloc(cs.namespace(|| "s denom"), || {
-		let value: E::Fr = (E::Fr::from_repr((witness_s_denominator as u64).into())).unwrap();
-		print!("s_denom value = {:?}\n", value);
-		Ok(value)
+		witness_fr::<E>(witness_s_denominator)
 	})?;	
 	cs.enforce(	|| "enforce lum denom", 
 		|lc| { lc + sigma_x.variable}, 	
@@ -644,14 +541,13 @@ pub fn ssim_constraint<E: Engine, CS: ConstraintSystem<E>>(
 	circ_c2: &AllocatedPixel<E>,	
 	ssim_l_denom: &AllocatedPixel<E>,
 	ssim_c_denom: &AllocatedPixel<E>,
-	witness_ssim_numerator: u64,
-	witness_ssim_denominator: u64,
+	witness_ssim_numerator: Option<u64>,
+	witness_ssim_denominator: Option<u64>,
 ) -> Result<(AllocatedPixel<E>, AllocatedPixel<E>), SynthesisError>//-> (AllocatedPixel<E>, AllocatedPixel<E>)
 {
 
 	let ssim_numerator = AllocatedPixel::alloc(cs.namespace(|| "ssim numerator"), || {
-		let value: E::Fr = (E::Fr::from_repr((witness_ssim_numerator as u64).into())).unwrap();
-		Ok(value)
+		witness_fr::<E>(witness_ssim_numerator)
 	})?;
 	
 	//ssim_numerator.inputize(cs.namespace(|| "ssim num"))?;
@@ -667,8 +563,7 @@ pub fn ssim_constraint<E: Engine, CS: ConstraintSystem<E>>(
 	);	
 
 	let ssim_denom = AllocatedPixel::alloc(cs.namespace(|| "ssim denom"), || {
-		let value: E::Fr = (E::Fr::from_repr((witness_ssim_denominator as u64).into())).unwrap();
-		Ok(value)
+		witness_fr::<E>(witness_ssim_denominator)
 	})?;
 	
 	//ssim_denom.inputize(cs.namespace(|| "ssim den"))?;
@@ -689,14 +584,13 @@ pub fn ssim_m_constraint<E: Engine, CS: ConstraintSystem<E>>(
 	ssim_l_denom: &AllocatedPixel<E>,
 	sigma_x_sq: &AllocatedPixel<E>,
 	sigma_y_sq: &AllocatedPixel<E>,	
-	witness_ssim_m_numerator: u64,
-	witness_ssim_m_denominator: u64,
+	witness_ssim_m_numerator: Option<u64>,
+	witness_ssim_m_denominator: Option<u64>,
 ) -> Result<(AllocatedPixel<E>, AllocatedPixel<E>), SynthesisError>//-> (AllocatedPixel<E>, AllocatedPixel<E>)
 {
 
 	let ssim_m_numerator = AllocatedPixel::alloc(cs.namespace(|| "ssim numerator"), || {
-		let value: E::Fr = (E::Fr::from_repr((witness_ssim_m_numerator as u64).into())).unwrap();
-		Ok(value)
+		witness_fr::<E>(witness_ssim_m_numerator)
 	})?;
 	
 	//ssim_numerator.inputize(cs.namespace(|| "ssim num"))?;
@@ -712,72 +606,211 @@ pub fn ssim_m_constraint<E: Engine, CS: ConstraintSystem<E>>(
 	);	
 
 	let ssim_m_denom = AllocatedPixel::alloc(cs.namespace(|| "ssim denom"), || {
-		let value: E::Fr = (E::Fr::from_repr((witness_ssim_m_denominator as u64).into())).unwrap();
-		Ok(value)
+		witness_fr::<E>(witness_ssim_m_denominator)
 	})?;
 	
 	//ssim_denom.inputize(cs.namespace(|| "ssim den"))?;
 		
 	cs.enforce(	|| "enforce lum denom", 
 		|lc| { lc + ssim_l_denom.variable}, 	
-		|lc| { lc + sigma_x_sq.variable + sigma_y_sq.variable },
+		|lc| { lc + sigma_x_sq.variable + sigma_y_sq.variable + circ_c2.variable },
 		|lc| { lc + ssim_m_denom.variable},
 	);	
 	Ok((ssim_m_numerator,ssim_m_denom))
 }
 
-pub fn ssim_circuit<E: Engine, CS: ConstraintSystem<E>>(
-	mut cs: CS,
-	c: Ssim::<E>,
-) -> Result<(AllocatedPixel<E>, AllocatedPixel<E>), SynthesisError> {
-	let mut witns = c.witns;
-	let circ_mb_x = gen_sample(cs.namespace(|| "src mb"), c.src_mb.clone());
-	let circ_mb_y = gen_sample(cs.namespace(|| "dst mb"), c.dst_mb.clone());
-	let circ_mb_sum_x = sum_vec(cs.namespace(|| "src sum mb"), &circ_mb_x).unwrap();
+/// Allocated terms of the SSIM of one plane
+pub struct SsimPlane<E: Engine> {
+	pub sum_x: AllocatedPixel<E>,
+	pub sum_y: AllocatedPixel<E>,
+	pub sigma_x: AllocatedPixel<E>,
+	pub sigma_y: AllocatedPixel<E>,
+	pub sigma_xy: AllocatedPixel<E>,
+	pub ssim_numerator: AllocatedPixel<E>,
+	pub ssim_denom: AllocatedPixel<E>,
+	pub ssim_m_numerator: AllocatedPixel<E>,
+	pub ssim_m_denom: AllocatedPixel<E>,
+}
 
-	let circ_mb_sum_y = sum_vec(cs.namespace(|| "dst sum mb"), &circ_mb_y).unwrap();
+/// SSIM of one plane over allocated blocks. `src_mb` and `dst_mb` hold their native values and are `None` during setup.
+pub fn ssim_plane<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	circ_mb_x: &Vec<AllocatedPixel<E>>,
+	circ_mb_y: &Vec<AllocatedPixel<E>>,
+	src_mb: &Option<Vec<u32>>,
+	dst_mb: &Option<Vec<u32>>,
+	window: &SsimWindow,
+	bit_depth: u32,
+	witns: Option<&Witness>,
+) -> Result<SsimPlane<E>, SynthesisError> {
+	let (c1, c2, c3) = ssim_constants(bit_depth);
+	let weights = &window.weights;
+	let mb_size = window.mb_size();
+	let num_samples = window.total_weight();
+	let circ_mb_sum_x = sum_vec(cs.namespace(|| "src sum mb"), &circ_mb_x, weights)?;
+
+	let circ_mb_sum_y = sum_vec(cs.namespace(|| "dst sum mb"), &circ_mb_y, weights)?;
 	
-	let circ_mean_src = div_constraint(cs.namespace(|| "src meant mb"), &circ_mb_sum_x, witns.sum_x as u64, witns.num_samples as u64).unwrap();
-	let circ_mean_dst = div_constraint(cs.namespace(|| "dst meant mb"), &circ_mb_sum_y, witns.sum_y as u64, witns.num_samples as u64).unwrap();
+	let circ_mean_src = div_const(cs.namespace(|| "src meant mb"), &circ_mb_sum_x, witns.map(|w| w.sum_x), num_samples as u64)?;
+	let circ_mean_dst = div_const(cs.namespace(|| "dst meant mb"), &circ_mb_sum_y, witns.map(|w| w.sum_y), num_samples as u64)?;
+	range_check(cs.namespace(|| "src mean range"), &circ_mean_src, bit_depth as usize)?;
+	range_check(cs.namespace(|| "dst mean range"), &circ_mean_dst, bit_depth as usize)?;
 	
 	
-	let (circ_l_numerator, circ_l_denom, c1_crc) = ssim_lum_or_contrast(cs.namespace(|| "ssim lum"), &circ_mean_src, &circ_mean_dst, witns.c1 as u64, witns.l_numerator as u64, witns.l_denom as u64).unwrap();
+	let (circ_l_numerator, circ_l_denom, c1_crc) = ssim_lum_or_contrast(cs.namespace(|| "ssim lum"), &circ_mean_src, &circ_mean_dst, 
+			c1, witns.map(|w| w.l_numerator), witns.map(|w| w.l_denom))?;
 	
 	//
 	// Structure
 	//
-	let circ_src_sign = gen_sample_sign(cs.namespace(|| "sign src"), &c.src_mb, witns.sum_x / witns.num_samples);
-	let circ_dst_sign = gen_sample_sign(cs.namespace(|| "sign dst"), &c.dst_mb, witns.sum_y / witns.num_samples);
+	let circ_src_sign = gen_sample_sign(cs.namespace(|| "sign src"), src_mb, witns.map(|w| w.sum_x / w.num_samples), mb_size)?;
+	let circ_dst_sign = gen_sample_sign(cs.namespace(|| "sign dst"), dst_mb, witns.map(|w| w.sum_y / w.num_samples), mb_size)?;
 	
-	let mut circ_diff_vec_src = absdiff_vec(cs.namespace(|| "absdiff a"), &circ_mb_x, &circ_mean_src, &circ_src_sign);
-	let mut circ_diff_vec_dst = absdiff_vec(cs.namespace(|| "abs diff b"),  &circ_mb_y, &circ_mean_dst, &circ_dst_sign);
+	let mut circ_diff_vec_src = absdiff_vec(cs.namespace(|| "absdiff a"), &circ_mb_x, &circ_mean_src, &circ_src_sign, bit_depth)?;
+	let mut circ_diff_vec_dst = absdiff_vec(cs.namespace(|| "abs diff b"),  &circ_mb_y, &circ_mean_dst, &circ_dst_sign, bit_depth)?;
 
 	let (circ_sigma_x_sq_sum, circ_sigma_x_sq, circ_sigma_x) = vairance_constraint(cs.namespace(|| "sigma x const"), 
-			&circ_diff_vec_src, &circ_diff_vec_src, witns.sigma_x_sq_sum, witns.sigma_x, witns.sigma_x_frac);
+			&circ_diff_vec_src, &circ_diff_vec_src, weights, witns.map(|w| w.sigma_x_sq_sum), bit_depth)?;
 	
 	let (circ_sigma_y_sq_sum, circ_sigma_y_sq, circ_sigma_y) = vairance_constraint(cs.namespace(|| "sigma y const"), 
-			&circ_diff_vec_dst, &circ_diff_vec_dst, witns.sigma_y_sq_sum, witns.sigma_y, witns.sigma_y_frac);
+			&circ_diff_vec_dst, &circ_diff_vec_dst, weights, witns.map(|w| w.sigma_y_sq_sum), bit_depth)?;
 	
 	let (circ_sigma_xy_sum, circ_sigma_xy) = covairance_constraint(cs.namespace(|| "sigma xy const"), 
-			&circ_diff_vec_src, &circ_diff_vec_dst, witns.sigma_xy_sum, witns.sigma_xy);
+			&circ_diff_vec_src, &circ_diff_vec_dst, weights, witns.map(|w| w.sigma_xy_sum), witns.map(|w| w.sigma_xy), bit_depth)?;
 	
-	let (circ_s_numerator, circ_s_denom) = ssim_struct_constraint(cs.namespace(|| "ssim struct"), &circ_sigma_xy, &circ_sigma_x, &circ_sigma_y, witns.c3 as u64, witns.s_numerator as u64, witns.s_denom as u64).unwrap();
+	let (circ_s_numerator, circ_s_denom) = ssim_struct_constraint(cs.namespace(|| "ssim struct"), &circ_sigma_xy, &circ_sigma_x, &circ_sigma_y, 
+			c3, witns.map(|w| w.s_numerator), witns.map(|w| w.s_denom))?;
 	//
 	// contrast
 	//
-	let (circ_c_numerator, circ_c_denom, c2_circ) = ssim_lum_or_contrast(cs.namespace(|| "ssim contrast"), &circ_sigma_x, &circ_sigma_y, witns.c2 as u64, witns.c_numerator as u64, witns.c_denom as u64).unwrap();
+	let (circ_c_numerator, circ_c_denom, c2_circ) = ssim_lum_or_contrast(cs.namespace(|| "ssim contrast"), &circ_sigma_x, &circ_sigma_y, 
+			c2, witns.map(|w| w.c_numerator), witns.map(|w| w.c_denom))?;
 	//
 	// ssim
 	//
-	let (circ_ssim_numerator, circ_ssim_denom) = ssim_constraint(cs.namespace(|| "ssim constraint"), &circ_l_numerator, &circ_sigma_xy, &c2_circ, &circ_l_denom, &circ_c_denom, witns.ssim_numerator as u64, witns.ssim_denom as u64).unwrap();
-	let (circ_ssim_m_numerator, circ_ssim_m_denom) = ssim_m_constraint(cs.namespace(|| "ssim m constraint"), &circ_l_numerator, &circ_sigma_xy, &c2_circ, &circ_l_denom, &circ_sigma_x_sq, &circ_sigma_y_sq, witns.ssim_m_numerator as u64, witns.ssim_m_denom as u64).unwrap();
+	let (circ_ssim_numerator, circ_ssim_denom) = ssim_constraint(cs.namespace(|| "ssim constraint"), &circ_l_numerator, &circ_sigma_xy, &c2_circ, &circ_l_denom, &circ_c_denom, 
+			witns.map(|w| w.ssim_numerator), witns.map(|w| w.ssim_denom))?;
+	let (circ_ssim_m_numerator, circ_ssim_m_denom) = ssim_m_constraint(cs.namespace(|| "ssim m constraint"), &circ_l_numerator, &circ_sigma_xy, &c2_circ, &circ_l_denom, &circ_sigma_x_sq, &circ_sigma_y_sq, 
+			witns.map(|w| w.ssim_m_numerator), witns.map(|w| w.ssim_m_denom))?;
+
+	Ok(SsimPlane {
+		sum_x: circ_mb_sum_x,
+		sum_y: circ_mb_sum_y,
+		sigma_x: circ_sigma_x,
+		sigma_y: circ_sigma_y,
+		sigma_xy: circ_sigma_xy,
+		ssim_numerator: circ_ssim_numerator,
+		ssim_denom: circ_ssim_denom,
+		ssim_m_numerator: circ_ssim_m_numerator,
+		ssim_m_denom: circ_ssim_m_denom,
+	})
+}
+
+/// Weighted YUV SSIM from the per-plane SSIM fractions n/d:
+/// (wy*ny*du*dv + wu*nu*dy*dv + wv*nv*dy*du) / ((wy+wu+wv)*dy*du*dv)
+pub fn ssim_yuv_constraint<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	weights: &YuvWeights,
+	y: &SsimPlane<E>,
+	u: &SsimPlane<E>,
+	v: &SsimPlane<E>,
+) -> Result<(AllocatedPixel<E>, AllocatedPixel<E>), SynthesisError> {
+	let du_dv = u.ssim_m_denom.mul(cs.namespace(|| "du dv"), &v.ssim_m_denom)?;
+	let dy_dv = y.ssim_m_denom.mul(cs.namespace(|| "dy dv"), &v.ssim_m_denom)?;
+	let dy_du = y.ssim_m_denom.mul(cs.namespace(|| "dy du"), &u.ssim_m_denom)?;
+	let term_y = y.ssim_m_numerator.mul(cs.namespace(|| "ny du dv"), &du_dv)?;
+	let term_u = u.ssim_m_numerator.mul(cs.namespace(|| "nu dy dv"), &dy_dv)?;
+	let term_v = v.ssim_m_numerator.mul(cs.namespace(|| "nv dy du"), &dy_du)?;
+	let denom_prod = y.ssim_m_denom.mul(cs.namespace(|| "dy du dv"), &du_dv)?;
+
+	let fr = |w: u32| E::Fr::from_repr((w as u64).into()).unwrap();
+	let yuv_numerator = AllocatedPixel::alloc(cs.namespace(|| "yuv numerator"), || {
+		let mut value = E::Fr::zero();
+		for (term, w) in [(&term_y, weights.y), (&term_u, weights.u), (&term_v, weights.v)].iter() {
+			let mut tmp = term.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+			tmp.mul_assign(&fr(*w));
+			value.add_assign(&tmp);
+		}
+		Ok(value)
+	})?;
+	cs.enforce(|| "enforce yuv numerator",
+		|lc| lc + (fr(weights.y), term_y.variable) + (fr(weights.u), term_u.variable) + (fr(weights.v), term_v.variable),
+		|lc| lc + CS::one(),
+		|lc| lc + yuv_numerator.variable,
+	);
+
+	let yuv_denom = AllocatedPixel::alloc(cs.namespace(|| "yuv denom"), || {
+		let mut value = denom_prod.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+		value.mul_assign(&fr(weights.total()));
+		Ok(value)
+	})?;
+	cs.enforce(|| "enforce yuv denom",
+		|lc| lc + (fr(weights.total()), denom_prod.variable),
+		|lc| lc + CS::one(),
+		|lc| lc + yuv_denom.variable,
+	);
+	Ok((yuv_numerator, yuv_denom))
+}
+
+pub fn ssim_circuit<E: JubjubEngine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	c: Ssim::<E>,
+) -> Result<(AllocatedPixel<E>, AllocatedPixel<E>), SynthesisError> {
+	let witns = c.witns.as_ref();
+	let mb_size = c.window.mb_size();
+	let src_side = c.ratio.src_side(c.window.size);
+	let circ_src_mb = gen_sample(cs.namespace(|| "src mb"), &c.src_mb, (src_side * src_side) as usize)?;
+	let circ_mb_y = gen_sample_range(cs.namespace(|| "dst mb"), &c.dst_mb, mb_size, c.bit_depth)?;
+
+	// Chroma blocks are 4:2:0, half the luma window per side
+	let chroma_window = c.window.chroma();
+	let chroma_src_side = src_side / 2;
+	let mut circ_chroma_src = vec![];
+	if c.chroma.is_some() {
+		let chroma_mb = c.chroma_mb.as_ref();
+		let chroma_src_size = (chroma_src_side * chroma_src_side) as usize;
+		circ_chroma_src.push(gen_sample(cs.namespace(|| "src u mb"), &chroma_mb.map(|b| b.src_u.clone()), chroma_src_size)?);
+		circ_chroma_src.push(gen_sample(cs.namespace(|| "src v mb"), &chroma_mb.map(|b| b.src_v.clone()), chroma_src_size)?);
+	}
+
+	let mut circ_src_planes = circ_src_mb.clone();
+	for plane in circ_chroma_src.iter() {
+		circ_src_planes.extend(plane.iter().cloned());
+	}
+	let circ_src_leaf = source_commit::source_mb_hash_circuit(cs.namespace(|| "src mb hash"), &circ_src_planes, c.bit_depth, c.params)?;
+	let circ_mb_x = resample::resample_circuit(cs.namespace(|| "src resample"), &circ_src_mb, &c.src_mb, &c.ratio, c.window.size, c.bit_depth)?;
+	let src_mb = c.src_mb.as_ref().map(|mb| resample::resample(mb, &c.ratio, c.window.size));
 
-	circ_mb_sum_y.inputize(cs.namespace(|| "sum y"))?;
-	circ_sigma_y.inputize(cs.namespace(|| "sigma_y"))?;
-	circ_ssim_numerator.inputize(cs.namespace(|| "ssim_numerator"))?;
-	circ_ssim_denom.inputize(cs.namespace(|| "ssim_denom"))?;
+	let circ_y = ssim_plane(&mut cs, &circ_mb_x, &circ_mb_y, &src_mb, &c.dst_mb, &c.window, c.bit_depth, witns)?;
+
+	for stat in c.public_inputs.stats.iter() {
+		stat.allocated(&circ_y).inputize(cs.namespace(|| stat.name()))?;
+	}
+
+	if let Some(yuv_weights) = c.chroma.as_ref() {
+		let chroma_mb = c.chroma_mb.as_ref();
+		let chroma_witns = c.chroma_witns.as_ref();
+		let mut circ_chroma = vec![];
+		for (i, name) in ["u", "v"].iter().enumerate() {
+			let mut cs = cs.namespace(|| format!("{} plane", name));
+			let src_native = chroma_mb.map(|b| if i == 0 { b.src_u.clone() } else { b.src_v.clone() });
+			let dst_native = chroma_mb.map(|b| if i == 0 { b.dst_u.clone() } else { b.dst_v.clone() });
+			let plane_witns = chroma_witns.map(|w| if i == 0 { &w.u } else { &w.v });
+			let circ_dst = gen_sample_range(cs.namespace(|| "dst mb"), &dst_native, chroma_window.mb_size(), c.bit_depth)?;
+			let circ_src = resample::resample_circuit(cs.namespace(|| "src resample"), &circ_chroma_src[i], &src_native, &c.ratio, chroma_window.size, c.bit_depth)?;
+			let src_native = src_native.map(|mb| resample::resample(&mb, &c.ratio, chroma_window.size));
+			circ_chroma.push(ssim_plane(cs.namespace(|| "ssim"), &circ_src, &circ_dst, &src_native, &dst_native, &chroma_window, c.bit_depth, plane_witns)?);
+		}
+		let (circ_yuv_numerator, circ_yuv_denom) = ssim_yuv_constraint(cs.namespace(|| "ssim yuv"), yuv_weights, &circ_y, &circ_chroma[0], &circ_chroma[1])?;
+		circ_yuv_numerator.inputize(cs.namespace(|| "yuv_numerator"))?;
+		circ_yuv_denom.inputize(cs.namespace(|| "yuv_denom"))?;
+	}
+
+	let (circ_frame, circ_frame_bits) = challenge::alloc_frame(cs.namespace(|| "frame"), c.position.map(|p| p.frame))?;
+	let auth_path_bits = source_commit::source_path_circuit(cs.namespace(|| "src mb path"), circ_src_leaf, &circ_frame_bits, &c.src_auth_path, c.src_root, c.params)?;
+	challenge::position_circuit(cs.namespace(|| "position"), c.position, &circ_frame, &auth_path_bits)?;
 	
-	Ok((circ_ssim_numerator, circ_ssim_denom))
+	Ok((circ_y.ssim_numerator, circ_y.ssim_denom))
 }
 
 /// Generate a unique cache path, based on the inputs.
@@ -796,104 +829,198 @@ fn get_cache_path(
 /// Utility functions
 ///
 
-/// generate random input macroblock
-fn gen_mb(mb_size: usize ) -> Vec<u32>  {	
-	let mut rng = rand::thread_rng();
-	let mb: Vec<u32> = (0..mb_size).map(|x| (rng.gen::<u8>()) as u32).collect();
-	mb
-}
-
-fn get_mb_sum(mb: &Vec<u32> ) -> u32  {	
-	let sum = mb.iter().map(|x| x).sum();
+pub fn get_mb_sum(mb: &Vec<u32>, weights: &[u32]) -> u64  {	
+	let sum = mb.iter().zip(weights.iter()).map(|(x, w)| *x as u64 * *w as u64).sum();
 	sum
 }
 
-fn get_sqrt(x: u32 ) -> (u32, u32)  {	
-	let sqrt_x = (x as f64).sqrt() as u32;
+fn get_sqrt(x: u64 ) -> (u64, u64)  {	
+	let sqrt_x = gadget::isqrt(x);
 	(sqrt_x, x - sqrt_x * sqrt_x)
 }
 
-fn get_mb_covariance(mb_src: &Vec<u32>, mb_dst: &Vec<u32>, mean_src: u32, mean_dst: u32 ) -> u32 {
-	let mut covar: u32 = 0;
-	for it in mb_src.iter().zip(mb_dst.iter()) {
-		let (src, dst) = it;
-		let  mut a_diff: u32  = 0;
-		let  mut b_diff: u32  = 0;
-		if *src > mean_src {a_diff = *src  - mean_src} else {a_diff = mean_src - *src};
-		if *dst > mean_dst {b_diff = *dst  - mean_dst} else {b_diff = mean_dst - *dst};
-		covar = covar + a_diff  * b_diff;
+fn get_mb_covariance(mb_src: &Vec<u32>, mb_dst: &Vec<u32>, weights: &[u32], mean_src: u64, mean_dst: u64 ) -> u64 {
+	let mut covar: u64 = 0;
+	for it in mb_src.iter().zip(mb_dst.iter()).zip(weights.iter()) {
+		let ((src, dst), w) = it;
+		let (src, dst) = (*src as u64, *dst as u64);
+		let  mut a_diff: u64  = 0;
+		let  mut b_diff: u64  = 0;
+		if src > mean_src {a_diff = src  - mean_src} else {a_diff = mean_src - src};
+		if dst > mean_dst {b_diff = dst  - mean_dst} else {b_diff = mean_dst - dst};
+		covar = covar + *w as u64 * a_diff  * b_diff;
 	}
 	covar
 }
 
-fn get_witness_sigma(src_mb: &Vec<u32>, dst_mb: &Vec<u32>) -> (u32, u32, u32, u32) {
+pub fn get_witness_sigma(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, weights: &[u32]) -> (u64, u64, u64, u64) {
 	
-	let sum_x = get_mb_sum(src_mb);
-	let sum_y = get_mb_sum(dst_mb);
-	let num_samples: u32 = src_mb.len() as u32;
-	let sigma_sq_sum = get_mb_covariance(&src_mb, &dst_mb, sum_x / num_samples, sum_y/ num_samples);
+	let sum_x = get_mb_sum(src_mb, weights);
+	let sum_y = get_mb_sum(dst_mb, weights);
+	let num_samples = weights.iter().sum::<u32>() as u64;
+	let sigma_sq_sum = get_mb_covariance(&src_mb, &dst_mb, weights, sum_x / num_samples, sum_y/ num_samples);
 	let sigma_sq = sigma_sq_sum / num_samples;		
 	let (sigma, sigma_frac) = get_sqrt(sigma_sq);
 	(sigma_sq_sum, sigma_sq, sigma, sigma_frac)
 }
 
-fn get_witness_sigma_xy(src_mb: &Vec<u32>, dst_mb: &Vec<u32>) -> (u32, u32) {
+pub fn get_witness_sigma_xy(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, weights: &[u32]) -> (u64, u64) {
 	
-	let sum_x = get_mb_sum(src_mb);
-	let sum_y = get_mb_sum(dst_mb);
-	let num_samples: u32 = src_mb.len() as u32;
-	let sigma_xy_sum = get_mb_covariance(&src_mb, &dst_mb, sum_x / num_samples, sum_y/ num_samples);
+	let sum_x = get_mb_sum(src_mb, weights);
+	let sum_y = get_mb_sum(dst_mb, weights);
+	let num_samples = weights.iter().sum::<u32>() as u64;
+	let sigma_xy_sum = get_mb_covariance(&src_mb, &dst_mb, weights, sum_x / num_samples, sum_y/ num_samples);
 	let sigma_xy = sigma_xy_sum / num_samples;		
 	(sigma_xy_sum, sigma_xy)
 }
 
-fn gen_sample<E: Engine, CS: ConstraintSystem<E>>(mut cs: CS, mb: Vec<u32> ) -> Vec<AllocatedPixel<E>>  {
-	// Prepare 3x3 test vector
-
-	let mut var_pix3x3: Vec<AllocatedPixel<E>> = Vec::new();
-	for i in 0..mb.len() {
+/// Allocates the macroblock pixels. `mb` is `None` during setup.
+pub fn gen_sample<E: Engine, CS: ConstraintSystem<E>>(mut cs: CS, mb: &Option<Vec<u32>>, mb_size: usize) -> Result<Vec<AllocatedPixel<E>>, SynthesisError>  {
+	let mut var_pix: Vec<AllocatedPixel<E>> = Vec::new();
+	for i in 0..mb_size {
 		let value_num = AllocatedPixel::alloc(cs.namespace(|| format!("val {}", i)), || {
-			let value = (E::Fr::from_repr((mb[i] as u64).into())).unwrap();
-			Ok(value)
-		});
-		var_pix3x3.push(value_num.unwrap());
+			witness_fr::<E>(mb.as_ref().map(|mb| mb[i] as u64))
+		})?;
+		var_pix.push(value_num);
+	}
+	Ok(var_pix)
+}
+
+/// Allocates the macroblock pixels and proves each fits `bit_depth` bits. `mb` is `None` during setup.
+pub fn gen_sample_range<E: Engine, CS: ConstraintSystem<E>>(mut cs: CS, mb: &Option<Vec<u32>>, mb_size: usize, bit_depth: u32) -> Result<Vec<AllocatedPixel<E>>, SynthesisError>  {
+	let var_pix = gen_sample(cs.namespace(|| "pixels"), mb, mb_size)?;
+	for (i, pix) in var_pix.iter().enumerate() {
+		pix.into_bits_le_fixed(cs.namespace(|| format!("range {}", i)), bit_depth as usize)?;
 	}
-	var_pix3x3
+	Ok(var_pix)
 }
 
-fn gen_sample_sign<E: Engine, CS: ConstraintSystem<E>>(mut cs: CS, mb: &Vec<u32>, mean: u32 ) -> Vec<boolean::AllocatedBit>  {
+pub fn gen_sample_sign<E: Engine, CS: ConstraintSystem<E>>(mut cs: CS, mb: &Option<Vec<u32>>, mean: Option<u64>, mb_size: usize) -> Result<Vec<boolean::AllocatedBit>, SynthesisError>  {
 	let mut var_sign: Vec<boolean::AllocatedBit> = Vec::new();
-	for i in 0..mb.len() {
+	for i in 0..mb_size {
+		let sign = match (mb, mean) {
+			(Some(mb), Some(mean)) => Some(mb[i] as u64 <= mean),
+			_ => None,
+		};
 		let cur_sign = boolean::AllocatedBit::alloc(
             cs.namespace(|| format!("sign {}", i)),
-            if mb[i] > mean {Some(false)} else {Some(true)},
-        );
-		var_sign.push(cur_sign.unwrap());
+            sign,
+        )?;
+		var_sign.push(cur_sign);
 	}
-	var_sign
+	Ok(var_sign)
 }
 
 /// Wrapper for SSIM API
 pub struct SsimApp {
     mb_size: u32,
-	src_pixel: Vec<u32>, 
-    dst_pixel: Vec<u32>,
-	witns: Witness,
+	window: SsimWindow,
+	tree_depth: u32,
+	ratio: ResampleRatio,
+	chroma: Option<YuvWeights>,
+	bit_depth: u32,
+	public_inputs: PublicInputSchema,
 }
 
-impl Default for SsimApp {
-    fn default() -> Self {
-		let rng = &mut thread_rng();
-		let mb_size = 256;
-		let src_pixel: Vec<u32>  = (0..mb_size).map(|x| (rng.gen::<u8>()) as u32).collect();
-		let dst_pixel: Vec<u32> =(0..mb_size).map(|x| (rng.gen::<u8>()) as u32).collect();
-		let witns = gen_witness(&src_pixel.clone(), &dst_pixel.clone());
+impl SsimApp {
+	pub fn new(window: SsimWindow, tree_depth: u32, ratio: ResampleRatio, chroma: Option<YuvWeights>, bit_depth: u32, public_inputs: PublicInputSchema) -> Self {
+		let mb_size = window.mb_size() as u32;
         SsimApp {
 			mb_size,
-            src_pixel,
-            dst_pixel, 
-			witns
+			window,
+			tree_depth,
+			ratio,
+			chroma,
+			bit_depth,
+			public_inputs,
         }
+	}
+
+	pub fn window(&self) -> &SsimWindow {
+		&self.window
+	}
+
+	pub fn tree_depth(&self) -> u32 {
+		self.tree_depth
+	}
+
+	/// Number of pixels of a source block
+	pub fn src_mb_size(&self) -> usize {
+		let src_side = self.ratio.src_side(self.window.size);
+		(src_side * src_side) as usize
+	}
+
+	pub fn chroma(&self) -> Option<YuvWeights> {
+		self.chroma
+	}
+
+	pub fn bit_depth(&self) -> u32 {
+		self.bit_depth
+	}
+
+	pub fn public_inputs(&self) -> &PublicInputSchema {
+		&self.public_inputs
+	}
+
+	/// Circuit of this configuration without blocks or witness, as synthesized during setup
+	pub fn blank_circuit(&self) -> Ssim<'static, Bls12> {
+		Ssim::<Bls12> {
+			src_mb: None,
+			dst_mb: None,
+			window: self.window.clone(),
+			ratio: self.ratio,
+			bit_depth: self.bit_depth,
+			public_inputs: self.public_inputs.clone(),
+			witns:  None,
+			chroma: self.chroma,
+			chroma_mb: None,
+			chroma_witns: None,
+			src_auth_path: vec![None; self.tree_depth as usize],
+			src_root: None,
+			position: None,
+			params: &source_commit::JUBJUB_BLS_PARAMS,
+		}
+	}
+
+	/// Circuit of this configuration assigned with the blocks and witness of a proof
+	pub fn circuit(
+		&self,
+		src_pixel: Vec<u32>,
+		dst_pixel: Vec<u32>,
+		witns: Witness,
+		src_auth_path: Vec<Option<(Fr, bool)>>,
+		src_root: Fr,
+		position: MbPosition,
+		chroma: Option<(ChromaBlocks, ChromaWitness)>,
+	) -> Ssim<'static, Bls12> {
+		assert_eq!(src_auth_path.len(), self.tree_depth as usize, "source path does not match the tree depth");
+		assert_eq!(chroma.is_some(), self.chroma.is_some(), "chroma blocks do not match the crs");
+		let (chroma_mb, chroma_witns) = match chroma {
+			Some((blocks, witns)) => (Some(blocks), Some(witns)),
+			None => (None, None),
+		};
+		Ssim::<Bls12> {
+			src_mb: Some(src_pixel),
+			dst_mb: Some(dst_pixel),
+			window: self.window.clone(),
+			ratio: self.ratio,
+			bit_depth: self.bit_depth,
+			public_inputs: self.public_inputs.clone(),
+			witns:  Some(witns),
+			chroma: self.chroma,
+			chroma_mb,
+			chroma_witns,
+			src_auth_path,
+			src_root: Some(src_root),
+			position: Some(position),
+			params: &source_commit::JUBJUB_BLS_PARAMS,
+		}
+	}
+}
+
+impl Default for SsimApp {
+    fn default() -> Self {
+		SsimApp::new(SsimWindow::new(16, false), source_commit::DEFAULT_SOURCE_TREE_DEPTH, ResampleRatio::identity(), None, DEFAULT_BIT_DEPTH, PublicInputSchema::default())
     }
 }
 
@@ -903,7 +1030,8 @@ pub trait SsimApi<'a, C: Circuit<Bls12>>: Default {
     fn name() -> String;
 
     /// Generate groth parameters.
-	/// arguments _src_pixel etc are random
+	/// The circuit is synthesised without any assignment, so the CRS depends only on the window
+	/// and the depth of the source tree.
     fn setup(
         &mut self,
     ) -> Parameters<Bls12>;
@@ -915,17 +1043,27 @@ pub trait SsimApi<'a, C: Circuit<Bls12>>: Default {
 		src_pixel: Vec<u32>, 
 	    dst_pixel: Vec<u32>,
 		witns: Witness,		
+		src_auth_path: Vec<Option<(Fr, bool)>>,
+		src_root: Fr,
+		position: MbPosition,
+		chroma: Option<(ChromaBlocks, ChromaWitness)>,
     ) -> Proof<Bls12>;
 
     /// Verify the given proof, return `None` if not implemented.
+	/// `public_inputs` are the luma statistics of the public input schema, in its order.
+	/// `position` is the challenged block, its index is the leaf of the source block in the committed frame, and `src_root` the frame commitment.
+	/// `yuv` is the weighted YUV SSIM numerator and denominator, required when the CRS covers chroma.
     fn verify_proof(
 		&mut self, 
         pvk: &PreparedVerifyingKey<Bls12>,		
 		proof: &Proof<Bls12>,
-		public_inputs: Vec<u32>) -> Option<bool>;
+		public_inputs: Vec<u64>,
+		position: &MbPosition,
+		src_root: Fr,
+		yuv: Option<(Fr, Fr)>) -> Option<bool>;
 }
 
-impl<'a> SsimApi<'a, Ssim<Bls12>> for SsimApp {
+impl<'a> SsimApi<'a, Ssim<'a, Bls12>> for SsimApp {
     fn name() -> String {
         "Ssim".to_string()
     }
@@ -936,13 +1074,7 @@ impl<'a> SsimApi<'a, Ssim<Bls12>> for SsimApp {
 		// Create parameters for our circuit
 		let rng = &mut thread_rng();
 		let params = {
-			let c = Ssim::<Bls12> {
-				src_mb: self.src_pixel.clone(),
-				dst_mb: self.dst_pixel.clone(),
-				witns:  self.witns.clone(),
-				phantom: Default::default(),
-			};
-	
+			let c = self.blank_circuit();
 			generate_random_parameters(c, rng).unwrap()
 		};
 		params
@@ -954,14 +1086,13 @@ impl<'a> SsimApi<'a, Ssim<Bls12>> for SsimApp {
 		src_pixel: Vec<u32>, 
 	    dst_pixel: Vec<u32>,
 		witns: Witness,		
+		src_auth_path: Vec<Option<(Fr, bool)>>,
+		src_root: Fr,
+		position: MbPosition,
+		chroma: Option<(ChromaBlocks, ChromaWitness)>,
     ) -> Proof<Bls12> {
 		let rng = &mut thread_rng();
-		let c = Ssim::<Bls12> {
-			src_mb: src_pixel.clone(),
-			dst_mb: dst_pixel.clone(),
-			witns:  witns.clone(),
-			phantom: Default::default(),
-		};
+		let c = self.circuit(src_pixel, dst_pixel, witns, src_auth_path, src_root, position, chroma);
 
         create_random_proof(c, groth_params, rng).expect("failed to create proof")
 
@@ -971,10 +1102,20 @@ impl<'a> SsimApi<'a, Ssim<Bls12>> for SsimApp {
         &mut self,
         pvk: &PreparedVerifyingKey<Bls12>,
         proof: &Proof<Bls12>,
-		public_inputs: Vec<u32>,
+		public_inputs: Vec<u64>,
+		position: &MbPosition,
+		src_root: Fr,
+		yuv: Option<(Fr, Fr)>,
     ) -> Option<bool> {
-        let expected_inputs: Vec<Fr> = public_inputs.iter().map(|x| (Fr::from_repr((*x as u64).into())).unwrap()).collect();
-		//let expected_inputs: Vec<Fr> = vec![];
+		assert_eq!(public_inputs.len(), self.public_inputs.stats.len(), "public inputs do not match the schema of the crs");
+        let mut expected_inputs: Vec<Fr> = public_inputs.iter().map(|x| (Fr::from_repr((*x).into())).unwrap()).collect();
+		if let Some((yuv_numerator, yuv_denom)) = yuv {
+			expected_inputs.push(yuv_numerator);
+			expected_inputs.push(yuv_denom);
+		}
+		expected_inputs.extend(source_commit::source_path_inputs(self.tree_depth, position.index()));
+		expected_inputs.push(src_root);
+		expected_inputs.extend(position.public_inputs());
         // -- verify proof with public inputs
         Some(verify_proof(pvk, proof, &expected_inputs).expect("failed to verify proof"))
     }
@@ -984,65 +1125,65 @@ impl<'a> SsimApi<'a, Ssim<Bls12>> for SsimApp {
 #[derive(Clone)]
 #[derive(Default)]
 pub struct Witness {
-	num_samples: u32,
-	sum_x: u32,
-	pub sum_y: u32,
-	l_numerator: u32,
-	l_denom: u32,
-	c1: u32,
+	/// Sum of the window weights, the number of pixels for the block window
+	num_samples: u64,
+	sum_x: u64,
+	pub sum_y: u64,
+	l_numerator: u64,
+	l_denom: u64,
+	c1: u64,
 	
-	sigma_x_sq_sum: u32, 
-	sigma_x_sq: u32, 
-	sigma_x: u32, 
-	sigma_x_frac: u32,
+	sigma_x_sq_sum: u64, 
+	sigma_x_sq: u64, 
+	sigma_x: u64, 
+	sigma_x_frac: u64,
 	
-	sigma_y_sq_sum: u32, 
-	sigma_y_sq: u32, 
-	pub sigma_y: u32, 
-	sigma_y_frac: u32,
+	sigma_y_sq_sum: u64, 
+	sigma_y_sq: u64, 
+	pub sigma_y: u64, 
+	sigma_y_frac: u64,
 	
-	sigma_xy_sum: u32, 
-	sigma_xy: u32, 
-
-	s_numerator: u32,
-	s_denom: u32,
-	c3: u32,
-	c_numerator: u32,
-	c_denom: u32,
-	c2: u32,
+	sigma_xy_sum: u64, 
+	sigma_xy: u64, 
+
+	s_numerator: u64,
+	s_denom: u64,
+	c3: u64,
+	c_numerator: u64,
+	c_denom: u64,
+	c2: u64,
 	
-	pub ssim_numerator: u32,
-	pub ssim_denom: u32,
-	pub ssim_m_numerator: u32,
-	pub ssim_m_denom: u32,	
+	pub ssim_numerator: u64,
+	pub ssim_denom: u64,
+	pub ssim_m_numerator: u64,
+	pub ssim_m_denom: u64,	
 }	
 
 
-pub fn gen_witness(src_mb: &Vec<u32>, dst_mb: &Vec<u32>) -> Witness {
+pub fn gen_witness(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, window: &SsimWindow, bit_depth: u32) -> Witness {
 	
-		let num_samples = src_mb.len() as u32;
-		let sum_x = get_mb_sum(&src_mb);
-		let sum_y = get_mb_sum(&dst_mb);
+		let weights = &window.weights;
+		let num_samples = window.total_weight() as u64;
+		let (c1, c2, c3) = ssim_constants(bit_depth);
+		let sum_x = get_mb_sum(&src_mb, weights);
+		let sum_y = get_mb_sum(&dst_mb, weights);
 
 		//
 		// Lumen
 		//
-		let c1 = 0;
 		let l_numerator = 2 * (sum_x / num_samples) * (sum_y / num_samples) + c1; 
 		let l_denom = ((sum_x / num_samples) * (sum_x / num_samples) + (sum_y / num_samples) * (sum_y / num_samples)) + c1;
-		let (sigma_x_sq_sum, sigma_x_sq, sigma_x, sigma_x_frac)= get_witness_sigma(&src_mb, &src_mb);		
-		let (sigma_y_sq_sum, sigma_y_sq, sigma_y, sigma_y_frac)= get_witness_sigma(&dst_mb, &dst_mb);
-		let (sigma_xy_sum, sigma_xy)= get_witness_sigma_xy(&src_mb, &dst_mb);
-		let c3 = 0;
+		let (sigma_x_sq_sum, sigma_x_sq, sigma_x, sigma_x_frac)= get_witness_sigma(&src_mb, &src_mb, weights);		
+		let (sigma_y_sq_sum, sigma_y_sq, sigma_y, sigma_y_frac)= get_witness_sigma(&dst_mb, &dst_mb, weights);
+		let (sigma_xy_sum, sigma_xy)= get_witness_sigma_xy(&src_mb, &dst_mb, weights);
 		let s_numerator = sigma_xy + c3; 
 		let s_denom = sigma_x * sigma_y  + c3;
-		let c2 = 0;
 		let c_numerator = 2 * sigma_x * sigma_y + c2; 
-		let c_denom = (sigma_x * sigma_x) + (sigma_y * sigma_y) + c3;
-		let ssim_numerator = l_numerator * 2 * sigma_xy + c2;
+		let c_denom = (sigma_x * sigma_x) + (sigma_y * sigma_y) + c2;
+		let ssim_numerator = l_numerator * (2 * sigma_xy + c2);
 		let ssim_denom = l_denom * c_denom;
 		let ssim_m_numerator = l_numerator * (2 * sigma_xy + c2);
-		let ssim_m_denom = l_denom * (sigma_x_sq + sigma_y_sq);		
+		let ssim_m_denom = l_denom * (sigma_x_sq + sigma_y_sq + c2);		
 		let mut witns = Witness {
 			num_samples,
 			sum_x,
@@ -1081,19 +1222,46 @@ pub fn gen_witness(src_mb: &Vec<u32>, dst_mb: &Vec<u32>) -> Witness {
 		witns
 }
 	
+/// Weighted YUV SSIM numerator and denominator as the circuit computes them
+pub fn yuv_fraction(weights: &YuvWeights, y: &Witness, chroma: &ChromaWitness) -> (Fr, Fr) {
+	let fr = |x: u64| Fr::from_repr(x.into()).unwrap();
+	let mul = |a: Fr, b: Fr| { let mut tmp = a; tmp.mul_assign(&b); tmp };
+	let (ny, dy) = (fr(y.ssim_m_numerator), fr(y.ssim_m_denom));
+	let (nu, du) = (fr(chroma.u.ssim_m_numerator), fr(chroma.u.ssim_m_denom));
+	let (nv, dv) = (fr(chroma.v.ssim_m_numerator), fr(chroma.v.ssim_m_denom));
+
+	let mut numerator = mul(fr(weights.y as u64), mul(ny, mul(du, dv)));
+	numerator.add_assign(&mul(fr(weights.u as u64), mul(nu, mul(dy, dv))));
+	numerator.add_assign(&mul(fr(weights.v as u64), mul(nv, mul(dy, du))));
+	let denom = mul(fr(weights.total() as u64), mul(dy, mul(du, dv)));
+	(numerator, denom)
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
+	use crate::test_helper::gen_mb;
 	use storage_proofs::circuit::test::*;
 
+	/// Circuit of `app` over a single source block, committed at position 0 of frame 0.
+	/// The chroma blocks are committed after the luma block, as the source commitment does.
+	fn single_block_circuit(app: &SsimApp, src_mb: Vec<u32>, dst_mb: Vec<u32>, witns: Witness, chroma: Option<(ChromaBlocks, ChromaWitness)>) -> Ssim<'static, Bls12> {
+		let mut src_planes = src_mb.clone();
+		if let Some((blocks, _)) = &chroma {
+			src_planes.extend(blocks.src_u.iter().chain(blocks.src_v.iter()));
+		}
+		let (src_auth_path, src_root) = source_commit::source_path(&[src_planes], app.tree_depth, app.bit_depth, 0, 0);
+		app.circuit(src_mb, dst_mb, witns, src_auth_path, src_root, MbPosition::from_index(0, 0, 1), chroma)
+	}
 
 	#[test]
 	fn test_struct_ssim() {
 		let mut cs = TestConstraintSystem::<Bls12>::new();
-		let src_mb = gen_mb(256);
-		let dst_mb = gen_mb(256);		
+		let window = SsimWindow::new(16, false);
+		let src_mb = gen_mb(256, DEFAULT_BIT_DEPTH);
+		let dst_mb = gen_mb(256, DEFAULT_BIT_DEPTH);		
 		
-		let witns = gen_witness(&src_mb, &dst_mb);
+		let witns = gen_witness(&src_mb, &dst_mb, &window, DEFAULT_BIT_DEPTH);
 		let tmp_src_pixels: Vec<Option<Fr>> = src_mb
 			.iter()
 			.map(|x| Some((Fr::from_repr(FrRepr::from(*x as u64))).unwrap()))
@@ -1103,12 +1271,8 @@ mod test {
 			.map(|x| Some((Fr::from_repr(FrRepr::from(*x as u64))).unwrap()))
 			.collect();
 	
-		let c = Ssim::<Bls12> {
-			src_mb: src_mb.clone(),
-			dst_mb: dst_mb.clone(),			
-			witns: witns.clone(),
-			phantom: Default::default(),
-		};
+		let app = SsimApp::new(window, 0, ResampleRatio::identity(), None, DEFAULT_BIT_DEPTH, PublicInputSchema::default());
+		let c = single_block_circuit(&app, src_mb.clone(), dst_mb.clone(), witns.clone(), None);
 			
 		let (circ_ssim_numerator, circ_ssim_denom) = ssim_circuit(cs.namespace(|| "ssim"), c).unwrap();
 		//print!("inputs l_numerator={:?} l_denom={:?}\n", l_numerator, l_denom);
@@ -1118,5 +1282,275 @@ mod test {
 		//print!("circ_sigma_xy_sum={:?} circ_sigma_xy={:?} ",circ_sigma_xy_sum.value, circ_sigma_xy.value);
 
 		assert!(cs.is_satisfied());
-	}	
+	}
+
+	#[test]
+	fn test_gaussian_window_ssim() {
+		for size in SSIM_WINDOW_SIZES.iter() {
+			let mut cs = TestConstraintSystem::<Bls12>::new();
+			let window = SsimWindow::new(*size, true);
+			let src_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+			let dst_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+
+			let witns = gen_witness(&src_mb, &dst_mb, &window, DEFAULT_BIT_DEPTH);
+			let app = SsimApp::new(window, 0, ResampleRatio::identity(), None, DEFAULT_BIT_DEPTH, PublicInputSchema::default());
+			let c = single_block_circuit(&app, src_mb, dst_mb, witns, None);
+
+			ssim_circuit(cs.namespace(|| "ssim"), c).unwrap();
+			assert!(cs.is_satisfied(), "window {}", size);
+		}
+	}
+
+	#[test]
+	fn test_resampled_ssim() {
+		let window = SsimWindow::new(16, false);
+		for ratio in resample::LADDER_RATIOS.iter() {
+			let mut cs = TestConstraintSystem::<Bls12>::new();
+			let src_side = ratio.src_side(window.size);
+			let src_mb = gen_mb((src_side * src_side) as usize, DEFAULT_BIT_DEPTH);
+			let dst_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+
+			let witns = gen_witness(&resample::resample(&src_mb, ratio, window.size), &dst_mb, &window, DEFAULT_BIT_DEPTH);
+			let app = SsimApp::new(window.clone(), 0, *ratio, None, DEFAULT_BIT_DEPTH, PublicInputSchema::default());
+			let c = single_block_circuit(&app, src_mb, dst_mb, witns, None);
+
+			ssim_circuit(cs.namespace(|| "ssim"), c).unwrap();
+			assert!(cs.is_satisfied(), "ratio {:?}", ratio);
+		}
+	}
+
+	#[test]
+	fn test_setup_without_assignments() {
+		let window = SsimWindow::new(4, false);
+		let tree_depth = 2;
+		let mut ssim = SsimApp::new(window.clone(), tree_depth, ResampleRatio::identity(), None, DEFAULT_BIT_DEPTH, PublicInputSchema::default());
+		let groth_params = ssim.setup();
+
+		let src_blocks: Vec<Vec<u32>> = (0..4).map(|_| gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH)).collect();
+		let position = MbPosition::from_index(7, 2, 2);
+		let src_index = position.index();
+		let src_mb = src_blocks[src_index].clone();
+		let dst_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+		let witns = gen_witness(&src_mb, &dst_mb, &window, DEFAULT_BIT_DEPTH);
+		let (src_auth_path, src_root) = source_commit::source_path(&src_blocks, tree_depth, DEFAULT_BIT_DEPTH, position.frame, src_index);
+		let proof = ssim.create_proof(&groth_params, src_mb, dst_mb, witns.clone(), src_auth_path, src_root, position, None);
+
+		let pvk = prepare_verifying_key(&groth_params.vk);
+		let public_inputs = vec![witns.sum_y, witns.sigma_y, witns.ssim_numerator, witns.ssim_denom];
+		assert_eq!(ssim.verify_proof(&pvk, &proof, public_inputs.clone(), &position, src_root, None), Some(true));
+
+		// A proof about a source block at another position or frame, or under another commitment, does not verify
+		let other_block = MbPosition::from_index(7, 1, 2);
+		assert_eq!(ssim.verify_proof(&pvk, &proof, public_inputs.clone(), &other_block, src_root, None), Some(false));
+		let other_frame = MbPosition::from_index(8, 2, 2);
+		assert_eq!(ssim.verify_proof(&pvk, &proof, public_inputs.clone(), &other_frame, src_root, None), Some(false));
+		let other_frame_root = source_commit::source_commit(&src_blocks, tree_depth, DEFAULT_BIT_DEPTH, other_frame.frame);
+		assert_eq!(ssim.verify_proof(&pvk, &proof, public_inputs.clone(), &other_frame, other_frame_root, None), Some(false));
+		let other_root = source_commit::source_commit(&[gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH)], tree_depth, DEFAULT_BIT_DEPTH, position.frame);
+		assert_eq!(ssim.verify_proof(&pvk, &proof, public_inputs, &position, other_root, None), Some(false));
+	}
+
+	#[test]
+	fn test_yuv_ssim() {
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let window = SsimWindow::new(16, false);
+		let chroma_window = window.chroma();
+		let src_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+		let dst_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+		let blocks = ChromaBlocks {
+			src_u: gen_mb(chroma_window.mb_size(), DEFAULT_BIT_DEPTH),
+			src_v: gen_mb(chroma_window.mb_size(), DEFAULT_BIT_DEPTH),
+			dst_u: gen_mb(chroma_window.mb_size(), DEFAULT_BIT_DEPTH),
+			dst_v: gen_mb(chroma_window.mb_size(), DEFAULT_BIT_DEPTH),
+		};
+		let witns = gen_witness(&src_mb, &dst_mb, &window, DEFAULT_BIT_DEPTH);
+		let chroma_witns = ChromaWitness {
+			u: gen_witness(&blocks.src_u, &blocks.dst_u, &chroma_window, DEFAULT_BIT_DEPTH),
+			v: gen_witness(&blocks.src_v, &blocks.dst_v, &chroma_window, DEFAULT_BIT_DEPTH),
+		};
+		let weights = YuvWeights::default();
+
+		let app = SsimApp::new(window, 0, ResampleRatio::identity(), Some(weights), DEFAULT_BIT_DEPTH, PublicInputSchema::default());
+		let c = single_block_circuit(&app, src_mb, dst_mb, witns.clone(), Some((blocks, chroma_witns.clone())));
+		let src_root = c.src_root.unwrap();
+		ssim_circuit(cs.namespace(|| "ssim"), c).unwrap();
+		assert!(cs.is_satisfied());
+
+		let (yuv_numerator, yuv_denom) = yuv_fraction(&weights, &witns, &chroma_witns);
+		let mut expected: Vec<Fr> = PublicInputSchema::default().values(&witns)
+			.iter()
+			.map(|x| Fr::from_repr((*x as u64).into()).unwrap())
+			.collect();
+		expected.extend(vec![yuv_numerator, yuv_denom, src_root]);
+		expected.extend(MbPosition::from_index(0, 0, 1).public_inputs());
+		assert!(cs.verify(&expected));
+	}
+
+	#[test]
+	fn test_public_input_schema() {
+		assert_eq!(PublicInputSchema::parse("sum_y,sigma_y,ssim_numerator,ssim_denom"), Some(PublicInputSchema::default()));
+		assert_eq!(PublicInputSchema::parse("sum_y,sum_y"), None);
+		assert_eq!(PublicInputSchema::parse("sum_z"), None);
+
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let window = SsimWindow::new(8, false);
+		let src_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+		let dst_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+		let witns = gen_witness(&src_mb, &dst_mb, &window, DEFAULT_BIT_DEPTH);
+		let schema = PublicInputSchema::parse("ssim_m_numerator,ssim_m_denom,sigma_xy").unwrap();
+		let app = SsimApp::new(window, 0, ResampleRatio::identity(), None, DEFAULT_BIT_DEPTH, schema.clone());
+		let c = single_block_circuit(&app, src_mb, dst_mb, witns.clone(), None);
+		let src_root = c.src_root.unwrap();
+		ssim_circuit(cs.namespace(|| "ssim"), c).unwrap();
+		assert!(cs.is_satisfied());
+
+		let mut expected: Vec<Fr> = [witns.ssim_m_numerator, witns.ssim_m_denom, witns.sigma_xy]
+			.iter()
+			.map(|x| Fr::from_repr((*x).into()).unwrap())
+			.collect();
+		assert_eq!(schema.values(&witns), vec![witns.ssim_m_numerator, witns.ssim_m_denom, witns.sigma_xy]);
+		expected.push(src_root);
+		expected.extend(MbPosition::from_index(0, 0, 1).public_inputs());
+		assert!(cs.verify(&expected));
+	}
+
+	#[test]
+	fn test_hdr_ssim() {
+		let window = SsimWindow::new(16, false);
+		for bit_depth in SUPPORTED_BIT_DEPTHS.iter() {
+			let mut cs = TestConstraintSystem::<Bls12>::new();
+			let src_mb = gen_mb(window.mb_size(), *bit_depth);
+			let mut dst_mb = gen_mb(window.mb_size(), *bit_depth);
+			dst_mb[0] = max_pixel_value(*bit_depth);
+
+			let witns = gen_witness(&src_mb, &dst_mb, &window, *bit_depth);
+			let app = SsimApp::new(window.clone(), 0, ResampleRatio::identity(), None, *bit_depth, PublicInputSchema::default());
+			let c = single_block_circuit(&app, src_mb, dst_mb, witns, None);
+			ssim_circuit(cs.namespace(|| "ssim"), c).unwrap();
+			assert!(cs.is_satisfied(), "bit depth {}", bit_depth);
+		}
+
+		// A 10-bit destination pixel does not fit an 8-bit circuit
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let src_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+		let mut dst_mb = gen_mb(window.mb_size(), DEFAULT_BIT_DEPTH);
+		dst_mb[0] = max_pixel_value(10);
+		let witns = gen_witness(&src_mb, &dst_mb, &window, DEFAULT_BIT_DEPTH);
+		let app = SsimApp::new(window, 0, ResampleRatio::identity(), None, DEFAULT_BIT_DEPTH, PublicInputSchema::default());
+		let c = single_block_circuit(&app, src_mb, dst_mb, witns, None);
+		ssim_circuit(cs.namespace(|| "ssim"), c).unwrap();
+		assert!(!cs.is_satisfied());
+	}
+
+	//
+	// Property tests: the native witness against the values synthesised by the circuit,
+	// over structured blocks where the integer arithmetic is most likely to diverge.
+	//
+	use proptest::prelude::*;
+
+	const PROP_WINDOW_SIZE: u32 = 8;
+
+	fn fr(x: u64) -> Fr {
+		Fr::from_repr(FrRepr::from(x)).unwrap()
+	}
+
+	/// Flat, gradient, checkerboard, saturated and random blocks of the bit depth
+	fn block_strategy(side: usize, bit_depth: u32) -> BoxedStrategy<Vec<u32>> {
+		let max = max_pixel_value(bit_depth);
+		prop_oneof![
+			(0..max + 1).prop_map(move |v| vec![v; side * side]),
+			(0..max + 1, 0..max + 1, any::<bool>()).prop_map(move |(a, b, horizontal)| {
+				(0..side * side).map(|i| {
+					let t = if horizontal { i % side } else { i / side } as i64;
+					(a as i64 + (b as i64 - a as i64) * t / (side as i64 - 1)) as u32
+				}).collect()
+			}),
+			(0..max + 1, 0..max + 1, 1..4usize).prop_map(move |(a, b, cell)| {
+				(0..side * side).map(|i| if (i % side / cell + i / side / cell) % 2 == 0 { a } else { b }).collect()
+			}),
+			prop::collection::vec(any::<bool>(), side * side)
+				.prop_map(move |bits| bits.iter().map(|b| if *b { max } else { 0 }).collect()),
+			prop::collection::vec(0..max + 1, side * side),
+		].boxed()
+	}
+
+	/// Bit depth and a block of it
+	fn block_with_depth(side: usize) -> BoxedStrategy<(u32, Vec<u32>)> {
+		(0..SUPPORTED_BIT_DEPTHS.len()).prop_flat_map(move |i| {
+			let bit_depth = SUPPORTED_BIT_DEPTHS[i];
+			block_strategy(side, bit_depth).prop_map(move |mb| (bit_depth, mb))
+		}).boxed()
+	}
+
+	/// Bit depth and a block pair of it, identical pairs included
+	fn block_pair_with_depth(side: usize) -> BoxedStrategy<(u32, Vec<u32>, Vec<u32>)> {
+		(0..SUPPORTED_BIT_DEPTHS.len()).prop_flat_map(move |i| {
+			let bit_depth = SUPPORTED_BIT_DEPTHS[i];
+			let block = block_strategy(side, bit_depth);
+			prop_oneof![
+				block.clone().prop_map(|mb| (mb.clone(), mb)),
+				(block.clone(), block),
+			].prop_map(move |(src_mb, dst_mb)| (bit_depth, src_mb, dst_mb))
+		}).boxed()
+	}
+
+	fn synthesize_ssim(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, window: &SsimWindow, bit_depth: u32, witns: &Witness) -> TestConstraintSystem<Bls12> {
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let app = SsimApp::new(window.clone(), 0, ResampleRatio::identity(), None, bit_depth, PublicInputSchema::default());
+		let c = single_block_circuit(&app, src_mb.clone(), dst_mb.clone(), witns.clone(), None);
+		ssim_circuit(cs.namespace(|| "ssim"), c).unwrap();
+		cs
+	}
+
+	proptest! {
+		#![proptest_config(ProptestConfig::with_cases(16))]
+
+		#[test]
+		fn prop_witness_matches_circuit((bit_depth, src_mb, dst_mb) in block_pair_with_depth(PROP_WINDOW_SIZE as usize), gaussian in any::<bool>()) {
+			let window = SsimWindow::new(PROP_WINDOW_SIZE, gaussian);
+			let witns = gen_witness(&src_mb, &dst_mb, &window, bit_depth);
+			let mut cs = synthesize_ssim(&src_mb, &dst_mb, &window, bit_depth, &witns);
+			prop_assert!(cs.is_satisfied());
+
+			let expected = [
+				("ssim/src sum mb/sum/pix", witns.sum_x),
+				("ssim/dst sum mb/sum/pix", witns.sum_y),
+				("ssim/src meant mb/quotient/pix", witns.sum_x / witns.num_samples),
+				("ssim/dst meant mb/quotient/pix", witns.sum_y / witns.num_samples),
+				("ssim/ssim lum/lum numerator/pix", witns.l_numerator),
+				("ssim/ssim lum/lum denom/pix", witns.l_denom),
+				("ssim/sigma x const/covariance sum/sum var/pix", witns.sigma_x_sq_sum),
+				("ssim/sigma x const/sigma sq sum/quotient/pix", witns.sigma_x_sq),
+				("ssim/sigma x const/sigma/squrt/pix", witns.sigma_x),
+				("ssim/sigma y const/covariance sum/sum var/pix", witns.sigma_y_sq_sum),
+				("ssim/sigma y const/sigma sq sum/quotient/pix", witns.sigma_y_sq),
+				("ssim/sigma y const/sigma/squrt/pix", witns.sigma_y),
+				("ssim/sigma xy const/covariance sum/sum var/pix", witns.sigma_xy_sum),
+				("ssim/sigma xy const/sigma xy sum/quotient/pix", witns.sigma_xy),
+				("ssim/ssim contrast/lum numerator/pix", witns.c_numerator),
+				("ssim/ssim contrast/lum denom/pix", witns.c_denom),
+				("ssim/ssim constraint/ssim numerator/pix", witns.ssim_numerator),
+				("ssim/ssim constraint/ssim denom/pix", witns.ssim_denom),
+				("ssim/ssim m constraint/ssim numerator/pix", witns.ssim_m_numerator),
+				("ssim/ssim m constraint/ssim denom/pix", witns.ssim_m_denom),
+			];
+			for (path, value) in expected.iter() {
+				prop_assert_eq!(cs.get(path), fr(*value), "{}", path);
+			}
+		}
+
+		#[test]
+		fn prop_ssim_of_identical_blocks_is_one((bit_depth, mb) in block_with_depth(PROP_WINDOW_SIZE as usize), gaussian in any::<bool>()) {
+			let window = SsimWindow::new(PROP_WINDOW_SIZE, gaussian);
+			let witns = gen_witness(&mb, &mb, &window, bit_depth);
+			// covariance equals variance for x == x, so the fraction without square roots is exact
+			prop_assert!(witns.ssim_m_denom > 0);
+			prop_assert_eq!(witns.ssim_m_numerator, witns.ssim_m_denom);
+
+			let mut cs = synthesize_ssim(&mb, &mb, &window, bit_depth, &witns);
+			prop_assert!(cs.is_satisfied());
+			let numerator = cs.get("ssim/ssim m constraint/ssim numerator/pix");
+			prop_assert_eq!(numerator, cs.get("ssim/ssim m constraint/ssim denom/pix"));
+		}
+	}
 }
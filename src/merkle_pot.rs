@@ -12,6 +12,7 @@ use ff::Field;
 use ff::PrimeField;
 use fil_sapling_crypto::circuit::{num, boolean, pedersen_hash, multipack};
 use fil_sapling_crypto::jubjub::{JubjubBls12, JubjubEngine, edwards::Point};
+use fil_sapling_crypto::pedersen_hash::{pedersen_hash as native_pedersen_hash, Personalization};
 use paired::bls12_381::{Bls12, Fr, FrRepr};
 use rand::{Rng, SeedableRng, XorShiftRng};
 
@@ -38,7 +39,8 @@ use pbr::ProgressBar;
 
 //use logging_toolkit::make_logger;
 use slog::Logger;
-use super::constraint;
+use super::gadget;
+use rust_phash::HashSize;
 use log::{info, trace, warn};
 
 use rustc_serialize::json::Json;
@@ -160,7 +162,7 @@ impl<'a, E: JubjubEngine> Circuit<E> for ProofOfRetrievability<'a, E> {
 
         {
             // Validate that the root of the merkle tree that we calculated is the same as the input.
-            constraint::equal(&mut cs, || "enforce root is correct", &cur, &rt);
+            gadget::equal(&mut cs, || "enforce root is correct", &cur, &rt);
         }
 
         // Expose the root
@@ -426,12 +428,57 @@ fn work_groth(
     info!( "params_generation_time: {:?}", param_duration);
 }
 
+/// Personalization of hashed phash leaves, a level above any tree of phashes
+const PHASH_LEAF_LEVEL: usize = 62;
+
+/// Bits of each side of the hash size in a hashed leaf
+const HASH_SIDE_BITS: usize = 16;
+
+/// Bits of a phash, least significant bit of the first byte first
+fn phash_bits(hash: &[u8]) -> Vec<bool> {
+    hash.iter().flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1 == 1)).collect()
+}
+
+/// A phash packed into field elements, `Fr::CAPACITY` bits per element. Hashes of up to 64 bits
+/// pack into the element of their little-endian value.
+pub fn phash_elements(hash: &[u8]) -> Vec<Fr> {
+    multipack::compute_multipacking::<Bls12>(&phash_bits(hash))
+}
+
+/// Merkle leaf of a phash of the given size. 8x8 hashes keep the leaf of their u64 value, as
+/// before other sizes existed. Other sizes hash the width, the height and the bits of the hash
+/// under their own personalization, so a hash never shares a leaf with a hash of another size.
+pub fn phash_leaf(hash: &[u8], size: HashSize) -> Fr {
+    let elements = phash_elements(hash);
+    if size == HashSize::default() && elements.len() == 1 {
+        return elements[0];
+    }
+    let side_bits = |side: u32| (0..HASH_SIDE_BITS).map(move |i| (side >> i) & 1 == 1);
+    let preimage: Vec<bool> = side_bits(size.width)
+        .chain(side_bits(size.height))
+        .chain(phash_bits(hash))
+        .collect();
+    native_pedersen_hash::<Bls12, _>(
+        Personalization::MerkleTree(PHASH_LEAF_LEVEL),
+        preimage.into_iter(),
+        &JUBJUB_BLS_PARAMS,
+    )
+    .into_xy()
+    .0
+}
+
+pub fn phash_leaves(hashes: &[Vec<u8>], size: HashSize) -> Vec<Fr> {
+    hashes.iter().map(|hash| phash_leaf(hash, size)).collect()
+}
+
+/// Authentication path of the challenged leaf in the tree of the phashes
 pub fn merkel_path(
-	data: Vec<u64>,
+	hashes: &[Vec<u8>],
+	size: HashSize,
 ) -> (Vec<Option<(Fr, bool)>>, Fr, Fr) {
     let challenge_leaf_index = 1;
     info!( "challenge_leaf_index {}", challenge_leaf_index);
-	let mut leaves: Vec<Fr> = data.iter().map(|x| (Fr::from_repr(FrRepr::from(*x as u64))).unwrap()).collect();
+	let leaves: Vec<Fr> = phash_leaves(hashes, size);
  	let merk_tree = MerkleTree::<PedersenDomain, PedersenFunction>::from_data(leaves);
     info!( "merk_tree height {}", merk_tree.height());
     // generate merkle path for challenged node and parents
@@ -445,7 +492,8 @@ pub fn merkel_path(
 }
 
 
-pub fn test_zkpor(data: Vec<u64>) {
+/// Proof of retrievability over 8x8 hashes
+pub fn test_zkpor(data: Vec<Vec<u8>>) {
     env_logger::init();
 
     info!( "MerklePorApp::default(");
@@ -454,7 +502,7 @@ pub fn test_zkpor(data: Vec<u64>) {
     let start = Instant::now();
     let tree_depth = (data.len() as f64).log2().ceil() as usize;
     info!( "test_zkpor tree_depth {}", tree_depth);
-    let (auth_path, leaf, root) = merkel_path(data);
+    let (auth_path, leaf, root) = merkel_path(&data, HashSize::default());
     let merkle_creation_duration = start.elapsed();
     let merkle_creation_duration = f64::from(merkle_creation_duration.subsec_nanos()) / 1_000_000_000f64
         + (merkle_creation_duration.as_secs() as f64);
@@ -469,3 +517,37 @@ pub fn test_zkpor(data: Vec<u64>) {
     instance.dump();
 	work_groth(instance, tree_depth, auth_path.clone(), leaf, root);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_phash_leaf() {
+        // 8x8 hashes keep the leaves of their u64 value
+        let hash = 0x0123_4567_89ab_cdefu64;
+        let leaf_8x8 = phash_leaf(&hash.to_le_bytes(), HashSize::default());
+        assert_eq!(leaf_8x8, Fr::from_repr(FrRepr::from(hash)).unwrap());
+
+        // the same bits as a 16x4 hash, or zero padded to a 16x8 hash, have leaves of their own
+        let leaf_16x4 = phash_leaf(&hash.to_le_bytes(), HashSize::new(16, 4).unwrap());
+        assert!(leaf_16x4 != leaf_8x8);
+        let mut padded = hash.to_le_bytes().to_vec();
+        padded.extend(vec![0; 8]);
+        let leaf_16x8 = phash_leaf(&padded, HashSize::new(16, 8).unwrap());
+        assert!(leaf_16x8 != leaf_8x8 && leaf_16x8 != leaf_16x4);
+
+        // 256-bit hashes span two elements and differ in the bits past the first
+        let size = HashSize::new(16, 16).unwrap();
+        let wide = vec![0xa5u8; 32];
+        assert_eq!(phash_elements(&wide).len(), 2);
+        let mut flipped = wide.clone();
+        flipped[31] ^= 0x80;
+        assert_eq!(phash_elements(&flipped)[0], phash_elements(&wide)[0]);
+        assert!(phash_leaf(&flipped, size) != phash_leaf(&wide, size));
+
+        let (auth_path, leaf, _) = merkel_path(&vec![wide.clone(), flipped.clone(), wide, vec![0; 32]], size);
+        assert_eq!(auth_path.len(), 2);
+        assert_eq!(leaf, phash_leaf(&flipped, size));
+    }
+}
@@ -8,6 +8,8 @@ use bellperson::{ConstraintSystem, LinearCombination, SynthesisError, Variable};
 
 use fil_sapling_crypto::circuit::boolean::{self, AllocatedBit, Boolean};
 
+use super::gadget;
+
 /// pixel.rs currently same as the following implementation. 
 /// https://github.com/zcash-hackworks/sapling-crypto/blob/master/src/circuit/num.rs
 /// It provides a gadget for  a number allocation (AllocatedNum) which encapsulates a constraint system variable and 
@@ -20,6 +22,8 @@ use fil_sapling_crypto::circuit::boolean::{self, AllocatedBit, Boolean};
 /// 		in little-endian bitorder requiring that the representation strictly exists 
 /// 		"in the field" (i.e., a congruency is not allowed.)
 /// 	into_bits_le : 
+/// 	into_bits_le_fixed : Deconstructs this allocated number into a fixed number of little-endian bits,
+/// 		which also range checks it
 /// 	mul
 /// 	square
 /// 	assert_nonzero
@@ -28,6 +32,17 @@ use fil_sapling_crypto::circuit::boolean::{self, AllocatedBit, Boolean};
 /// 	get_variable
 /// https://github.com/zcash-hackworks/sapling-crypto/blob/master/src/circuit/num.rs
 
+/// Bits per pixel of 8-bit SDR content
+pub const DEFAULT_BIT_DEPTH: u32 = 8;
+
+/// 8-bit SDR, 10-bit HDR10 and 12-bit
+pub const SUPPORTED_BIT_DEPTHS: [u32; 3] = [8, 10, 12];
+
+/// Largest pixel value, the dynamic range L of the quality metrics
+pub fn max_pixel_value(bit_depth: u32) -> u32 {
+    (1 << bit_depth) - 1
+}
+
 pub struct AllocatedPixel<E: Engine> {
     pub value: Option<E::Fr>,
     pub variable: Variable,
@@ -247,6 +262,15 @@ impl<E: Engine> AllocatedPixel<E> {
         Ok(bits.into_iter().map(|b| Boolean::from(b)).collect())
     }
 
+    /// Decomposes the pixel into exactly `num_bits` little-endian bits.
+    /// This also proves the pixel value is in [0, 2^num_bits).
+    pub fn into_bits_le_fixed<CS>(&self, cs: CS, num_bits: usize) -> Result<Vec<Boolean>, SynthesisError>
+    where
+        CS: ConstraintSystem<E>,
+    {
+        gadget::range_check(cs, self, num_bits)
+    }
+
     pub fn mul<CS>(&self, mut cs: CS, other: &Self) -> Result<Self, SynthesisError>
     where
         CS: ConstraintSystem<E>,
@@ -386,6 +410,94 @@ impl<E: Engine> AllocatedPixel<E> {
         Ok((c, d))
     }
 
+    /// Allocates a value range checked to [0, 2^num_bits).
+    /// Costs num_bits + 1 constraints.
+    pub fn alloc_with_range<CS, F>(mut cs: CS, value: F, num_bits: usize) -> Result<Self, SynthesisError>
+    where
+        CS: ConstraintSystem<E>,
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+    {
+        let pix = Self::alloc(cs.namespace(|| "value"), value)?;
+        gadget::range_check(cs.namespace(|| "range"), &pix, num_bits)?;
+
+        Ok(pix)
+    }
+
+    /// Enforces self < other, both known to be below 2^num_bits,
+    /// by range checking other - self - 1 to num_bits.
+    /// Costs num_bits + 1 constraints.
+    pub fn enforce_less_than<CS>(&self, mut cs: CS, other: &Self, num_bits: usize) -> Result<(), SynthesisError>
+    where
+        CS: ConstraintSystem<E>,
+    {
+        let value = match (self.value, other.value) {
+            (Some(a), Some(b)) => {
+                let mut tmp = b;
+                tmp.sub_assign(&a);
+                tmp.sub_assign(&E::Fr::one());
+                Some(tmp)
+            }
+            _ => None,
+        };
+        let target = LinearCombination::zero() + other.variable - self.variable - CS::one();
+        gadget::range_check_lc(cs.namespace(|| "less than"), value, target, num_bits)?;
+
+        Ok(())
+    }
+
+    /// Returns a bit set iff self < other, both known to be below 2^num_bits.
+    /// 2^num_bits + self - other lies in [1, 2^(num_bits + 1)) and its top bit is clear iff self < other.
+    /// Costs num_bits + 2 constraints.
+    pub fn is_less_than<CS>(&self, mut cs: CS, other: &Self, num_bits: usize) -> Result<Boolean, SynthesisError>
+    where
+        CS: ConstraintSystem<E>,
+    {
+        assert!(num_bits < E::Fr::CAPACITY as usize);
+        let mut offset = E::Fr::one();
+        for _ in 0..num_bits {
+            offset.double();
+        }
+        let value = match (self.value, other.value) {
+            (Some(a), Some(b)) => {
+                let mut tmp = offset;
+                tmp.add_assign(&a);
+                tmp.sub_assign(&b);
+                Some(tmp)
+            }
+            _ => None,
+        };
+        let target = LinearCombination::zero() + (offset, CS::one()) + self.variable - other.variable;
+        let bits = gadget::range_check_lc(cs.namespace(|| "offset difference"), value, target, num_bits + 1)?;
+
+        Ok(Boolean::from(bits[num_bits].clone()).not())
+    }
+
+    /// Returns a bit set iff the value is zero.
+    /// Costs 3 constraints.
+    pub fn is_zero<CS>(&self, cs: CS) -> Result<Boolean, SynthesisError>
+    where
+        CS: ConstraintSystem<E>,
+    {
+        lc_is_zero(cs, self.value, LinearCombination::zero() + self.variable)
+    }
+
+    /// Returns a bit set iff self == other, without allocating the difference.
+    /// Costs 3 constraints.
+    pub fn is_equal<CS>(&self, cs: CS, other: &Self) -> Result<Boolean, SynthesisError>
+    where
+        CS: ConstraintSystem<E>,
+    {
+        let value = match (self.value, other.value) {
+            (Some(a), Some(b)) => {
+                let mut tmp = a;
+                tmp.sub_assign(&b);
+                Some(tmp)
+            }
+            _ => None,
+        };
+        lc_is_zero(cs, value, LinearCombination::zero() + self.variable - other.variable)
+    }
+
     pub fn get_value(&self) -> Option<E::Fr> {
         self.value
     }
@@ -395,6 +507,42 @@ impl<E: Engine> AllocatedPixel<E> {
     }
 }
 
+/// Returns a bit set iff lc, of the given value, is zero:
+/// lc * inv = 1 - is_zero and lc * is_zero = 0
+fn lc_is_zero<E, CS>(
+    mut cs: CS,
+    value: Option<E::Fr>,
+    lc: LinearCombination<E>,
+) -> Result<Boolean, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let is_zero = AllocatedBit::alloc(cs.namespace(|| "is zero"), value.map(|v| v.is_zero()))?;
+    let inv = cs.alloc(
+        || "inverse",
+        || {
+            let tmp = *value.get()?;
+            Ok(tmp.inverse().unwrap_or(E::Fr::zero()))
+        },
+    )?;
+
+    cs.enforce(
+        || "inverse constraint",
+        |l| l + &lc,
+        |l| l + inv,
+        |l| l + CS::one() - is_zero.get_variable(),
+    );
+    cs.enforce(
+        || "zero constraint",
+        |l| l + &lc,
+        |l| l + is_zero.get_variable(),
+        |l| l,
+    );
+
+    Ok(Boolean::from(is_zero))
+}
+
 #[cfg(test)]
 mod test {
     use super::{AllocatedPixel, Boolean};
@@ -524,4 +672,56 @@ mod test {
             }
         }
     }*/
+
+    fn pix(cs: &mut TestConstraintSystem<Bls12>, name: &str, value: u64) -> AllocatedPixel<Bls12> {
+        AllocatedPixel::alloc(cs.namespace(|| name), || Ok(Fr::from_repr(value.into()).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_num_less_than() {
+        for (a, b) in [(3u64, 200u64), (200, 3), (255, 255), (0, 255)].iter() {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let x = pix(&mut cs, "a", *a);
+            let y = pix(&mut cs, "b", *b);
+            let lt = x.is_less_than(cs.namespace(|| "is less"), &y, 8).unwrap();
+
+            assert_eq!(lt.get_value(), Some(a < b));
+            assert_eq!(cs.num_constraints(), 8 + 2);
+            assert!(cs.is_satisfied());
+
+            x.enforce_less_than(cs.namespace(|| "enforce less"), &y, 8).unwrap();
+            assert_eq!(cs.is_satisfied(), a < b);
+        }
+    }
+
+    #[test]
+    fn test_num_is_zero_and_equal() {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let zero = pix(&mut cs, "zero", 0);
+        let a = pix(&mut cs, "a", 17);
+        let b = pix(&mut cs, "b", 17);
+
+        assert_eq!(zero.is_zero(cs.namespace(|| "zero is zero")).unwrap().get_value(), Some(true));
+        assert_eq!(a.is_zero(cs.namespace(|| "a is zero")).unwrap().get_value(), Some(false));
+        assert_eq!(a.is_equal(cs.namespace(|| "a equals b"), &b).unwrap().get_value(), Some(true));
+        assert_eq!(a.is_equal(cs.namespace(|| "a equals zero"), &zero).unwrap().get_value(), Some(false));
+        assert_eq!(cs.num_constraints(), 4 * 3);
+        assert!(cs.is_satisfied());
+
+        // claiming a nonzero value is zero breaks the zero constraint
+        cs.set("a is zero/is zero/boolean", Fr::one());
+        assert!(!cs.is_satisfied());
+    }
+
+    #[test]
+    fn test_num_alloc_with_range() {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let x = AllocatedPixel::<Bls12>::alloc_with_range(cs.namespace(|| "x"), || Ok(Fr::from_str("1023").unwrap()), 10).unwrap();
+        assert_eq!(cs.num_constraints(), 10 + 1);
+        assert!(cs.is_satisfied());
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        AllocatedPixel::<Bls12>::alloc_with_range(cs.namespace(|| "x"), || Ok(Fr::from_str("1024").unwrap()), 10).unwrap();
+        assert!(!cs.is_satisfied());
+    }
 }
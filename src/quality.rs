@@ -0,0 +1,165 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+use super::mb_mse;
+use super::mb_ssim::{self, SsimWindow};
+use super::pixel;
+use super::resample::{self, ResampleRatio};
+
+// Native quality metrics, without any circuit.
+//
+// Every metric is computed twice: as the exact floating-point reference value and as the
+// integer approximation the circuits prove (floored means and variances, integer square
+// roots, absolute deviations in the covariance). Validators pre-screen a transcode with the
+// reference values and only request SNARKs for blocks that look suspicious; the discrepancy
+// tells how far a proven value may be from the reference one.
+
+/// Reference and circuit values of one macroblock pair
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Debug)]
+pub struct BlockQuality {
+	pub ssim: f64,
+	/// ssim_m_numerator / ssim_m_denom of the SSIM witness
+	pub ssim_circuit: f64,
+	pub mse: f64,
+	/// Floored MSE of the MSE circuit
+	pub mse_circuit: u64,
+	pub psnr: f64,
+	pub psnr_circuit: f64,
+}
+
+impl BlockQuality {
+	pub fn ssim_discrepancy(&self) -> f64 {
+		(self.ssim - self.ssim_circuit).abs()
+	}
+
+	pub fn psnr_discrepancy(&self) -> f64 {
+		if self.psnr.is_infinite() && self.psnr_circuit.is_infinite() {
+			return 0.0;
+		}
+		(self.psnr - self.psnr_circuit).abs()
+	}
+}
+
+/// Floating-point SSIM of a block pair with the window weights and the constants of the bit depth
+pub fn ssim_reference(src_mb: &[u32], dst_mb: &[u32], window: &SsimWindow, bit_depth: u32) -> f64 {
+	let l = pixel::max_pixel_value(bit_depth) as f64;
+	let c1 = (0.01 * l) * (0.01 * l);
+	let c2 = (0.03 * l) * (0.03 * l);
+	let total = window.total_weight() as f64;
+	let weighted = |f: &dyn Fn(f64, f64) -> f64| -> f64 {
+		src_mb.iter().zip(dst_mb.iter()).zip(window.weights.iter())
+			.map(|((x, y), w)| *w as f64 * f(*x as f64, *y as f64))
+			.sum::<f64>() / total
+	};
+	let mean_x = weighted(&|x, _| x);
+	let mean_y = weighted(&|_, y| y);
+	let var_x = weighted(&|x, _| (x - mean_x) * (x - mean_x));
+	let var_y = weighted(&|_, y| (y - mean_y) * (y - mean_y));
+	let cov_xy = weighted(&|x, y| (x - mean_x) * (y - mean_y));
+	((2.0 * mean_x * mean_y + c1) * (2.0 * cov_xy + c2))
+		/ ((mean_x * mean_x + mean_y * mean_y + c1) * (var_x + var_y + c2))
+}
+
+/// SSIM as the circuit computes it
+pub fn ssim_circuit_value(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, window: &SsimWindow, bit_depth: u32) -> f64 {
+	let witns = mb_ssim::gen_witness(src_mb, dst_mb, window, bit_depth);
+	witns.ssim_m_numerator as f64 / witns.ssim_m_denom as f64
+}
+
+pub fn mse_reference(src_mb: &[u32], dst_mb: &[u32]) -> f64 {
+	let sum_sq: f64 = src_mb.iter().zip(dst_mb.iter())
+		.map(|(x, y)| {
+			let d = *x as f64 - *y as f64;
+			d * d
+		})
+		.sum();
+	sum_sq / src_mb.len() as f64
+}
+
+/// Quality of a block pair. The source block covers `ratio.src_side(window.size)` pixels per side
+/// and is resampled onto the window first, as in the SSIM circuit.
+pub fn block_quality(src_mb: &Vec<u32>, dst_mb: &Vec<u32>, window: &SsimWindow, ratio: &ResampleRatio, bit_depth: u32) -> BlockQuality {
+	let src_mb = resample::resample(src_mb, ratio, window.size);
+	let mse = mse_reference(&src_mb, dst_mb);
+	let mse_circuit = mb_mse::gen_witness(&src_mb, dst_mb, 0).mse;
+	BlockQuality {
+		ssim: ssim_reference(&src_mb, dst_mb, window, bit_depth),
+		ssim_circuit: ssim_circuit_value(&src_mb, dst_mb, window, bit_depth),
+		mse,
+		mse_circuit,
+		psnr: mb_mse::mse_to_psnr(mse, bit_depth),
+		psnr_circuit: mb_mse::mse_to_psnr(mse_circuit as f64, bit_depth),
+	}
+}
+
+/// Per-block quality of a frame, blocks in raster order
+#[derive(Clone, Debug)]
+pub struct FrameQuality {
+	pub blocks: Vec<BlockQuality>,
+	pub bit_depth: u32,
+}
+
+impl FrameQuality {
+	pub fn new(src_blocks: &[Vec<u32>], dst_blocks: &[Vec<u32>], window: &SsimWindow, ratio: &ResampleRatio, bit_depth: u32) -> Self {
+		assert_eq!(src_blocks.len(), dst_blocks.len(), "frames have a different number of blocks");
+		FrameQuality {
+			blocks: src_blocks.iter().zip(dst_blocks.iter())
+				.map(|(src, dst)| block_quality(src, dst, window, ratio, bit_depth))
+				.collect(),
+			bit_depth,
+		}
+	}
+
+	pub fn mean_ssim(&self) -> f64 {
+		self.blocks.iter().map(|b| b.ssim).sum::<f64>() / self.blocks.len() as f64
+	}
+
+	pub fn mean_psnr(&self) -> f64 {
+		let mse = self.blocks.iter().map(|b| b.mse).sum::<f64>() / self.blocks.len() as f64;
+		mb_mse::mse_to_psnr(mse, self.bit_depth)
+	}
+
+	pub fn max_ssim_discrepancy(&self) -> f64 {
+		self.blocks.iter().map(|b| b.ssim_discrepancy()).fold(0.0, f64::max)
+	}
+
+	pub fn max_psnr_discrepancy(&self) -> f64 {
+		self.blocks.iter().map(|b| b.psnr_discrepancy()).fold(0.0, f64::max)
+	}
+
+	/// Blocks whose reference SSIM is below `min_ssim`, the ones worth a SNARK
+	pub fn suspicious(&self, min_ssim: f64) -> Vec<usize> {
+		self.blocks.iter().enumerate()
+			.filter(|(_, b)| b.ssim < min_ssim)
+			.map(|(i, _)| i)
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_block_quality() {
+		let window = SsimWindow::new(16, false);
+		let ratio = ResampleRatio::identity();
+		// fixed blocks, so the SSIM of every pair is known
+		let src_mb: Vec<u32> = (0..window.mb_size() as u32).map(|i| (i * 37) % 256).collect();
+
+		let same = block_quality(&src_mb, &src_mb, &window, &ratio, pixel::DEFAULT_BIT_DEPTH);
+		assert!((same.ssim - 1.0).abs() < 1e-9);
+		assert_eq!(same.mse_circuit, 0);
+		assert_eq!(same.psnr_discrepancy(), 0.0);
+
+		let dst_mb: Vec<u32> = src_mb.iter().map(|x| if *x > 3 { x - 3 } else { x + 3 }).collect();
+		let q = block_quality(&src_mb, &dst_mb, &window, &ratio, pixel::DEFAULT_BIT_DEPTH);
+		assert_eq!(q.mse_circuit, q.mse.floor() as u64);
+		assert!(q.ssim < 1.0 && q.ssim_circuit <= 1.0);
+
+		// the negative of the source is far below any sensible threshold
+		let negative: Vec<u32> = src_mb.iter().map(|x| 255 - x).collect();
+		let frame = FrameQuality::new(&[src_mb.clone(), src_mb], &[dst_mb, negative], &window, &ratio, pixel::DEFAULT_BIT_DEPTH);
+		assert_eq!(frame.suspicious(0.5), vec![1]);
+	}
+}
@@ -0,0 +1,245 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+extern crate paired;
+use byteorder::{LittleEndian, WriteBytesExt};
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use paired::bls12_381::{Bls12, Fr, FrRepr};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use super::macroblock::TestConstraintSystem;
+
+// R1CS export for external auditing tools.
+//
+// A circuit recorded by the test constraint system is written in the binary formats of iden3
+// (https://github.com/iden3/r1csfile, https://github.com/iden3/snarkjs), read by snarkjs and
+// circom tooling, and as JSON. All three number the wires alike: wire 0 is the constant ONE, the
+// public inputs follow in the order they are allocated, then every auxiliary variable. The
+// auxiliary variables are exported as internal wires, so the iden3 header has no outputs and no
+// private inputs. Field elements are in canonical form, not Montgomery form.
+//
+// .r1cs, version 1, all integers little-endian:
+//   "r1cs" u32 version u32 n_sections, then per section u32 type u64 size and the content
+//   header (1):      u32 field size (32) | prime | u32 n_wires | u32 n_pub_out (0) | u32 n_pub_in
+//                    | u32 n_prv_in (0) | u64 n_labels | u32 n_constraints
+//   constraints (2): per constraint, for a, b then c: u32 n_terms, then u32 wire | 32 byte coefficient
+//   wire to label (3): u64 label per wire, the wire index itself
+// .wtns, version 2:
+//   "wtns" u32 version u32 n_sections (2)
+//   header (1):      u32 field size (32) | prime | u32 n_wires
+//   witness (2):     32 bytes per wire
+// JSON (R1csJson): numbers as decimal strings, every side of a constraint as a map of wire to
+// coefficient, the namespace path of every wire and constraint, the witness when exported.
+
+const FIELD_SIZE: u32 = 32;
+
+/// Constraint system of a circuit, optionally with the assignment of its wires
+pub struct R1cs {
+	pub num_inputs: usize,
+	pub num_aux: usize,
+	/// a, b and c of every constraint as (wire, coefficient) terms
+	pub constraints: Vec<[Vec<(usize, Fr)>; 3]>,
+	pub constraint_labels: Vec<String>,
+	pub wire_labels: Vec<String>,
+	pub witness: Option<Vec<Fr>>,
+}
+
+/// Documented JSON form of an `R1cs`
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Debug)]
+pub struct R1csJson {
+	pub prime: String,
+	pub n_wires: usize,
+	/// Public inputs, not counting the constant ONE
+	pub n_pub_inputs: usize,
+	pub n_constraints: usize,
+	pub constraints: Vec<Vec<BTreeMap<String, String>>>,
+	pub constraint_labels: Vec<String>,
+	pub wire_labels: Vec<String>,
+	pub witness: Option<Vec<String>>,
+}
+
+impl R1cs {
+	/// Constraint system recorded by `cs`, with the wire values when `with_witness` is set
+	pub fn from_cs(cs: &TestConstraintSystem<Bls12>, with_witness: bool) -> Self {
+		let (constraints, constraint_labels): (Vec<_>, Vec<_>) = cs.wire_constraints()
+			.into_iter()
+			.map(|(terms, path)| (terms, path.to_string()))
+			.unzip();
+		R1cs {
+			num_inputs: cs.num_inputs(),
+			num_aux: cs.num_aux(),
+			constraints,
+			constraint_labels,
+			wire_labels: cs.wire_labels().into_iter().map(|label| label.to_string()).collect(),
+			witness: if with_witness { Some(cs.wire_values()) } else { None },
+		}
+	}
+
+	pub fn num_wires(&self) -> usize {
+		self.num_inputs + self.num_aux
+	}
+
+	pub fn write_r1cs<W: Write>(&self, mut w: W) -> io::Result<()> {
+		w.write_all(b"r1cs")?;
+		w.write_u32::<LittleEndian>(1)?;
+		w.write_u32::<LittleEndian>(3)?;
+
+		let mut header = vec![];
+		header.write_u32::<LittleEndian>(FIELD_SIZE)?;
+		Fr::char().write_le(&mut header)?;
+		header.write_u32::<LittleEndian>(self.num_wires() as u32)?;
+		header.write_u32::<LittleEndian>(0)?;
+		header.write_u32::<LittleEndian>((self.num_inputs - 1) as u32)?;
+		header.write_u32::<LittleEndian>(0)?;
+		header.write_u64::<LittleEndian>(self.num_wires() as u64)?;
+		header.write_u32::<LittleEndian>(self.constraints.len() as u32)?;
+		write_section(&mut w, 1, &header)?;
+
+		let mut constraints = vec![];
+		for constraint in self.constraints.iter() {
+			for terms in constraint.iter() {
+				constraints.write_u32::<LittleEndian>(terms.len() as u32)?;
+				for (wire, coeff) in terms.iter() {
+					constraints.write_u32::<LittleEndian>(*wire as u32)?;
+					coeff.into_repr().write_le(&mut constraints)?;
+				}
+			}
+		}
+		write_section(&mut w, 2, &constraints)?;
+
+		let mut labels = vec![];
+		for wire in 0..self.num_wires() {
+			labels.write_u64::<LittleEndian>(wire as u64)?;
+		}
+		write_section(&mut w, 3, &labels)
+	}
+
+	/// Fails when the system was recorded without its witness
+	pub fn write_wtns<W: Write>(&self, mut w: W) -> io::Result<()> {
+		let witness = self.witness.as_ref()
+			.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "r1cs recorded without a witness"))?;
+		w.write_all(b"wtns")?;
+		w.write_u32::<LittleEndian>(2)?;
+		w.write_u32::<LittleEndian>(2)?;
+
+		let mut header = vec![];
+		header.write_u32::<LittleEndian>(FIELD_SIZE)?;
+		Fr::char().write_le(&mut header)?;
+		header.write_u32::<LittleEndian>(witness.len() as u32)?;
+		write_section(&mut w, 1, &header)?;
+
+		let mut values = vec![];
+		for value in witness.iter() {
+			value.into_repr().write_le(&mut values)?;
+		}
+		write_section(&mut w, 2, &values)
+	}
+
+	pub fn to_json(&self) -> R1csJson {
+		let constraints = self.constraints.iter()
+			.map(|constraint| constraint.iter()
+				.map(|terms| terms.iter().map(|(wire, coeff)| (wire.to_string(), fr_to_decimal(coeff))).collect::<BTreeMap<_, _>>())
+				.collect())
+			.collect();
+		R1csJson {
+			prime: repr_to_decimal(&Fr::char()),
+			n_wires: self.num_wires(),
+			n_pub_inputs: self.num_inputs - 1,
+			n_constraints: self.constraints.len(),
+			constraints,
+			constraint_labels: self.constraint_labels.clone(),
+			wire_labels: self.wire_labels.clone(),
+			witness: self.witness.as_ref().map(|witness| witness.iter().map(fr_to_decimal).collect()),
+		}
+	}
+}
+
+fn write_section<W: Write>(w: &mut W, section_type: u32, content: &[u8]) -> io::Result<()> {
+	w.write_u32::<LittleEndian>(section_type)?;
+	w.write_u64::<LittleEndian>(content.len() as u64)?;
+	w.write_all(content)
+}
+
+pub fn fr_to_decimal(value: &Fr) -> String {
+	repr_to_decimal(&value.into_repr())
+}
+
+/// Decimal digits of a little-endian integer, taken 19 at a time
+fn repr_to_decimal(repr: &FrRepr) -> String {
+	const CHUNK: u128 = 10_000_000_000_000_000_000;
+	let mut limbs = repr.as_ref().to_vec();
+	let mut chunks = vec![];
+	while limbs.iter().any(|limb| *limb != 0) {
+		let mut rem = 0u128;
+		for limb in limbs.iter_mut().rev() {
+			let cur = rem << 64 | *limb as u128;
+			*limb = (cur / CHUNK) as u64;
+			rem = cur % CHUNK;
+		}
+		chunks.push(rem as u64);
+	}
+	match chunks.split_last() {
+		Some((high, low)) => low.iter().rev().fold(high.to_string(), |s, chunk| format!("{}{:019}", s, chunk)),
+		None => "0".to_string(),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use bellperson::ConstraintSystem;
+	use byteorder::{ByteOrder, LittleEndian};
+	use fil_sapling_crypto::circuit::num;
+
+	fn eval(terms: &[(usize, Fr)], witness: &[Fr]) -> Fr {
+		let mut acc = Fr::zero();
+		for (wire, coeff) in terms.iter() {
+			let mut term = witness[*wire];
+			term.mul_assign(coeff);
+			acc.add_assign(&term);
+		}
+		acc
+	}
+
+	#[test]
+	fn test_r1cs_export() {
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let x = num::AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(Fr::from_str("3").unwrap())).unwrap();
+		let y = num::AllocatedNum::alloc(cs.namespace(|| "y"), || Ok(Fr::from_str("5").unwrap())).unwrap();
+		let z = x.mul(cs.namespace(|| "x * y"), &y).unwrap();
+		z.inputize(cs.namespace(|| "z")).unwrap();
+		assert!(cs.is_satisfied());
+
+		let r1cs = R1cs::from_cs(&cs, true);
+		// ONE, z | x, y, x * y
+		assert_eq!((r1cs.num_inputs, r1cs.num_aux), (2, 3));
+		assert_eq!(r1cs.wire_labels[1], "z/input variable");
+		let witness = r1cs.witness.clone().unwrap();
+		for constraint in r1cs.constraints.iter() {
+			let mut ab = eval(&constraint[0], &witness);
+			ab.mul_assign(&eval(&constraint[1], &witness));
+			assert_eq!(ab, eval(&constraint[2], &witness));
+		}
+
+		let mut r1cs_bytes = vec![];
+		r1cs.write_r1cs(&mut r1cs_bytes).unwrap();
+		assert_eq!(&r1cs_bytes[..4], b"r1cs");
+		// header section content follows magic, version, section count, type and size
+		let header = &r1cs_bytes[24..];
+		assert_eq!(LittleEndian::read_u32(&header[..4]), 32);
+		assert_eq!(LittleEndian::read_u32(&header[36..40]), 5);
+		assert_eq!(LittleEndian::read_u32(&header[44..48]), 1);
+		assert_eq!(LittleEndian::read_u32(&header[60..64]), r1cs.constraints.len() as u32);
+
+		let mut wtns_bytes = vec![];
+		r1cs.write_wtns(&mut wtns_bytes).unwrap();
+		assert_eq!(wtns_bytes.len(), 12 + 12 + 40 + 12 + 5 * 32);
+		assert!(R1cs::from_cs(&cs, false).write_wtns(&mut vec![]).is_err());
+
+		let json = r1cs.to_json();
+		assert_eq!(json.prime, "52435875175126190479447740508185965837690552500527637822603658699938581184513");
+		assert_eq!(json.witness.unwrap()[4], "15");
+		assert_eq!(fr_to_decimal(&Fr::zero()), "0");
+	}
+}
@@ -0,0 +1,185 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+extern crate bellperson;
+extern crate paired;
+use bellperson::{ConstraintSystem, SynthesisError};
+use paired::Engine;
+
+use super::pixel::AllocatedPixel;
+use super::gadget::{div_const, range_check, sum_vec};
+
+/// Box resampling of a source region onto the destination grid, for renditions of a lower resolution.
+///
+/// With a ratio of `src:dst`, destination pixel j covers [j*src, (j+1)*src) and source pixel i covers
+/// [i*dst, (i+1)*dst) on a common axis. The overlap lengths are integer weights summing to `src` per
+/// axis, so a destination pixel is floor(sum(wy * wx * pixel) / src^2).
+#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResampleRatio {
+	pub src: u32,
+	pub dst: u32,
+}
+
+/// 1080->720 and 720->480 are 3:2, 1080->540 is 2:1
+pub const LADDER_RATIOS: [ResampleRatio; 3] = [
+	ResampleRatio { src: 1, dst: 1 },
+	ResampleRatio { src: 3, dst: 2 },
+	ResampleRatio { src: 2, dst: 1 },
+];
+
+impl ResampleRatio {
+	pub fn identity() -> Self {
+		ResampleRatio { src: 1, dst: 1 }
+	}
+
+	/// Parses "3:2" or a pair of heights such as "1080:720", reduced to lowest terms
+	pub fn parse(s: &str) -> Option<Self> {
+		let mut parts = s.split(':');
+		let src = parts.next()?.parse::<u32>().ok()?;
+		let dst = parts.next()?.parse::<u32>().ok()?;
+		if parts.next().is_some() || src == 0 || dst == 0 {
+			return None;
+		}
+		let (mut a, mut b) = (src, dst);
+		while b != 0 {
+			let t = a % b;
+			a = b;
+			b = t;
+		}
+		Some(ResampleRatio { src: src / a, dst: dst / a })
+	}
+
+	pub fn is_identity(&self) -> bool {
+		self.src == self.dst
+	}
+
+	/// Side of the source region mapped onto a destination block of side `dst_side`
+	pub fn src_side(&self, dst_side: u32) -> u32 {
+		assert_eq!(dst_side % self.dst, 0, "block side {} is not a multiple of {}", dst_side, self.dst);
+		dst_side / self.dst * self.src
+	}
+
+	/// For each destination pixel along an axis, the covered source pixels and their weights
+	pub fn axis_weights(&self, dst_side: u32) -> Vec<Vec<(usize, u32)>> {
+		let (p, q) = (self.src, self.dst);
+		(0..dst_side)
+			.map(|j| {
+				let (start, end) = (j * p, (j + 1) * p);
+				(start / q..=(end - 1) / q)
+					.map(|i| {
+						let overlap = end.min((i + 1) * q) - start.max(i * q);
+						(i as usize, overlap)
+					})
+					.collect()
+			})
+			.collect()
+	}
+
+	/// Sum of the weights of a destination pixel
+	pub fn total_weight(&self) -> u32 {
+		self.src * self.src
+	}
+}
+
+/// Source pixels and weights of every destination pixel, in raster order
+fn pixel_weights(ratio: &ResampleRatio, dst_side: u32) -> Vec<Vec<(usize, u32)>> {
+	let src_side = ratio.src_side(dst_side) as usize;
+	let axis = ratio.axis_weights(dst_side);
+	let mut out = Vec::with_capacity((dst_side * dst_side) as usize);
+	for wy in axis.iter() {
+		for wx in axis.iter() {
+			let mut taps = Vec::with_capacity(wy.len() * wx.len());
+			for (y, w1) in wy.iter() {
+				for (x, w2) in wx.iter() {
+					taps.push((y * src_side + x, w1 * w2));
+				}
+			}
+			out.push(taps);
+		}
+	}
+	out
+}
+
+/// Native resampling of a source region of side `ratio.src_side(dst_side)`
+pub fn resample(mb: &Vec<u32>, ratio: &ResampleRatio, dst_side: u32) -> Vec<u32> {
+	if ratio.is_identity() {
+		return mb.clone();
+	}
+	let total = ratio.total_weight();
+	pixel_weights(ratio, dst_side)
+		.iter()
+		.map(|taps| taps.iter().map(|(i, w)| mb[*i] * w).sum::<u32>() / total)
+		.collect()
+}
+
+/// In-circuit resampling. `mb` holds the native values of `pixels` and is `None` during setup.
+/// Every output is range checked to `bit_depth` bits so a forged division remainder cannot
+/// turn a resampled pixel into an arbitrary field element.
+pub fn resample_circuit<E: Engine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	pixels: &Vec<AllocatedPixel<E>>,
+	mb: &Option<Vec<u32>>,
+	ratio: &ResampleRatio,
+	dst_side: u32,
+	bit_depth: u32,
+) -> Result<Vec<AllocatedPixel<E>>, SynthesisError>
+{
+	if ratio.is_identity() {
+		return Ok(pixels.clone());
+	}
+	let total = ratio.total_weight() as u64;
+	let mut out = Vec::with_capacity((dst_side * dst_side) as usize);
+	for (j, taps) in pixel_weights(ratio, dst_side).iter().enumerate() {
+		let mut cs = cs.namespace(|| format!("resample {}", j));
+		let src: Vec<AllocatedPixel<E>> = taps.iter().map(|(i, _)| pixels[*i].clone()).collect();
+		let weights: Vec<u32> = taps.iter().map(|(_, w)| *w).collect();
+		let sum = mb.as_ref().map(|mb| taps.iter().map(|(i, w)| (mb[*i] * w) as u64).sum::<u64>());
+
+		let circ_sum = sum_vec(cs.namespace(|| "weighted sum"), &src, &weights)?;
+		let circ_pix = div_const(cs.namespace(|| "normalise"), &circ_sum, sum, total)?;
+		range_check(cs.namespace(|| "pixel range"), &circ_pix, bit_depth as usize)?;
+		out.push(circ_pix);
+	}
+	Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use ff::PrimeField;
+	use paired::bls12_381::{Bls12, Fr};
+	use rand::{Rng, thread_rng};
+	use storage_proofs::circuit::test::*;
+
+	#[test]
+	fn test_resample_ladder() {
+		let rng = &mut thread_rng();
+		let dst_side = 16;
+		for ratio in LADDER_RATIOS.iter() {
+			let src_side = ratio.src_side(dst_side);
+			let mb: Vec<u32> = (0..src_side * src_side).map(|_| rng.gen::<u8>() as u32).collect();
+			let expected = resample(&mb, ratio, dst_side);
+			assert_eq!(expected.len(), (dst_side * dst_side) as usize);
+
+			let mut cs = TestConstraintSystem::<Bls12>::new();
+			let pixels: Vec<AllocatedPixel<Bls12>> = mb
+				.iter()
+				.enumerate()
+				.map(|(i, x)| {
+					AllocatedPixel::alloc(cs.namespace(|| format!("val {}", i)), || {
+						Ok(Fr::from_repr((*x as u64).into()).unwrap())
+					})
+					.unwrap()
+				})
+				.collect();
+			let out = resample_circuit(cs.namespace(|| "resample"), &pixels, &Some(mb.clone()), ratio, dst_side, 8).unwrap();
+			assert!(cs.is_satisfied(), "ratio {:?}", ratio);
+			for (pix, x) in out.iter().zip(expected.iter()) {
+				assert_eq!(pix.get_value().unwrap(), Fr::from_repr((*x as u64).into()).unwrap());
+			}
+		}
+
+		assert_eq!(ResampleRatio::parse("1080:720"), Some(ResampleRatio { src: 3, dst: 2 }));
+		assert_eq!(ResampleRatio::parse("1080:540"), Some(ResampleRatio { src: 2, dst: 1 }));
+	}
+}
@@ -0,0 +1,296 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+extern crate bellperson;
+extern crate fil_sapling_crypto;
+extern crate paired;
+
+use bellperson::{ConstraintSystem, SynthesisError};
+use ff::{BitIterator, Field, PrimeField, PrimeFieldRepr};
+use fil_sapling_crypto::circuit::{boolean, multipack, num, pedersen_hash};
+use fil_sapling_crypto::jubjub::{JubjubBls12, JubjubEngine};
+use fil_sapling_crypto::pedersen_hash::{pedersen_hash as native_pedersen_hash, Personalization};
+use paired::bls12_381::{Bls12, Fr};
+
+use storage_proofs::hasher::pedersen::{PedersenDomain, PedersenFunction, PedersenHasher};
+use storage_proofs::merkle::{MerkleProof, MerkleTree};
+
+use super::challenge::FRAME_BITS;
+use super::gadget;
+use super::pixel::{AllocatedPixel, DEFAULT_BIT_DEPTH};
+
+// Commitment of the publisher to the source macroblocks of a frame.
+//
+// Every source macroblock is hashed into a leaf with a chained pedersen hash over the
+// little-endian bits of its pixels, `bit_depth` bits per pixel. The leaves, in raster order, form a pedersen merkle tree
+// of fixed depth (padded with zero leaves). With depth 0 the tree root is the hash of a single
+// macroblock. The frame commitment hashes the frame number with the tree root, so the blocks of
+// one frame cannot be passed off as another frame of the stream.
+//
+// The SSIM circuit recomputes the leaf from the private source block and checks its
+// authentication path against the committed root, so an SSIM proof speaks about the real
+// source at the challenged position.
+
+/// Pixel bits absorbed by each pedersen hash of the chain
+const SOURCE_HASH_CHUNK_BITS: usize = 256;
+
+/// Tree depth covering a 1080p frame of 16x16 macroblocks (8160 blocks)
+pub const DEFAULT_SOURCE_TREE_DEPTH: u32 = 13;
+
+/// Merkle personalization of the frame commitment, a level above any tree
+const FRAME_ROOT_LEVEL: usize = 63;
+
+lazy_static! {
+	pub static ref JUBJUB_BLS_PARAMS: JubjubBls12 = JubjubBls12::new();
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SourceCommitment {
+	pub root: PedersenDomain,
+	/// Frame number hashed into the root
+	pub frame: u32,
+	pub tree_depth: u32,
+	pub num_blocks: u32,
+	/// Bits per pixel of the committed blocks, 8 for commitments predating HDR support
+	#[serde(default = "default_bit_depth")]
+	pub bit_depth: u32,
+}
+
+fn default_bit_depth() -> u32 {
+	DEFAULT_BIT_DEPTH
+}
+
+fn fr_into_bits_le(value: Fr) -> Vec<bool> {
+	let mut bits: Vec<bool> = BitIterator::new(value.into_repr()).collect();
+	bits.reverse();
+	bits.truncate(Fr::NUM_BITS as usize);
+	bits
+}
+
+/// Native leaf of a source macroblock, whose pixels callers checked against `bit_depth`
+pub fn source_mb_hash(mb: &[u32], bit_depth: u32) -> Fr {
+	let bits = gadget::pixel_bits(mb, bit_depth).expect("source pixel exceeds the bit depth");
+	let mut cur: Option<Fr> = None;
+	for chunk in bits.chunks(SOURCE_HASH_CHUNK_BITS) {
+		let mut preimage = match cur {
+			Some(prev) => fr_into_bits_le(prev),
+			None => vec![],
+		};
+		preimage.extend(chunk.iter().cloned());
+		let hash = native_pedersen_hash::<Bls12, _>(
+			Personalization::NoteCommitment,
+			preimage.into_iter(),
+			&JUBJUB_BLS_PARAMS,
+		);
+		cur = Some(hash.into_xy().0);
+	}
+	cur.expect("empty macroblock")
+}
+
+/// In-circuit leaf of a source macroblock. Decomposing the pixels into bits also proves they fit `bit_depth` bits.
+pub fn source_mb_hash_circuit<E: JubjubEngine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	pixels: &[AllocatedPixel<E>],
+	bit_depth: u32,
+	params: &E::Params,
+) -> Result<num::AllocatedNum<E>, SynthesisError> {
+	let mut bits = Vec::with_capacity(pixels.len() * bit_depth as usize);
+	for (i, pix) in pixels.iter().enumerate() {
+		bits.extend(pix.into_bits_le_fixed(cs.namespace(|| format!("pixel bits {}", i)), bit_depth as usize)?);
+	}
+
+	let mut cur: Option<num::AllocatedNum<E>> = None;
+	for (i, chunk) in bits.chunks(SOURCE_HASH_CHUNK_BITS).enumerate() {
+		let mut cs = cs.namespace(|| format!("source hash chunk {}", i));
+		let mut preimage = match cur {
+			// strict, so the previous hash has no second encoding as x + p
+			Some(ref prev) => prev.into_bits_le_strict(cs.namespace(|| "prev into bits"))?,
+			None => vec![],
+		};
+		preimage.extend(chunk.iter().cloned());
+		cur = Some(
+			pedersen_hash::pedersen_hash(
+				cs.namespace(|| "computation of pedersen hash"),
+				pedersen_hash::Personalization::NoteCommitment,
+				&preimage,
+				params,
+			)?
+			.get_x()
+			.clone(),
+		);
+	}
+	Ok(cur.expect("empty macroblock"))
+}
+
+/// Leaves of the frame tree, padded to 2^tree_depth
+fn source_leaves(blocks: &[Vec<u32>], tree_depth: u32, bit_depth: u32) -> Vec<Fr> {
+	let num_leaves = 1usize << tree_depth;
+	assert!(blocks.len() <= num_leaves, "{} blocks do not fit a tree of depth {}", blocks.len(), tree_depth);
+	let mut leaves: Vec<Fr> = blocks.iter().map(|mb| source_mb_hash(mb, bit_depth)).collect();
+	leaves.resize(num_leaves, Fr::zero());
+	leaves
+}
+
+/// Little-endian bits of a frame number, as the circuit allocates them
+pub fn frame_bits_le(frame: u32) -> Vec<bool> {
+	(0..FRAME_BITS).map(|i| (frame >> i) & 1 == 1).collect()
+}
+
+/// Frame commitment, the hash of the frame number and the root of the block tree
+pub fn frame_root(frame: u32, tree_root: Fr) -> Fr {
+	let mut preimage = frame_bits_le(frame);
+	preimage.extend(fr_into_bits_le(tree_root));
+	native_pedersen_hash::<Bls12, _>(
+		Personalization::MerkleTree(FRAME_ROOT_LEVEL),
+		preimage.into_iter(),
+		&JUBJUB_BLS_PARAMS,
+	)
+	.into_xy()
+	.0
+}
+
+/// Frame commitment published for the source macroblocks of frame `frame`
+pub fn source_commit(blocks: &[Vec<u32>], tree_depth: u32, bit_depth: u32, frame: u32) -> Fr {
+	let leaves = source_leaves(blocks, tree_depth, bit_depth);
+	if tree_depth == 0 {
+		return frame_root(frame, leaves[0]);
+	}
+	let merk_tree = MerkleTree::<PedersenDomain, PedersenFunction>::from_data(leaves);
+	frame_root(frame, merk_tree.root().into())
+}
+
+/// Authentication path of the macroblock at `index`, along with the frame commitment
+pub fn source_path(blocks: &[Vec<u32>], tree_depth: u32, bit_depth: u32, frame: u32, index: usize) -> (Vec<Option<(Fr, bool)>>, Fr) {
+	assert!(index < blocks.len(), "macroblock {} out of range", index);
+	let leaves = source_leaves(blocks, tree_depth, bit_depth);
+	if tree_depth == 0 {
+		return (vec![], frame_root(frame, leaves[0]));
+	}
+	let merk_tree = MerkleTree::<PedersenDomain, PedersenFunction>::from_data(leaves);
+	let merk_proof = MerkleProof::<PedersenHasher>::new_from_proof(&merk_tree.gen_proof(index));
+	(merk_proof.as_options(), frame_root(frame, merk_tree.root().into()))
+}
+
+/// Position bits of an authentication path as verifiers expect them, packed into field elements
+pub fn source_path_inputs(tree_depth: u32, index: usize) -> Vec<Fr> {
+	let bits: Vec<bool> = (0..tree_depth).map(|i| (index >> i) & 1 == 1).collect();
+	multipack::compute_multipacking::<Bls12>(&bits)
+}
+
+/// Checks the authentication path of `leaf` in-circuit, hashes the tree root with the bits of the
+/// frame number and exposes the packed position bits and the frame commitment as public inputs,
+/// in that order. Returns the position bits, least significant first.
+pub fn source_path_circuit<E: JubjubEngine, CS: ConstraintSystem<E>>(
+	mut cs: CS,
+	leaf: num::AllocatedNum<E>,
+	frame_bits: &[boolean::Boolean],
+	auth_path: &[Option<(E::Fr, bool)>],
+	root: Option<E::Fr>,
+	params: &E::Params,
+) -> Result<Vec<boolean::Boolean>, SynthesisError> {
+	let rt = num::AllocatedNum::alloc(cs.namespace(|| "source root"), || {
+		root.ok_or(SynthesisError::AssignmentMissing)
+	})?;
+
+	let mut cur = leaf;
+	let mut auth_path_bits = Vec::with_capacity(auth_path.len());
+
+	// Ascend the merkle tree authentication path
+	for (i, e) in auth_path.iter().enumerate() {
+		let cs = &mut cs.namespace(|| format!("source merkle tree hash {}", i));
+
+		let cur_is_right = boolean::Boolean::from(boolean::AllocatedBit::alloc(
+			cs.namespace(|| "position bit"),
+			e.map(|e| e.1),
+		)?);
+
+		let path_element = num::AllocatedNum::alloc(cs.namespace(|| "path element"), || {
+			Ok(e.ok_or(SynthesisError::AssignmentMissing)?.0)
+		})?;
+
+		let (xl, xr) = num::AllocatedNum::conditionally_reverse(
+			cs.namespace(|| "conditional reversal of preimage"),
+			&cur,
+			&path_element,
+			&cur_is_right,
+		)?;
+
+		let mut preimage = vec![];
+		preimage.extend(xl.into_bits_le(cs.namespace(|| "xl into bits"))?);
+		preimage.extend(xr.into_bits_le(cs.namespace(|| "xr into bits"))?);
+
+		cur = pedersen_hash::pedersen_hash(
+			cs.namespace(|| "computation of pedersen hash"),
+			pedersen_hash::Personalization::MerkleTree(i),
+			&preimage,
+			params,
+		)?
+		.get_x()
+		.clone();
+
+		auth_path_bits.push(cur_is_right);
+	}
+
+	multipack::pack_into_inputs(cs.namespace(|| "packed source auth_path"), &auth_path_bits)?;
+
+	assert_eq!(frame_bits.len(), FRAME_BITS, "frame number of {} bits", frame_bits.len());
+	let mut preimage = frame_bits.to_vec();
+	preimage.extend(cur.into_bits_le_strict(cs.namespace(|| "tree root into bits"))?);
+	let frame_root = pedersen_hash::pedersen_hash(
+		cs.namespace(|| "computation of frame root"),
+		pedersen_hash::Personalization::MerkleTree(FRAME_ROOT_LEVEL),
+		&preimage,
+		params,
+	)?
+	.get_x()
+	.clone();
+
+	gadget::equal(&mut cs, || "enforce source root is correct", &frame_root, &rt);
+
+	rt.inputize(cs.namespace(|| "source root input"))?;
+
+	Ok(auth_path_bits)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::challenge;
+	use storage_proofs::circuit::test::*;
+	use rand::{Rng, thread_rng};
+
+	#[test]
+	fn test_source_path_circuit() {
+		let rng = &mut thread_rng();
+		let tree_depth = 2;
+		let bit_depth = 10;
+		let blocks: Vec<Vec<u32>> = (0..3)
+			.map(|_| (0..16).map(|_| rng.gen_range(0, 1 << bit_depth)).collect())
+			.collect();
+		let (frame, index) = (5, 1);
+		let (auth_path, root) = source_path(&blocks, tree_depth, bit_depth, frame, index);
+		assert_eq!(root, source_commit(&blocks, tree_depth, bit_depth, frame));
+		assert_ne!(root, source_commit(&blocks, tree_depth, bit_depth, frame + 1));
+
+		let mut cs = TestConstraintSystem::<Bls12>::new();
+		let pixels: Vec<AllocatedPixel<Bls12>> = blocks[index]
+			.iter()
+			.enumerate()
+			.map(|(i, x)| {
+				AllocatedPixel::alloc(cs.namespace(|| format!("val {}", i)), || {
+					Ok(Fr::from_repr((*x as u64).into()).unwrap())
+				})
+				.unwrap()
+			})
+			.collect();
+		let leaf = source_mb_hash_circuit(cs.namespace(|| "leaf"), &pixels, bit_depth, &JUBJUB_BLS_PARAMS).unwrap();
+		assert_eq!(leaf.get_value().unwrap(), source_mb_hash(&blocks[index], bit_depth));
+
+		let (_, circ_frame_bits) = challenge::alloc_frame(cs.namespace(|| "frame"), Some(frame)).unwrap();
+		source_path_circuit(cs.namespace(|| "path"), leaf, &circ_frame_bits, &auth_path, Some(root), &JUBJUB_BLS_PARAMS).unwrap();
+		assert!(cs.is_satisfied());
+
+		let mut expected = source_path_inputs(tree_depth, index);
+		expected.push(root);
+		assert!(cs.verify(&expected));
+	}
+}
@@ -0,0 +1,9 @@
+use rand::{Rng, thread_rng};
+
+// Helpers shared by the tests of the circuit modules.
+
+/// Random macroblock of `mb_size` pixels of the given bit depth
+pub fn gen_mb(mb_size: usize, bit_depth: u32) -> Vec<u32> {
+	let mut rng = thread_rng();
+	(0..mb_size).map(|_| rng.gen_range(0, 1 << bit_depth)).collect()
+}
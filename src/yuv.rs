@@ -0,0 +1,207 @@
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Reader of decoded 4:2:0 video, raw planar YUV or Y4M.
+//
+// Samples of more than 8 bits are stored in two bytes, little-endian, as written by ffmpeg for
+// yuv420p10le and yuv420p12le. Raw files carry no header, so their dimensions and bit depth
+// are given by the caller. Y4M files describe themselves in the stream header.
+
+const Y4M_MAGIC: &str = "YUV4MPEG2 ";
+
+/// Dimensions and bit depth of the frames of a video
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct YuvFormat {
+	pub width: u32,
+	pub height: u32,
+	pub bit_depth: u32,
+}
+
+impl YuvFormat {
+	pub fn chroma_width(&self) -> u32 {
+		(self.width + 1) / 2
+	}
+
+	pub fn chroma_height(&self) -> u32 {
+		(self.height + 1) / 2
+	}
+
+	fn bytes_per_sample(&self) -> u64 {
+		if self.bit_depth > 8 { 2 } else { 1 }
+	}
+
+	/// Bytes of the Y, U and V planes of a frame
+	pub fn frame_size(&self) -> u64 {
+		let samples = self.width as u64 * self.height as u64 + 2 * self.chroma_width() as u64 * self.chroma_height() as u64;
+		samples * self.bytes_per_sample()
+	}
+}
+
+/// Decoded frame, planes in raster order
+pub struct YuvFrame {
+	pub format: YuvFormat,
+	pub y: Vec<u32>,
+	pub u: Vec<u32>,
+	pub v: Vec<u32>,
+}
+
+impl YuvFrame {
+	/// Square block of `side` pixels at pixel (x, y) of plane 0 (Y), 1 (U) or 2 (V).
+	/// Coordinates and side are in pixels of that plane.
+	pub fn block(&self, plane: usize, x: u32, y: u32, side: u32) -> Option<Vec<u32>> {
+		let (data, width, height) = match plane {
+			0 => (&self.y, self.format.width, self.format.height),
+			1 => (&self.u, self.format.chroma_width(), self.format.chroma_height()),
+			_ => (&self.v, self.format.chroma_width(), self.format.chroma_height()),
+		};
+		if x + side > width || y + side > height {
+			return None;
+		}
+		let mut block = Vec::with_capacity((side * side) as usize);
+		for row in y..y + side {
+			let start = (row * width + x) as usize;
+			block.extend_from_slice(&data[start..start + side as usize]);
+		}
+		Some(block)
+	}
+}
+
+pub struct YuvReader {
+	pub format: YuvFormat,
+	file: BufReader<File>,
+	y4m: bool,
+	/// Offset of the first frame, past the Y4M stream header
+	data_start: u64,
+}
+
+impl YuvReader {
+	/// Opens a Y4M file, recognized by its signature, or else a raw file of the given format
+	pub fn open<P: AsRef<Path>>(path: P, raw_format: Option<YuvFormat>) -> io::Result<Self> {
+		let mut file = BufReader::new(File::open(path)?);
+		let mut magic = [0u8; 10];
+		let is_y4m = file.read_exact(&mut magic).is_ok() && &magic[..] == Y4M_MAGIC.as_bytes();
+		file.seek(SeekFrom::Start(0))?;
+		if is_y4m {
+			let mut header = String::new();
+			file.read_line(&mut header)?;
+			let format = parse_y4m_header(&header)?;
+			let data_start = header.len() as u64;
+			return Ok(YuvReader { format, file, y4m: true, data_start });
+		}
+		match raw_format {
+			Some(format) => Ok(YuvReader { format, file, y4m: false, data_start: 0 }),
+			None => Err(invalid_data("raw yuv input needs its dimensions".to_string())),
+		}
+	}
+
+	/// Reads frame `index`, counted from 0
+	pub fn read_frame(&mut self, index: u32) -> io::Result<YuvFrame> {
+		let frame_size = self.format.frame_size();
+		if self.y4m {
+			// frame headers may carry parameters, so frames are walked one by one
+			self.file.seek(SeekFrom::Start(self.data_start))?;
+			for i in 0..=index {
+				let mut frame_header = String::new();
+				if self.file.read_line(&mut frame_header)? == 0 {
+					return Err(invalid_data(format!("frame {} past the end of the stream", index)));
+				}
+				if !frame_header.starts_with("FRAME") {
+					return Err(invalid_data(format!("bad y4m frame header {:?}", frame_header.trim_end())));
+				}
+				if i < index {
+					self.file.seek(SeekFrom::Current(frame_size as i64))?;
+				}
+			}
+		} else {
+			self.file.seek(SeekFrom::Start(self.data_start + index as u64 * frame_size))?;
+		}
+		let format = self.format;
+		let y = self.read_plane(format.width as usize * format.height as usize)?;
+		let chroma_size = format.chroma_width() as usize * format.chroma_height() as usize;
+		let u = self.read_plane(chroma_size)?;
+		let v = self.read_plane(chroma_size)?;
+		Ok(YuvFrame { format, y, u, v })
+	}
+
+	fn read_plane(&mut self, num_samples: usize) -> io::Result<Vec<u32>> {
+		let bytes_per_sample = self.format.bytes_per_sample() as usize;
+		let mut buf = vec![0u8; num_samples * bytes_per_sample];
+		self.file.read_exact(&mut buf)?;
+		let max = (1u32 << self.format.bit_depth) - 1;
+		let samples: Vec<u32> = if bytes_per_sample == 1 {
+			buf.iter().map(|x| *x as u32).collect()
+		} else {
+			buf.chunks(2).map(|x| x[0] as u32 | (x[1] as u32) << 8).collect()
+		};
+		if let Some(x) = samples.iter().find(|x| **x > max) {
+			return Err(invalid_data(format!("sample {} exceeds {} bits", x, self.format.bit_depth)));
+		}
+		Ok(samples)
+	}
+}
+
+fn invalid_data(msg: String) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Parses "YUV4MPEG2 W1920 H1080 F30:1 Ip A1:1 C420p10". Only 4:2:0 streams are supported.
+pub fn parse_y4m_header(header: &str) -> io::Result<YuvFormat> {
+	if !header.starts_with(Y4M_MAGIC) {
+		return Err(invalid_data("not a y4m stream".to_string()));
+	}
+	let (mut width, mut height, mut bit_depth) = (None, None, 8);
+	for token in header[Y4M_MAGIC.len()..].split_whitespace() {
+		let (tag, value) = token.split_at(1);
+		match tag {
+			"W" => width = value.parse::<u32>().ok(),
+			"H" => height = value.parse::<u32>().ok(),
+			"C" => {
+				bit_depth = match value {
+					"420" | "420jpeg" | "420paldv" | "420mpeg2" => 8,
+					"420p10" => 10,
+					"420p12" => 12,
+					_ => return Err(invalid_data(format!("unsupported y4m colorspace {}", value))),
+				}
+			},
+			_ => {},
+		}
+	}
+	match (width, height) {
+		(Some(width), Some(height)) if width > 0 && height > 0 => Ok(YuvFormat { width, height, bit_depth }),
+		_ => Err(invalid_data(format!("y4m header without dimensions {:?}", header.trim_end()))),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::io::Write;
+
+	#[test]
+	fn test_read_y4m_block() {
+		let format = YuvFormat { width: 8, height: 4, bit_depth: 10 };
+		let mut data = b"YUV4MPEG2 W8 H4 F25:1 Ip A1:1 C420p10\n".to_vec();
+		for frame in 0..3u32 {
+			data.extend_from_slice(if frame == 1 { b"FRAME Ixyz\n" } else { b"FRAME\n" });
+			let num_samples = format.frame_size() / 2;
+			for i in 0..num_samples as u32 {
+				let sample = (frame * 100 + i) as u16;
+				data.extend_from_slice(&[sample as u8, (sample >> 8) as u8]);
+			}
+		}
+		let mut file = tempfile::NamedTempFile::new().unwrap();
+		file.write_all(&data).unwrap();
+
+		let mut reader = YuvReader::open(file.path(), None).unwrap();
+		assert_eq!(reader.format, format);
+		let frame = reader.read_frame(2).unwrap();
+		assert_eq!(frame.block(0, 2, 1, 2), Some(vec![210, 211, 218, 219]));
+		// the U plane follows the 32 luma samples, the V plane the 8 U samples
+		assert_eq!(frame.block(1, 1, 0, 1), Some(vec![233]));
+		assert_eq!(frame.block(2, 0, 1, 2), None);
+		assert!(reader.read_frame(3).is_err());
+	}
+}
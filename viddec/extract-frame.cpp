@@ -15,12 +15,14 @@ extern "C"
 #include "libavutil/timestamp.h"
 #include "libavutil/base64.h"
 #include "libavutil/pixfmt.h"
+#include "libavutil/pixdesc.h"
 #include "libswscale/swscale.h"
 }
 
 #include "json.hpp"
 #include <iostream>
 #include <fstream>
+#include <vector>
 
 using json::JSON;
 using namespace std;
@@ -29,8 +31,10 @@ AVFormatContext* fmt_ctx;
 int frame_offset = 0;
 int macroblock_offset = 0;
 int fScale = 0;
-int region_width = 32;
-//int region_height = 16;
+int fChroma = 0;
+/* size of the region at the top left of the frame, 0 for the whole frame, or 32x32 with --scale */
+int region_width = 0;
+int region_height = 0;
 const char *output_file = "test.json";
 const char* input_file;
 
@@ -44,7 +48,8 @@ void parse_options(int argc, const char* argv[]);
 void hexDump (unsigned char *pData, int n);
 static char* itoa(int val, int base);
 int getParam(AVFrame *frame, char *key);
-int extractLuma(AVFrame *frame, unsigned char *pRawY, int x , int y, int width, int height);
+int extractLuma(AVFrame *frame, unsigned short *pRawY, int x , int y, int width, int height);
+int extractPlane(AVCodecContext *pCodecCtx, AVFrame *frame, int plane, unsigned short *pRaw, int x , int y, int width, int height);
 
 char *mb_data = NULL;
 int mb_size = 0;
@@ -75,18 +80,24 @@ void parse_options(int argc, const char* argv[])
 			i++;
 		}  else if(strcmp(argv[i], "-s") == 0 || strcmp(argv[i], "--scale") == 0) {
 			fScale = 1;
+		}  else if(strcmp(argv[i], "-u") == 0 || strcmp(argv[i], "--chroma") == 0) {
+			fChroma = 1;
 		}  else if(strcmp(argv[i], "-c") == 0 || strcmp(argv[i], "--count") == 0) {
 			if(i+1 < argc)
 				gFrameCount = atoi(argv[i+1]);;
 		}  else if(strcmp(argv[i], "-w") == 0 || strcmp(argv[i], "--width") == 0) {
 			if(i+1 < argc)
 				region_width = atoi(argv[i+1]);;
+		}  else if(strcmp(argv[i], "--height") == 0) {
+			if(i+1 < argc)
+				region_height = atoi(argv[i+1]);
+			i++;
 		} 
 		i++;
 	}
 	if(ARG_HELP || input_file == NULL)
 	{
-		fprintf(stderr, "Usage: gen-hash [--frame <frame start>] [--count <num frames>] --scale --input videoPath --output outfile.json\n");
+		fprintf(stderr, "Usage: extract-frame [--frame <frame start>] [--count <num frames>] [--width <region width>] [--height <region height>] [--scale] [--chroma] --input videoPath --output outfile.json\n");
 		exit(1);
 	}
 }
@@ -132,8 +143,8 @@ int ScaleImg(AVFrame *src_picture, AVFrame *dst_picture)
 	struct SwsContext* pSwsContext;
 
 	pSwsContext = sws_getContext(
-			src_picture->width, src_picture->height, AV_PIX_FMT_YUV420P,
-			dst_picture->width, dst_picture->height, AV_PIX_FMT_YUV420P,
+			src_picture->width, src_picture->height, (AVPixelFormat)src_picture->format,
+			dst_picture->width, dst_picture->height, (AVPixelFormat)dst_picture->format,
 			SWS_BICUBIC,
 			NULL, NULL, NULL);
 
@@ -172,33 +183,45 @@ static void init_frame(AVCodecContext *c, AVFrame **framep, int width, int heigh
     *framep = frame;
 }
 
-int extractLuma(AVCodecContext *pCodecCtx, AVFrame *frame, unsigned char *pRawY, int x , int y, int width, int height)
+int extractLuma(AVCodecContext *pCodecCtx, AVFrame *frame, unsigned short *pRawY, int x , int y, int width, int height)
+{
+	return extractPlane(pCodecCtx, frame, 0, pRawY, x, y, width, height);
+}
+
+/*
+ * Copies a region of plane 0 (Y), 1 (U) or 2 (V). x, y, width and height are in luma pixels,
+ * 4:2:0 chroma planes are read at half of them. Samples of 10-bit and 12-bit formats
+ * (yuv420p10le, yuv420p12le) are 16-bit words and are copied at their full bit depth.
+ */
+int extractPlane(AVCodecContext *pCodecCtx, AVFrame *frame, int plane, unsigned short *pRaw, int x , int y, int width, int height)
 {
+	int shift = plane ? 1 : 0;
+	AVFrame *src_frame = frame;
 	if(fScale) {
 		AVFrame *dst_frame = NULL;;
 		init_frame(pCodecCtx, &dst_frame, width, height);
 
 		ScaleImg(frame, dst_frame);
 
+		src_frame = dst_frame;
 		x = 0; y = 0;
-		unsigned char *pDst = pRawY;
-			if((x + width) > dst_frame->linesize[0] || (y + height) > dst_frame->height)
-				return -1;
-			for (int v=0; v < height; v++){
-				char *pSrc = (char *)dst_frame->data[0] + (y + v)  * dst_frame->linesize[0] + x;
-				for (int w=0; w < width; w++){
-					*pDst++ = *pSrc++;
-				}
-		}
-	} else {
-	 	unsigned char *pDst = pRawY;
-		if((x + width) > frame->linesize[0] || (y + height) > frame->height)
-			return -1;
-		for (int v=0; v < height; v++){
-			char *pSrc = (char *)frame->data[0] + (y + v)  * frame->linesize[0] + x;
-			for (int w=0; w < width; w++){
-				*pDst++ = *pSrc++;
+	}
+	x >>= shift; y >>= shift;
+	width >>= shift; height >>= shift;
+	const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get((AVPixelFormat)src_frame->format);
+	int bytes = (desc && desc->comp[plane].depth > 8) ? 2 : 1;
+	unsigned short *pDst = pRaw;
+	if((x + width) * bytes > src_frame->linesize[plane] || (y + height) > (src_frame->height >> shift))
+		return -1;
+	for (int v=0; v < height; v++){
+		unsigned char *pSrc = src_frame->data[plane] + (y + v)  * src_frame->linesize[plane] + x * bytes;
+		for (int w=0; w < width; w++){
+			if(bytes == 2) {
+				*pDst++ = pSrc[0] | (pSrc[1] << 8);
+			} else {
+				*pDst++ = pSrc[0];
 			}
+			pSrc += bytes;
 		}
 	}
 	return 0;
@@ -283,7 +306,6 @@ int main(int argc, char **argv)
 	if(ffmpeg_videoStreamIndex == -1){
 		fprintf(stderr, "Video stream not found.");
 	}
-	unsigned char *pRawY = (unsigned char *)malloc(region_width * region_width);
 	int extracted = 0;
 
 	JSON FrameObjects;
@@ -310,15 +332,35 @@ int main(int argc, char **argv)
 				} else if (got_frame){
 					fprintf(stderr, "Frame decoded\n");
 					if(frame_count >= frame_offset) {
-						extractLuma(codec_ctx, frame, pRawY, 0, 0, region_width, region_width);
+						/* unscaled frames keep their size, rust-phash resizes them */
+						int width = region_width ? region_width : (fScale ? 32 : frame->width);
+						int height = region_height ? region_height : (fScale ? 32 : frame->height);
+						vector<unsigned short> rawY(width * height), rawU(width * height / 4), rawV(width * height / 4);
+						if(extractLuma(codec_ctx, frame, rawY.data(), 0, 0, width, height) < 0) {
+							fprintf(stderr, "%dx%d region outside the %dx%d frame\n", width, height, frame->width, frame->height);
+							exit(1);
+						}
 						extracted = 1;
 
 						JSON array;
 						json::JSON obj;
-						for (int i = 0; i < region_width * region_width; i++) {
-							array[i] = pRawY[i];
+						for (int i = 0; i < width * height; i++) {
+							array[i] = rawY[i];
 						}
 						obj["pixels"] = array;
+						obj["width"] = width;
+						obj["height"] = height;
+						if(fChroma) {
+							extractPlane(codec_ctx, frame, 1, rawU.data(), 0, 0, width, height);
+							extractPlane(codec_ctx, frame, 2, rawV.data(), 0, 0, width, height);
+							JSON u_array, v_array;
+							for (int i = 0; i < (width >> 1) * (height >> 1); i++) {
+								u_array[i] = rawU[i];
+								v_array[i] = rawV[i];
+							}
+							obj["u"] = u_array;
+							obj["v"] = v_array;
+						}
 						FrameObjects.append(obj);
 						extracted_frames++;
 						if(extracted_frames >= gFrameCount)
@@ -336,7 +378,6 @@ int main(int argc, char **argv)
 	}
 
 	free(mb_data);
-	free (pRawY);
     return 0;
 
 }